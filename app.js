@@ -1,50 +1,21 @@
 
-function getTouchesInfo(touchEvent){
-    let { touches } = touchEvent;
-    let touchCount = touches.length;
-    let centerX = 0;
-    let centerY = 0;
-    let averageDistance = 0;
-    for(let touch of touches){
-        let {screenX, screenY} = touch;
-        centerX += screenX;
-        centerY += screenY;
-    }
-    centerX /= touchCount;
-    centerY /= touchCount;
-    for(let touch of touches){
-        let {screenX, screenY} = touch;
-        let dx = screenX - centerX;
-        let dy = screenY - centerY;
-        averageDistance += Math.sqrt(dx * dx + dy * dy);
-    }
-    averageDistance /= touchCount;
-    return { centerX, centerY, averageDistance, touchCount };
-}
-
-function getTime(){
-    return new Date().getTime();
-}
-
 const WEBGL_OPTIONS =  {"stencil" : true, "alpha" : true , "preserveDrawingBuffer" : true, antialias : true };
 
 export class App {
     constructor(pkg, canvasSelector, font){
-        this._oldTouches = [];
-        this._previousMouseX = 0;
-        this._previousMouseY = 0;
         let canvasElement = document.querySelector(canvasSelector);
         let canvasContext = canvasElement.getContext("webgl2", WEBGL_OPTIONS);
         this.pkg = pkg;
         this._canvas = new pkg.Canvas(canvasContext);
         this._canvas.set_current_xrange(-10, 10);
         this._canvas.set_current_yrange(-10, 10);
-        canvasElement.addEventListener("mousedown", this.handleMouseDown.bind(this));
-        canvasElement.addEventListener("mouseup", this.handleMouseUp.bind(this));
-        canvasElement.addEventListener("mousemove", this.handleMouseMove.bind(this));
-        canvasElement.addEventListener("touchstart", this.handleTouchStart.bind(this));
-        canvasElement.addEventListener("touchmove", this.handleTouchMove.bind(this));
-        canvasElement.addEventListener("touchend", this.handleTouchEnd.bind(this));
+        this._canvas.set_kinetic_panning(true);
+        // Panning and zooming is done by the canvas, the events are just passed on.
+        canvasElement.style.touchAction = "none";
+        canvasElement.addEventListener("pointerdown", this.handlePointerDown.bind(this));
+        canvasElement.addEventListener("pointermove", this.handlePointerMove.bind(this));
+        canvasElement.addEventListener("pointerup", this.handlePointerUp.bind(this));
+        canvasElement.addEventListener("pointercancel", this.handlePointerCancel.bind(this));
         
         canvasElement.addEventListener("webglcontextlost", (event) => {
             event.preventDefault();
@@ -59,7 +30,20 @@ export class App {
             this._requestFrame();
         });
 
-        canvasElement.addEventListener("wheel", this.handleScroll.bind(this));
+        canvasElement.addEventListener("wheel", this.handleWheel.bind(this), { passive : false });
+        // Keyboard navigation between nodes, the focused node is described in a live region for screen readers.
+        canvasElement.tabIndex = 0;
+        canvasElement.setAttribute("role", "application");
+        canvasElement.setAttribute("aria-label", "Chart, use the arrow keys or Tab to move between nodes");
+        canvasElement.addEventListener("keydown", this.handleKeyDown.bind(this));
+        this._focusDescriptionElement = document.createElement("div");
+        this._focusDescriptionElement.setAttribute("aria-live", "polite");
+        this._focusDescriptionElement.style.cssText = "position: absolute; width: 1px; height: 1px; overflow: hidden; clip: rect(0 0 0 0);";
+        document.body.appendChild(this._focusDescriptionElement);
+        this._canvasElement = canvasElement;
+        this._boxZoomElement = document.createElement("div");
+        this._boxZoomElement.style.cssText = "position: fixed; display: none; pointer-events: none; border: 1px solid rgba(0, 0, 0, 0.5); background: rgba(0, 0, 0, 0.05);";
+        document.body.appendChild(this._boxZoomElement);
         this._needsRedraw = true;
         this._idleFrames = 0;
         this.font = font;
@@ -75,169 +59,103 @@ export class App {
     }
     
     _stopAnimation(){
-
+        this._canvas.stop_animation();
     }
 
-    handleScroll(event) {
-        event.preventDefault();
-        this._stopAnimation();
-        let mousePoint = new Vec2(event.offsetX, event.offsetY);
-        // If we are close to a grid point (currently within 10px) lock on to it.
-        let [nearestX, nearestY, distance] = this._canvas.nearest_gridpoint(mousePoint);
-        if(distance < 10){
-            this._canvas.translate(new Vec2(-nearestX + mousePoint.x, -nearestY + mousePoint.y));
-        }
-        let direction = Math.sign(event.deltaY);
-        this._canvas.scale_around(Math.pow(0.6, direction), mousePoint);
+    animateToRange(xmin, xmax, ymin, ymax, duration = 300){
+        this._canvas.animate_to_range(xmin, xmax, ymin, ymax, duration, this.pkg.Easing.EaseInOut);
         this._requestRedraw();
     }
-    
-    handlePinch(x, y, delta) {
-        this._stopAnimation();
-        this._canvas.scale_around(Math.pow(0.98, delta), new Vec2(x, y));
+
+    showMinimap(width = 200, height = 150){
+        this._canvas.show_minimap(width, height, this.pkg.Corner.BottomRight);
         this._requestRedraw();
     }
-    
-    handleResize() {
-        // _canvas.translateOrigin((_platform.width - _oldPlatformWidth) / 2, (_platform.height - _oldPlatformHeight) / 2)
-        // _oldPlatformWidth = _platform.width
-        // _oldPlatformHeight = _platform.height
-        // this._stopAnimation();
-        // this._draw();
-    }
-    
-    handleTouchStart(event) {
-        event.preventDefault();
-        let { centerX, centerY, averageDistance, touchCount } = getTouchesInfo(event);
-        let time = getTime();
-        this._stopAnimation();
-        this._oldTouches.push({centerX, centerY, averageDistance, touchCount, time});
+
+    // range is [xmin, xmax, ymin, ymax], returns a png Blob.
+    exportPng(width, height, range, dpi = 300){
+        let bytes = this._canvas.render_to_png(width, height, new Float32Array(range), dpi / 96);
+        this._requestRedraw();
+        return new Blob([bytes], { type : "image/png" });
     }
-    
-    handleTouchMove(event) {
-        event.preventDefault();
-        let { centerX, centerY, averageDistance, touchCount } = getTouchesInfo(event);
-        let previous = this._oldTouches[this._oldTouches.length - 1];
-        if(previous.touchCount === touchCount) {
-            if(averageDistance !== 0 && previous.averageDistance !== 0) {
-                this._canvas.scale_around(averageDistance / previous.averageDistance, new Vec2(previous.centerX, previous.centerY));
-            }
-            this._canvas.translate(new Vec2(centerX - previous.centerX, centerY - previous.centerY));
+
+    _handled(needsRedraw){
+        if(needsRedraw) {
             this._requestRedraw();
         }
-        let time = getTime();
-        this._oldTouches.push({centerX, centerY, averageDistance, touchCount, time});
     }
-    
-    handleTouchEnd(event) {
-        event.preventDefault();
-        let { centerX, centerY, averageDistance, touchCount } = getTouchesInfo(event);
-        let time = getTime();
-        if(touchCount !== 0) {
-            this._oldTouches.push({centerX, centerY, averageDistance, touchCount, time})
+
+    handlePointerDown(event) {
+        if(event.pointerType === "mouse" && event.button !== 0) {
             return;
         }
+        this._canvasElement.setPointerCapture(event.pointerId);
+        this._handled(this._canvas.pointer_down(event.pointerId, event.offsetX, event.offsetY, event.shiftKey));
+    }
 
-        let oldTouches = this._oldTouches;
-        this._oldTouches = [];
-
-        // Search for an old touch that was long enough ago that the velocity should be stable
-        for(let i = oldTouches.length - 2; i >= 0; i--) {
-            // Ignore touches due to a pinch gesture
-            if(oldTouches[i].touchCount > 1) {
-                return;
-            }
-
-            // If we find an old enough touch, maybe do a fling
-            if(time - oldTouches[i].time > 0.1 * 1000) {
-                this._maybeFling(oldTouches[i], oldTouches[i + 1]);
-                return;
-            }
-        }
+    handlePointerMove(event) {
+        this._handled(this._canvas.pointer_move(event.pointerId, event.offsetX, event.offsetY));
     }
 
-    _maybeFling(beforeTouch, afterTouch){
-        // let scale = 1 / (afterTouch.time - beforeTouch.time);
-        // let vx = (afterTouch.centerX - beforeTouch.centerX) * scale;
-        // let vy = (afterTouch.centerY - beforeTouch.centerY) * scale;
-        // let speed = Math.sqrt(vx * vx + vy * vy);
-        // let duration = Math.log(1 + speed) / 5;
-        // let flingDistance = speed * duration / 5; // Divide by 5 since a quintic decay function has an initial slope of 5
+    handlePointerUp(event) {
+        this._handled(this._canvas.pointer_up(event.pointerId, event.offsetX, event.offsetY, event.shiftKey));
+    }
 
-        // // Only fling if the speed is fast enough
-        // if(speed > 50) {
-        //     _startAnimation(.DECAY, duration);
-        //     _endOrigin += velocity * (flingDistance / speed);
-        // }
+    handlePointerCancel(event) {
+        this._handled(this._canvas.pointer_cancel(event.pointerId));
     }
-    
-    handleMouseDown(event) {
-        let { offsetX : x, offsetY : y } = event;
-        // this.setCursor(.MOVE);
-        this._mouseDown = true;
-        this._previousMouseX = x;
-        this._previousMouseY = y;
-        console.log(this._canvas.object_underneath_pixel(new Vec2(x, y)));
+
+    handleWheel(event) {
+        event.preventDefault();
+        this._handled(this._canvas.wheel(event.offsetX, event.offsetY, event.deltaX, event.deltaY, event.deltaMode, event.ctrlKey));
     }
-    
-    handleMouseMove(event) {
-        let { offsetX : x, offsetY : y, buttons } = event;
-        if(buttons > 0){ 
-            this._canvas.translate(new Vec2(x - this._previousMouseX, y - this._previousMouseY));
-            this._requestRedraw();
-            // this.setCursor(.MOVE);
+
+    handleKeyDown(event) {
+        let action = this._canvas.handle_key(event.key, event.shiftKey);
+        if(action === this.pkg.KeyAction.Ignored) {
+            return;
         }
-    
-        this._previousMouseX = x;
-        this._previousMouseY = y;
+        event.preventDefault();
+        if(action === this.pkg.KeyAction.Activate) {
+            let id = this._canvas.focused_node();
+            this._canvasElement.dispatchEvent(new CustomEvent("nodeactivate", { detail : { id } }));
+            return;
+        }
+        this._focusDescriptionElement.textContent = this._canvas.focus_description() || "";
+        this._requestRedraw();
+    }
+
+    handleResize() {
+        // _canvas.translateOrigin((_platform.width - _oldPlatformWidth) / 2, (_platform.height - _oldPlatformHeight) / 2)
+        // _oldPlatformWidth = _platform.width
+        // _oldPlatformHeight = _platform.height
+        // this._stopAnimation();
+        // this._draw();
     }
     
-    handleMouseUp(event) {
-        let { offsetX : x, offsetY : y, buttons } = event;
-        if(buttons === 0) {
-            this._mouseDown = false;
-            // this._mouseAction = .NONE
-            // this.setCursor(.DEFAULT);
+    _updateBoxZoom(){
+        let rectangle = this._canvas.box_zoom_rectangle();
+        let style = this._boxZoomElement.style;
+        if(!rectangle) {
+            style.display = "none";
+            return;
         }
-    
-        this._previousMouseX = x;
-        this._previousMouseY = y;
+        let [xmin, ymin, xmax, ymax] = rectangle;
+        let { left, top } = this._canvasElement.getBoundingClientRect();
+        style.display = "block";
+        style.left = `${left + xmin}px`;
+        style.top = `${top + ymin}px`;
+        style.width = `${xmax - xmin}px`;
+        style.height = `${ymax - ymin}px`;
     }
 
-
     handleFrame() {
         this._requestFrame();
         
-		// let time = getTime();
-
-		// if _animation != .NONE {
-		// 	var t = (time - _startTime) / (_endTime - _startTime)
-
-		// 	# Stop the animation once it's done
-		// 	if t > 1 {
-		// 		_canvas.setOriginAndScale(_endOrigin.x, _endOrigin.y, _endScale)
-		// 		_animation = .NONE
-		// 	}
-
-		// 	else {
-		// 		# Bend the animation curve for a more pleasant animation
-		// 		if _animation == .EASE_IN_OUT {
-		// 			t *= t * t * (t * (t * 6 - 15) + 10)
-		// 		} else {
-		// 			assert(_animation == .DECAY)
-		// 			t = 1 - t
-		// 			t = 1 - t * t * t * t * t
-		// 		}
-
-		// 		# Animate both origin and scale
-		// 		_canvas.setOriginAndScale(
-		// 			_startOrigin.x + (_endOrigin.x - _startOrigin.x) * t,
-		// 			_startOrigin.y + (_endOrigin.y - _startOrigin.y) * t,
-		// 			1 / (1 / _startScale + (1 / _endScale - 1 / _startScale) * t))
-		// 	}
-
-		// 	_requestRedraw
-		// }
+        // Viewport animations and kinetic panning are advanced by start_frame, keep drawing until they are done.
+        if(this._canvas.is_animating()) {
+            this._requestRedraw();
+        }
 
 		if(this._needsRedraw) {
             this._idleFrames = 0;
@@ -255,6 +173,7 @@ export class App {
 
     _draw(){
         this._canvas.start_frame();
+        this._updateBoxZoom();
         this._canvas.render();
         // this._canvas.test_speed("\u220e", 0.0);
         // this._canvas.draw_box(
@@ -0,0 +1,389 @@
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use lyon::geom::math::{Point, Vector, vector};
+use std::collections::{BTreeSet, VecDeque};
+
+#[allow(unused_imports)]
+use crate::log;
+use crate::coordinate_system::CoordinateSystem;
+
+/// How far along an animation is as a function of the fraction of its duration that has passed.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Easing {
+    Linear,
+    /// Starts and ends smoothly (smootherstep).
+    EaseInOut,
+    /// Starts fast and slows down to a stop, like a fling.
+    Decay,
+}
+
+impl Easing {
+    pub fn apply(self, t : f32) -> f32 {
+        match self {
+            Easing::Linear => t,
+            Easing::EaseInOut => {
+                // The polynomial is symmetric, evaluating it near 0 keeps it from wobbling by rounding errors near 1.
+                let smootherstep = |t : f32| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+                if t <= 0.5 { smootherstep(t) } else { 1.0 - smootherstep(1.0 - t) }
+            }
+            Easing::Decay => {
+                let s = 1.0 - t;
+                1.0 - s * s * s * s * s
+            }
+        }
+    }
+}
+
+/// Moves the origin and scale of a coordinate system from one viewport to another over time.
+/// Times are in milliseconds.
+#[derive(Clone, Copy, Debug)]
+pub struct ViewportAnimation {
+    start_origin : Point,
+    start_scale : Vector,
+    end_origin : Point,
+    end_scale : Vector,
+    end_natural_scale_ratio : f32,
+    start_time : f64,
+    duration : f64,
+    easing : Easing,
+}
+
+impl ViewportAnimation {
+    pub fn new(from : &CoordinateSystem, to : &CoordinateSystem, start_time : f64, duration : f64, easing : Easing) -> Self {
+        Self {
+            start_origin : from.origin,
+            start_scale : from.scale,
+            end_origin : to.origin,
+            end_scale : to.scale,
+            end_natural_scale_ratio : to.natural_scale_ratio,
+            start_time,
+            duration,
+            easing,
+        }
+    }
+
+    /// Set the origin and scale for the given time. Returns true once the animation has reached its end.
+    pub fn apply(&self, coordinate_system : &mut CoordinateSystem, time : f64) -> bool {
+        let elapsed = time - self.start_time;
+        if elapsed >= self.duration {
+            coordinate_system.origin = self.end_origin;
+            coordinate_system.scale = self.end_scale;
+            coordinate_system.natural_scale_ratio = self.end_natural_scale_ratio;
+            return true;
+        }
+        let t = self.easing.apply((elapsed / self.duration).max(0.0) as f32);
+        coordinate_system.origin = self.start_origin.lerp(self.end_origin, t);
+        // Interpolating the reciprocal of the scale makes zooming in and out feel equally fast.
+        let inverse_scale = |start : f32, end : f32| 1.0 / (1.0 / start + (1.0 / end - 1.0 / start) * t);
+        coordinate_system.scale = vector(
+            inverse_scale(self.start_scale.x, self.end_scale.x),
+            inverse_scale(self.start_scale.y, self.end_scale.y),
+        );
+        false
+    }
+}
+
+/// The time span over which a node or edge changes from its previous to its current position and colors, in
+/// milliseconds since the epoch. The shaders interpolate between the two by the progress, see Transitions.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transition {
+    pub start_time : f64,
+    pub duration : f64,
+}
+
+impl Transition {
+    /// Already finished.
+    pub const NONE : Transition = Transition { start_time : 0.0, duration : 0.0 };
+
+    pub fn new(start_time : f64, duration : f64) -> Self {
+        Self { start_time, duration }
+    }
+
+    pub fn end_time(&self) -> f64 {
+        self.start_time + self.duration
+    }
+
+    /// Fraction of the transition that has passed at time, eased in and out.
+    pub fn progress(&self, time : f64) -> f32 {
+        if self.duration <= 0.0 {
+            return 1.0;
+        }
+        Easing::EaseInOut.apply(((time - self.start_time) / self.duration).clamp(0.0, 1.0) as f32)
+    }
+}
+
+/// The transitions of the instances of a shader. Only the progress of each instance goes to the GPU, update hands out
+/// the new progress of the instances that are still moving.
+#[derive(Clone, Debug, Default)]
+pub struct Transitions {
+    transitions : Vec<Transition>,
+    // Indices of the transitions that hadn't finished at the last update.
+    running : BTreeSet<usize>,
+}
+
+impl Transitions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self) {
+        self.transitions.push(Transition::NONE);
+    }
+
+    pub fn clear(&mut self) {
+        self.transitions.clear();
+        self.running.clear();
+    }
+
+    /// Replace the transition of the instance with the given index. Returns how far the previous transition had got at
+    /// the start of the new one, which is where the instance starts moving from.
+    pub fn start(&mut self, index : usize, transition : Transition) -> f32 {
+        let previous = self.transitions[index].progress(transition.start_time);
+        self.transitions[index] = transition;
+        self.running.insert(index);
+        previous
+    }
+
+    /// Call set_progress with the index and progress at time of each running transition. A finished transition gets a
+    /// last call with progress 1 and is dropped from the running ones.
+    pub fn update(&mut self, time : f64, mut set_progress : impl FnMut(usize, f32)) {
+        let transitions = &self.transitions;
+        self.running.retain(|&index| {
+            let transition = transitions[index];
+            set_progress(index, transition.progress(time));
+            time < transition.end_time()
+        });
+    }
+}
+
+// Fraction of the velocity of a fling lost per millisecond.
+const FRICTION : f32 = 0.003;
+// The spring pulling an overscrolled viewport back into bounds, per millisecond squared. Critically damped.
+const SPRING_STIFFNESS : f32 = 4e-4;
+const SPRING_DAMPING : f32 = 0.04;
+// Longest time step of the integration, for stability of the spring.
+const MAX_STEP : f32 = 4.0;
+// Pixels per millisecond below which a fling stops.
+const MIN_SPEED : f32 = 0.01;
+
+/// Panning that continues with the velocity at release, slowed down by friction. When the viewport is past the bounds
+/// of the coordinate system, a spring pulls it back instead. A viewport zoomed out past the bounds is zoomed back in by
+/// the same spring. Velocities are in pixels per millisecond.
+#[derive(Clone, Copy, Debug)]
+pub struct KineticPan {
+    velocity : Vector,
+    // Of the scale overscroll, see CoordinateSystem::scale_overscroll.
+    scale_velocity : Vector,
+}
+
+impl KineticPan {
+    pub fn new(velocity : Vector) -> Self {
+        Self { velocity, scale_velocity : Vector::zero() }
+    }
+
+    /// True if there is anything to do: the velocity is fast enough to fling or the viewport is past the bounds.
+    pub fn is_needed(&self, coordinate_system : &CoordinateSystem) -> bool {
+        self.velocity.length() >= MIN_SPEED || coordinate_system.overscroll() != Vector::zero()
+            || coordinate_system.scale_overscroll() != Vector::zero()
+    }
+
+    /// Move the origin forward by dt milliseconds. Returns true once it has come to rest within the bounds.
+    pub fn step(&mut self, coordinate_system : &mut CoordinateSystem, dt : f64) -> bool {
+        let spring = |velocity : f32, overscroll : f32, h : f32| velocity - (SPRING_STIFFNESS * overscroll + SPRING_DAMPING * velocity) * h;
+        let mut remaining = dt as f32;
+        while remaining > 0.0 {
+            let h = remaining.min(MAX_STEP);
+            remaining -= h;
+            let scale_overscroll = coordinate_system.scale_overscroll();
+            if scale_overscroll != Vector::zero() {
+                let axis = |velocity : f32, overscroll : f32| if overscroll == 0.0 { 0.0 } else { spring(velocity, overscroll, h) };
+                self.scale_velocity = vector(axis(self.scale_velocity.x, scale_overscroll.x), axis(self.scale_velocity.y, scale_overscroll.y));
+                coordinate_system.set_scale_overscroll(scale_overscroll + self.scale_velocity * h);
+            }
+            let overscroll = coordinate_system.overscroll();
+            let axis = |velocity : f32, overscroll : f32| {
+                if overscroll == 0.0 {
+                    velocity * (-FRICTION * h).exp()
+                } else {
+                    spring(velocity, overscroll, h)
+                }
+            };
+            self.velocity = vector(axis(self.velocity.x, overscroll.x), axis(self.velocity.y, overscroll.y));
+            coordinate_system.origin += self.velocity * h;
+        }
+        let finished = self.velocity.length() < MIN_SPEED && coordinate_system.overscroll().length() < 0.5
+            && coordinate_system.scale_overscroll().length() < 0.5;
+        if finished {
+            coordinate_system.set_scale_overscroll(Vector::zero());
+            coordinate_system.enforce_translation_bounds();
+        }
+        finished
+    }
+}
+
+// Only drags in this many milliseconds before the release count towards the velocity.
+const VELOCITY_WINDOW : f64 = 100.0;
+
+/// Estimates the velocity of a drag from its recent movements. Times are in milliseconds.
+#[derive(Clone, Debug, Default)]
+pub struct VelocityTracker {
+    samples : VecDeque<(f64, Vector)>,
+}
+
+impl VelocityTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, time : f64, delta : Vector) {
+        while matches!(self.samples.front(), Some(&(t, _)) if t < time - VELOCITY_WINDOW) {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((time, delta));
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    /// The average velocity over the window before time, zero if the drag paused for most of it.
+    pub fn velocity(&self, time : f64) -> Vector {
+        let recent = || self.samples.iter().filter(|&&(t, _)| t >= time - VELOCITY_WINDOW);
+        let (first, last) = match (recent().next(), recent().next_back()) {
+            (Some(&(first, _)), Some(&(last, _))) => (first, last),
+            _ => return Vector::zero(),
+        };
+        if last - first <= 0.0 || time - last > VELOCITY_WINDOW / 2.0 {
+            return Vector::zero();
+        }
+        // The movement of the first sample took place before its time, so it isn't part of the span from first to last.
+        let distance = recent().skip(1).fold(Vector::zero(), |sum, &(_, delta)| sum + delta);
+        distance / (last - first) as f32
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn easing() {
+        for &easing in &[Easing::Linear, Easing::EaseInOut, Easing::Decay] {
+            assert_eq!(easing.apply(0.0), 0.0);
+            assert_eq!(easing.apply(1.0), 1.0);
+            let values : Vec<f32> = (0 ..= 1000).map(|i| easing.apply(i as f32 / 1000.0)).collect();
+            assert!(values.windows(2).all(|pair| pair[0] <= pair[1]), "{:?} decreases", easing);
+        }
+        assert_eq!(Easing::EaseInOut.apply(0.5), 0.5);
+        // A fling is mostly done halfway through.
+        assert!(Easing::Decay.apply(0.5) > 0.9);
+    }
+
+    fn viewport(origin : Point, scale : Vector, natural_scale_ratio : f32) -> CoordinateSystem {
+        let mut coordinate_system = CoordinateSystem::new();
+        coordinate_system.origin = origin;
+        coordinate_system.scale = scale;
+        coordinate_system.natural_scale_ratio = natural_scale_ratio;
+        coordinate_system
+    }
+
+    #[test]
+    fn viewport_animation() {
+        use lyon::geom::math::point;
+
+        let from = viewport(point(0.0, 0.0), vector(10.0, 20.0), 2.0);
+        let to = viewport(point(100.0, -50.0), vector(40.0, 20.0), 0.5);
+        let animation = ViewportAnimation::new(&from, &to, 1000.0, 200.0, Easing::Linear);
+        let mut coordinate_system = from;
+        assert!(!animation.apply(&mut coordinate_system, 1000.0));
+        assert_eq!((coordinate_system.origin, coordinate_system.scale), (from.origin, from.scale));
+        // Halfway the origin is halfway and the scale is the harmonic mean.
+        assert!(!animation.apply(&mut coordinate_system, 1100.0));
+        assert_eq!(coordinate_system.origin, point(50.0, -25.0));
+        assert!((coordinate_system.scale.x - 2.0 * 10.0 * 40.0 / 50.0).abs() < 1e-4, "{:?}", coordinate_system.scale);
+        assert_eq!(coordinate_system.scale.y, 20.0);
+        assert_eq!(coordinate_system.natural_scale_ratio, 2.0);
+        // Exactly the end state at and after the end.
+        for &time in &[1200.0, 5000.0] {
+            let mut coordinate_system = from;
+            assert!(animation.apply(&mut coordinate_system, time));
+            assert_eq!((coordinate_system.origin, coordinate_system.scale, coordinate_system.natural_scale_ratio), (to.origin, to.scale, 0.5));
+        }
+
+        let instant = ViewportAnimation::new(&from, &to, 1000.0, 0.0, Easing::EaseInOut);
+        let mut coordinate_system = from;
+        assert!(!instant.apply(&mut coordinate_system, 999.0));
+        assert!(coordinate_system.origin.to_vector().is_finite() && coordinate_system.scale.is_finite());
+        assert!(instant.apply(&mut coordinate_system, 1000.0));
+        assert_eq!((coordinate_system.origin, coordinate_system.scale), (to.origin, to.scale));
+    }
+
+    #[test]
+    fn transition_progress() {
+        let transition = Transition::new(1e12, 100.0);
+        assert_eq!(transition.progress(0.0), 0.0);
+        assert_eq!(transition.progress(1e12 + 50.0), 0.5);
+        // Close enough to the end that an f32 time would be rounded past it.
+        assert!(transition.progress(1e12 + 99.0) < 1.0);
+        assert_eq!(transition.progress(1e12 + 100.0), 1.0);
+        assert_eq!(Transition::NONE.progress(0.0), 1.0);
+    }
+
+    #[test]
+    fn transitions_update() {
+        let mut transitions = Transitions::new();
+        for _ in 0 .. 3 {
+            transitions.push();
+        }
+        let updates = |transitions : &mut Transitions, time| {
+            let mut updates = Vec::new();
+            transitions.update(time, |index, progress| updates.push((index, progress)));
+            updates
+        };
+        assert_eq!(updates(&mut transitions, 0.0), []);
+
+        assert_eq!(transitions.start(2, Transition::new(100.0, 100.0)), 1.0);
+        assert_eq!(transitions.start(0, Transition::new(100.0, 50.0)), 1.0);
+        assert_eq!(updates(&mut transitions, 125.0), [(0, 0.5), (2, Easing::EaseInOut.apply(0.25))]);
+        // The one that finished gets progress 1 once more.
+        assert_eq!(updates(&mut transitions, 150.0), [(0, 1.0), (2, 0.5)]);
+        assert_eq!(updates(&mut transitions, 160.0), [(2, Easing::EaseInOut.apply(0.6))]);
+        // Restarting halfway returns how far it got.
+        assert_eq!(transitions.start(2, Transition::new(175.0, 10.0)), Easing::EaseInOut.apply(0.75));
+        assert_eq!(updates(&mut transitions, 1000.0), [(2, 1.0)]);
+        assert_eq!(updates(&mut transitions, 2000.0), []);
+
+        transitions.start(1, Transition::new(0.0, 10.0));
+        transitions.clear();
+        assert_eq!(updates(&mut transitions, 5.0), []);
+    }
+
+    #[test]
+    fn kinetic_pan_restores_scale() {
+        use crate::coordinate_system::tests::{bounded_coordinate_system, assert_within_bounds};
+        use crate::vector::JsPoint;
+
+        let mut coordinate_system = bounded_coordinate_system(true);
+        coordinate_system.scale_around(0.25, JsPoint { x : 100.0, y : 300.0 }).unwrap();
+        let mut kinetic_pan = KineticPan::new(Vector::zero());
+        assert!(kinetic_pan.is_needed(&coordinate_system));
+        let mut previous = coordinate_system.scale_overscroll();
+        let mut frames = 0;
+        while !kinetic_pan.step(&mut coordinate_system, 16.0) {
+            // Springs back gradually instead of snapping.
+            let scale_overscroll = coordinate_system.scale_overscroll();
+            assert!(scale_overscroll.x < previous.x || scale_overscroll.x < 0.5, "{:?} {:?}", scale_overscroll, previous);
+            assert!(scale_overscroll.x > 0.0 || frames > 10);
+            previous = scale_overscroll;
+            frames += 1;
+            assert!(frames < 1000);
+        }
+        assert_within_bounds(&coordinate_system);
+        assert_eq!(coordinate_system.scale_overscroll(), Vector::zero());
+        assert!((coordinate_system.scale.x - 60.0).abs() < 1e-3 && (coordinate_system.scale.y - 40.0).abs() < 1e-3);
+        assert!(!KineticPan::new(Vector::zero()).is_needed(&coordinate_system));
+    }
+}
@@ -1,6 +1,7 @@
 use uuid::Uuid;
 use std::rc::Rc;
 
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 
 use lyon::geom::math::{point, Point, Vector, vector, Angle, Transform};
@@ -12,7 +13,10 @@ use lyon::tessellation::{
     FillTessellator, FillOptions, VertexBuffers,
 };
 
-use crate::error::convert_tessellation_error;
+use crate::error::ChartError;
+use crate::svg_path::{parse_svg_path, write_svg_path};
+use crate::scene::{ArrowDefinition, ArrowStrokeDefinition};
+#[cfg(feature = "webgl")]
 use crate::webgl_wrapper::WebGlWrapper;
 // pub struct ArrowSettings {
 //     length : ArrowLength,
@@ -52,7 +56,7 @@ use crate::webgl_wrapper::WebGlWrapper;
 //     length_factor : f32,
 // }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 #[derive(Clone, Debug)]
 pub struct Arrow {
     pub(crate) tip_end : f32,
@@ -67,22 +71,87 @@ pub struct Arrow {
 }
 
 impl Arrow {
-    pub fn tesselate_into_buffers(&self, buffers : &mut VertexBuffers<Point, u16>) -> Result<(), JsValue> {
+    pub fn tip_end(&self) -> f32 {
+        self.tip_end
+    }
+
+    pub fn back_end(&self) -> f32 {
+        self.back_end
+    }
+
+    pub fn visual_tip_end(&self) -> f32 {
+        self.visual_tip_end
+    }
+
+    pub fn visual_back_end(&self) -> f32 {
+        self.visual_back_end
+    }
+
+    pub fn line_end(&self) -> f32 {
+        self.line_end
+    }
+
+    pub fn definition(&self) -> ArrowDefinition {
+        ArrowDefinition {
+            path : write_svg_path(self.path.iter()),
+            stroke : self.stroke.map(|options| ArrowStrokeDefinition {
+                line_width : options.line_width,
+                line_join : options.line_join.into(),
+                start_cap : options.start_cap.into(),
+                end_cap : options.end_cap.into(),
+                miter_limit : options.miter_limit,
+                tolerance : options.tolerance,
+            }),
+            fill : self.fill.map(|options| options.tolerance),
+            tip_end : self.tip_end,
+            back_end : self.back_end,
+            visual_tip_end : self.visual_tip_end,
+            visual_back_end : self.visual_back_end,
+            line_end : self.line_end,
+        }
+    }
+
+    /// Rebuild an arrow saved with definition. The result is a new arrow, distinct from the one that was saved.
+    pub fn from_definition(definition : &ArrowDefinition) -> Result<Arrow, ChartError> {
+        let path = parse_svg_path(&definition.path).map_err(ChartError::InvalidInput)?;
+        let stroke = definition.stroke.map(|stroke| StrokeOptions::default()
+            .with_line_width(stroke.line_width)
+            .with_line_join(stroke.line_join.into())
+            .with_start_cap(stroke.start_cap.into())
+            .with_end_cap(stroke.end_cap.into())
+            .with_miter_limit(stroke.miter_limit)
+            .with_tolerance(stroke.tolerance)
+        );
+        let fill = definition.fill.map(|tolerance| FillOptions::default().with_tolerance(tolerance));
+        Ok(Arrow {
+            tip_end : definition.tip_end,
+            back_end : definition.back_end,
+            visual_tip_end : definition.visual_tip_end,
+            visual_back_end : definition.visual_back_end,
+            line_end : definition.line_end,
+            path : Rc::new(path),
+            stroke,
+            fill,
+            uuid : Uuid::new_v4(),
+        })
+    }
+
+    pub fn tesselate_into_buffers(&self, buffers : &mut VertexBuffers<Point, u16>) -> Result<(), ChartError> {
         let mut vertex_builder = geometry_builder::simple_builder(buffers);
         let mut fill = FillTessellator::new();
         let mut stroke = StrokeTessellator::new();
 
         if let Some(fill_options) = &self.fill {
-            fill.tessellate(self.path.iter(), fill_options, &mut vertex_builder).map_err(convert_tessellation_error)?;
+            fill.tessellate(self.path.iter(), fill_options, &mut vertex_builder)?;
         }
         if let Some(stroke_options) = &self.stroke {
-            stroke.tessellate(self.path.iter(), stroke_options, &mut vertex_builder).map_err(convert_tessellation_error)?;
+            stroke.tessellate(self.path.iter(), stroke_options, &mut vertex_builder)?;
         }
         Ok(())
     }
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 impl Arrow {
     // length = +1.6pt 2.2, // 1.6pt + 2.2 * line_width
     // width' = +0pt 2.096774, // 2.096774 * length
@@ -117,8 +186,9 @@ impl Arrow {
     // \fi%
     // \pgfarrowssettipend{\ifpgfarrowroundjoin.5\pgfarrowlinewidth\else\pgf@xc\ifpgfarrowharpoon\advance\pgf@x by\pgf@xa\fi\fi}
 
-    pub fn normal_arrow(line_width : f32, round_join : bool, round_cap : bool, harpoon : bool, reversed : bool) -> Self {
-        let length = line_width * 2.2 + WebGlWrapper::point_to_pixels(1.6);
+    /// The pgf "Stealth" style arrow. `pixel_density` is the number of pixels per point.
+    pub fn normal_arrow_with_density(line_width : f32, pixel_density : f32, round_join : bool, round_cap : bool, harpoon : bool, reversed : bool) -> Self {
+        let length = line_width * 2.2 + 1.6 * pixel_density;
         let width = 2.096774 * length;
         let length = length - line_width;
         let width = width - line_width;
@@ -218,11 +288,12 @@ impl Arrow {
     //     line width = +0pt 1 1,
     //   },
 
-    pub fn hook_arrow(line_width : f32, angle : f32, round_join : bool, round_cap : bool, harpoon : bool, reversed : bool) -> Self {
+    /// The pgf "Hooks" style arrow. `pixel_density` is the number of pixels per point.
+    pub fn hook_arrow_with_density(line_width : f32, pixel_density : f32, angle : f32, round_join : bool, round_cap : bool, harpoon : bool, reversed : bool) -> Self {
     //     % Adjust width and length: Take line thickness into account:
     //     \advance\pgfarrowlength by-.5\pgfarrowlinewidth
     //     \advance\pgfarrowwidth by-\pgfarrowlinewidth
-        let length = line_width * 1.25 + WebGlWrapper::point_to_pixels(0.75);
+        let length = line_width * 1.25 + 0.75 * pixel_density;
         let width = 4.0 * length - line_width;
         let length = length - line_width / 2.0;
         let width = width - line_width;
@@ -418,4 +489,17 @@ impl Arrow {
             uuid : Uuid::new_v4(),
         }
     }
-}
\ No newline at end of file
+}
+
+// These use the pixel density of the browser window.
+#[cfg(feature = "webgl")]
+#[wasm_bindgen]
+impl Arrow {
+    pub fn normal_arrow(line_width : f32, round_join : bool, round_cap : bool, harpoon : bool, reversed : bool) -> Self {
+        Arrow::normal_arrow_with_density(line_width, WebGlWrapper::point_to_pixels(1.0), round_join, round_cap, harpoon, reversed)
+    }
+
+    pub fn hook_arrow(line_width : f32, angle : f32, round_join : bool, round_cap : bool, harpoon : bool, reversed : bool) -> Self {
+        Arrow::hook_arrow_with_density(line_width, WebGlWrapper::point_to_pixels(1.0), angle, round_join, round_cap, harpoon, reversed)
+    }
+}
@@ -1,23 +1,35 @@
 
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
 use wasm_bindgen::prelude::*;
-use web_sys::{WebGl2RenderingContext};
+use web_sys::{WebGl2RenderingContext, WebGlFramebuffer};
 // use std::f32::consts::PI;
 
 
 #[allow(unused_imports)]
 use crate::log;
 
-use crate::glyph::{Glyph, GlyphInstance};
+use crate::glyph::{Glyph, GlyphUuid, GlyphInstance, NUM_COLOR_SLOTS};
+use crate::arrow::Arrow;
+use crate::tessellation_cache::TessellationCache;
+use crate::scene::{Scene, NodeDefinition, EdgeDefinition, LayerDefinition, SCENE_VERSION};
 
-use crate::shader::{GridShader, ChartShaders, EdgeOptions};
+use crate::shader::{GridShader, ChartShaders, EdgeOptions, Layer};
 
 
+use crate::error::{ChartError, checked_index};
 use crate::webgl_wrapper::WebGlWrapper;
-use lyon::geom::math::{Point, point};
+use lyon::geom::math::{Point, point, Vector, Angle};
+use euclid::default::Box2D;
 use crate::vector::{JsPoint, Vec4};
 
 
-use crate::coordinate_system::{CoordinateSystem, BufferDimensions};
+use crate::coordinate_system::{CoordinateSystem, BufferDimensions, FitTarget};
+use crate::animation::{ViewportAnimation, Easing, Transition, KineticPan, VelocityTracker};
+use crate::minimap::{Minimap, Corner};
+use crate::export::{self, Image, Tile};
+use crate::interaction::{InteractionController, PointerEvent, WheelEvent, DeltaMode, Response, Direction, KeyAction, nearest_in_direction};
 
 #[allow(dead_code)]
 static BLACK : Vec4 = Vec4::new(0.0, 0.0, 0.0, 1.0);
@@ -25,6 +37,18 @@ static BLACK : Vec4 = Vec4::new(0.0, 0.0, 0.0, 1.0);
 static GRID_LIGHT_COLOR : Vec4 = Vec4::new(0.0, 0.0, 0.0, 30.0 / 255.0);
 #[allow(dead_code)]
 static GRID_DARK_COLOR : Vec4 = Vec4::new(0.0, 0.0, 0.0, 90.0 / 255.0);
+// Longest time step in milliseconds that start_frame advances kinetic panning by.
+const MAX_FRAME_TIME : f64 = 50.0;
+// Milliseconds of the zoom animation after a double click or box zoom, and of panning to the focused node.
+const INTERACTION_ANIMATION_DURATION : f64 = 250.0;
+// Pixels kept between the focused node and the edge of the screen.
+const FOCUS_PADDING : f32 = 20.0;
+static MINIMAP_BACKGROUND_COLOR : Vec4 = Vec4::new(0.97, 0.97, 0.97, 1.0);
+static MINIMAP_BORDER_COLOR : Vec4 = Vec4::new(0.6, 0.6, 0.6, 1.0);
+static MINIMAP_VIEW_COLOR : Vec4 = Vec4::new(0.1, 0.4, 0.9, 1.0);
+const MINIMAP_VIEW_THICKNESS : f32 = 1.5;
+// The smallest MAX_RENDERBUFFER_SIZE allowed by WebGL 2, MAX_VIEWPORT_DIMS is at least as large.
+const MIN_RENDERBUFFER_SIZE : f64 = 2048.0;
 
 
 
@@ -32,13 +56,38 @@ static GRID_DARK_COLOR : Vec4 = Vec4::new(0.0, 0.0, 0.0, 90.0 / 255.0);
 pub struct Canvas {
     // user affine coordinate transformation
     coordinate_system : CoordinateSystem,
+    // Advanced by start_frame.
+    animation : Option<ViewportAnimation>,
+    // Also advanced by start_frame, by the time since the previous frame.
+    kinetic_pan : Option<KineticPan>,
+    pan_velocity : VelocityTracker,
+    last_frame_time : f64,
+    interaction : InteractionController,
+    // Id of the node with keyboard focus.
+    focused : Option<usize>,
+    minimap : Option<Minimap>,
+    // The pointer dragging the view around in the minimap.
+    minimap_pointer : Option<i32>,
+    // When the last node or edge transition finishes.
+    transitions_end : f64,
 
     // Webgl shaders
     webgl : WebGlWrapper,
     minor_grid_shader : GridShader,
     major_grid_shader : GridShader,
     // axes_shader : LineShader,
-    chart_shaders : ChartShaders
+    chart_shaders : ChartShaders,
+
+    // Everything that was added, by id, so that the scene can be exported.
+    nodes : Vec<GlyphInstance>,
+    edges : Vec<Edge>,
+    page : i32,
+}
+
+pub(crate) struct Edge {
+    pub(crate) start : usize,
+    pub(crate) end : usize,
+    pub(crate) options : EdgeOptions,
 }
 
 #[wasm_bindgen]
@@ -55,9 +104,14 @@ impl JsBuffer {
 #[wasm_bindgen]
 impl Canvas {
     #[wasm_bindgen(constructor)]
-    pub fn new(webgl_context : &WebGl2RenderingContext) -> Result<Canvas, JsValue> {
+    pub fn new(webgl_context : &WebGl2RenderingContext) -> Result<Canvas, ChartError> {
+        Canvas::with_tessellation_cache(webgl_context, &TessellationCache::new())
+    }
+
+    /// Canvases that share a tessellation cache only tessellate each glyph and arrow tip once between them.
+    pub fn with_tessellation_cache(webgl_context : &WebGl2RenderingContext, tessellation_cache : &TessellationCache) -> Result<Canvas, ChartError> {
         let webgl = WebGlWrapper::new(webgl_context.clone());
-        let chart_shaders = ChartShaders::new(webgl.clone())?;
+        let chart_shaders = ChartShaders::new(webgl.clone(), tessellation_cache.clone())?;
 
         let mut minor_grid_shader = GridShader::new(webgl.clone())?;
         minor_grid_shader.thickness(0.5);
@@ -73,10 +127,22 @@ impl Canvas {
 
         let mut result = Self {
             coordinate_system,
+            animation : None,
+            kinetic_pan : None,
+            pan_velocity : VelocityTracker::new(),
+            last_frame_time : js_sys::Date::now(),
+            interaction : InteractionController::new(),
+            focused : None,
+            minimap : None,
+            minimap_pointer : None,
+            transitions_end : 0.0,
             webgl,
             minor_grid_shader,
             major_grid_shader,
             chart_shaders,
+            nodes : Vec::new(),
+            edges : Vec::new(),
+            page : 0,
         };
         result.resize(result.webgl.dimensions()?)?;
         Ok(result)   
@@ -98,9 +164,14 @@ impl Canvas {
         self.coordinate_system.inverse_transform_point(point.into()).into()
     }
 
-    pub fn restore_context(&mut self, webgl_context : &WebGl2RenderingContext) -> Result<(), JsValue> {
+    /// Recreate all the GPU resources on a new context from the retained glyphs, edges and settings.
+    pub fn restore_context(&mut self, webgl_context : &WebGl2RenderingContext) -> Result<(), ChartError> {
         self.webgl = WebGlWrapper::new(webgl_context.clone());
-        // self.grid_shader = LineShader::new(self.webgl.clone())?;
+        self.minor_grid_shader.restore_context(self.webgl.clone())?;
+        self.major_grid_shader.restore_context(self.webgl.clone())?;
+        self.chart_shaders.restore_context(self.webgl.clone())?;
+        self.webgl.viewport_dimensions(self.coordinate_system.buffer_dimensions);
+        self.update_scissor();
         Ok(())
     }
 
@@ -110,7 +181,7 @@ impl Canvas {
         right_margin : i32,
         bottom_margin : i32,
         top_margin : i32,
-    ) -> Result<(), JsValue> {
+    ) -> Result<(), ChartError> {
         self.coordinate_system.set_margins(left_margin, right_margin, bottom_margin, top_margin);
         self.update_scissor();
         Ok(())
@@ -126,13 +197,306 @@ impl Canvas {
     }
 
     pub fn set_current_xrange(&mut self, xmin: f32, xmax: f32) {
+        self.stop_animation();
         self.coordinate_system.set_current_xrange(xmin, xmax);
     }
 
     pub fn set_current_yrange(&mut self, ymin: f32, ymax: f32) {
+        self.stop_animation();
         self.coordinate_system.set_current_yrange(ymin, ymax);
     }
 
+    /// Move the keyboard focus to the node with the given id, or remove it. The viewport pans to show the node.
+    pub fn focus_node(&mut self, id : Option<u32>) -> Result<(), ChartError> {
+        if let Some(id) = id {
+            self.node(id)?;
+        }
+        self.set_focus(id.map(|id| id as usize));
+        Ok(())
+    }
+
+    pub fn focused_node(&self) -> Option<u32> {
+        self.focused.map(|index| index as u32)
+    }
+
+    /// Move the focus to the nearest visible node in the direction, or if nothing has focus to the node nearest the
+    /// middle of the screen. Returns false if there is no such node.
+    pub fn move_focus(&mut self, direction : Direction) -> bool {
+        let visible = self.visible_node_positions();
+        let next = match self.focused {
+            Some(index) => {
+                let from = self.coordinate_system.transform_point(self.nodes[index].center);
+                nearest_in_direction(from, direction, visible.into_iter().filter(|&(other, _)| other != index))
+            }
+            None => {
+                let (screen_x_min, screen_x_max) = self.coordinate_system.screen_x_range();
+                let (screen_y_min, screen_y_max) = self.coordinate_system.screen_y_range();
+                let middle = point(screen_x_min + screen_x_max, screen_y_min + screen_y_max) / 2.0;
+                visible.into_iter().min_by(|(_, a), (_, b)| a.distance_to(middle).total_cmp(&b.distance_to(middle))).map(|(index, _)| index)
+            }
+        };
+        if next.is_some() {
+            self.set_focus(next);
+        }
+        next.is_some()
+    }
+
+    /// Move the focus to the next visible node by id, or the previous one if reverse is set. Past the last node the
+    /// focus is removed and this returns false, so that Tab can leave the chart.
+    pub fn focus_next(&mut self, reverse : bool) -> bool {
+        let visible = (0 .. self.nodes.len()).filter(|&index| self.is_node_visible(index));
+        let next = match (self.focused, reverse) {
+            (None, false) => visible.min(),
+            (None, true) => visible.max(),
+            (Some(focused), false) => visible.filter(|&index| index > focused).min(),
+            (Some(focused), true) => visible.filter(|&index| index < focused).max(),
+        };
+        self.set_focus(next);
+        next.is_some()
+    }
+
+    /// Keyboard navigation, key as in KeyboardEvent.key: the arrow keys move the focus spatially, Tab and Shift+Tab
+    /// cycle through the nodes, Enter and space activate the focused node and Escape removes the focus. Keys that
+    /// don't change anything, like an arrow key with no node in that direction, are Ignored.
+    pub fn handle_key(&mut self, key : &str, shift_key : bool) -> KeyAction {
+        let direction = match key {
+            "ArrowLeft" => Some(Direction::Left),
+            "ArrowRight" => Some(Direction::Right),
+            "ArrowUp" => Some(Direction::Up),
+            "ArrowDown" => Some(Direction::Down),
+            _ => None,
+        };
+        if let Some(direction) = direction {
+            return if self.move_focus(direction) { KeyAction::Navigate } else { KeyAction::Ignored };
+        }
+        match key {
+            "Tab" if self.focus_next(shift_key) => KeyAction::Navigate,
+            "Enter" | " " if self.focused.is_some() => KeyAction::Activate,
+            "Escape" if self.focused.is_some() => {
+                self.set_focus(None);
+                KeyAction::Navigate
+            }
+            _ => KeyAction::Ignored,
+        }
+    }
+
+    /// Text for screen readers about the focused node: its label, position, number of edges and place in the tab order.
+    pub fn focus_description(&self) -> Option<String> {
+        let index = self.focused?;
+        let node = &self.nodes[index];
+        let name = node.label.clone().unwrap_or_else(|| format!("Node {}", index));
+        let layers = self.chart_shaders.layers();
+        let num_edges = self.edges.iter().filter(|edge|
+            (edge.start == index || edge.end == index)
+            && on_page(edge.options.page_range, self.page) && layers[edge.options.layer as usize].visible
+        ).count();
+        let visible : Vec<usize> = (0 .. self.nodes.len()).filter(|&index| self.is_node_visible(index)).collect();
+        let mut description = format!("{} at ({}, {}), {} edge{}",
+            name, node.center.x, node.center.y, num_edges, if num_edges == 1 { "" } else { "s" }
+        );
+        if let Ok(position) = visible.binary_search(&index) {
+            description += &format!(", {} of {}", position + 1, visible.len());
+        }
+        Some(description)
+    }
+
+    /// Name of the node for focus_description.
+    pub fn set_node_label(&mut self, id : u32, label : Option<String>) -> Result<(), ChartError> {
+        self.node(id)?;
+        self.nodes[id as usize].label = label;
+        Ok(())
+    }
+
+    /// Color of the focus ring, its distance from the convex hull of the node and its thickness in pixels.
+    pub fn set_focus_ring_style(&mut self, color : &Vec4, gap : f32, thickness : f32) -> Result<(), ChartError> {
+        if !(gap >= 0.0 && thickness > 0.0) {
+            return Err(ChartError::InvalidInput(format!("Invalid focus ring gap {} or thickness {}.", gap, thickness)));
+        }
+        self.chart_shaders.focus_ring_shader.set_style(*color, gap, thickness);
+        Ok(())
+    }
+
+    /// Frame the nodes with the given ids and the edges between them, or everything visible on the current page if ids
+    /// is not given, leaving padding pixels inside the margins.
+    pub fn zoom_to_fit(&mut self, ids : Option<Vec<u32>>, padding : f32) -> Result<(), ChartError> {
+        let selected : Vec<bool> = match ids {
+            Some(ids) => {
+                let mut selected = vec![false; self.nodes.len()];
+                for id in ids {
+                    self.node(id)?;
+                    selected[id as usize] = true;
+                }
+                selected
+            }
+            None => (0 .. self.nodes.len()).map(|index| self.is_node_visible(index)).collect()
+        };
+        let glyph_scale = self.coordinate_system.glyph_scale;
+        let mut targets : Vec<FitTarget> = self.nodes.iter().zip(&selected).filter(|(_, &selected)| selected).map(|(node, _)| {
+            let extent = Box2D::from_points(node.glyph.boundary().iter().map(|v| (*v * node.scale * glyph_scale).to_point()));
+            FitTarget::Point { position : node.center, extent }
+        }).collect();
+        targets.extend(self.edges.iter().filter(|edge|
+            selected[edge.start] && selected[edge.end] && edge.options.angle.radians != 0.0
+        ).map(|edge| FitTarget::Arc {
+            start : self.nodes[edge.start].center,
+            end : self.nodes[edge.end].center,
+            angle : edge.options.angle.radians,
+            thickness : edge.options.thickness,
+        }));
+        self.stop_animation();
+        self.coordinate_system.zoom_to_fit(&targets, padding);
+        Ok(())
+    }
+
+    /// Move the viewport to show the given range over duration milliseconds, advanced by start_frame.
+    /// Use is_animating to find out when it is done. Panning, zooming or setting the range stops the animation.
+    pub fn animate_to_range(&mut self, xmin : f32, xmax : f32, ymin : f32, ymax : f32, duration : f64, easing : Easing) -> Result<(), ChartError> {
+        if !(xmin < xmax && ymin < ymax) {
+            return Err(ChartError::InvalidInput(format!("Invalid range [{}, {}] x [{}, {}].", xmin, xmax, ymin, ymax)));
+        }
+        if !(duration >= 0.0 && duration.is_finite()) {
+            return Err(ChartError::InvalidInput(format!("Invalid animation duration {}.", duration)));
+        }
+        let mut target = self.coordinate_system;
+        target.set_current_xrange(xmin, xmax);
+        target.set_current_yrange(ymin, ymax);
+        self.animation = Some(ViewportAnimation::new(&self.coordinate_system, &target, js_sys::Date::now(), duration, easing));
+        Ok(())
+    }
+
+    /// True until start_frame has drawn the last frame of the animation started by animate_to_range, of kinetic panning,
+    /// of input gestures and of all node and edge transitions.
+    pub fn is_animating(&self) -> bool {
+        self.animation.is_some() || self.kinetic_pan.is_some() || self.interaction.is_waiting_for_release()
+            || js_sys::Date::now() < self.transitions_end
+    }
+
+    /// Leave the viewport where the animation or kinetic panning has taken it so far.
+    pub fn stop_animation(&mut self) {
+        self.animation = None;
+        self.kinetic_pan = None;
+    }
+
+    /// With kinetic panning, a drag released by release_pan keeps going and slows down with friction. Panning past the max
+    /// ranges and zooming out beyond them meet resistance, and the chart springs back into them afterwards. Without it
+    /// panning and zooming stop at the max ranges.
+    pub fn set_kinetic_panning(&mut self, enabled : bool) {
+        if !enabled {
+            self.kinetic_pan = None;
+        }
+        self.coordinate_system.set_elastic_bounds(enabled);
+    }
+
+    /// End of a drag made of calls to translate. With kinetic panning, the chart keeps moving at the speed of the
+    /// last 100 milliseconds of the drag and springs back into bounds.
+    pub fn release_pan(&mut self) {
+        let velocity = self.pan_velocity.velocity(js_sys::Date::now());
+        self.pan_velocity.clear();
+        self.start_kinetic_pan(velocity);
+    }
+
+    /// A pointer (mouse, finger or pen, from a pointerdown event) went down at (x, y). Shift starts a box zoom.
+    /// Returns true if the chart needs to be redrawn, same for the other input events.
+    pub fn pointer_down(&mut self, id : i32, x : f32, y : f32, shift_key : bool) -> bool {
+        self.stop_animation();
+        if let Some(minimap) = self.minimap {
+            if self.interaction.box_zoom_rectangle().is_none() && minimap.screen_rect(&self.coordinate_system).contains(point(x, y)) {
+                self.minimap_pointer = Some(id);
+                self.center_on_minimap_point(minimap, point(x, y));
+                return true;
+            }
+        }
+        let response = self.interaction.pointer_down(pointer_event(id, x, y, shift_key));
+        self.handle_response(response)
+    }
+
+    pub fn pointer_move(&mut self, id : i32, x : f32, y : f32) -> bool {
+        if let (Some(minimap), Some(minimap_pointer)) = (self.minimap, self.minimap_pointer) {
+            if id == minimap_pointer {
+                self.center_on_minimap_point(minimap, point(x, y));
+                return true;
+            }
+        }
+        let response = self.interaction.pointer_move(&mut self.coordinate_system, pointer_event(id, x, y, false));
+        self.handle_response(response)
+    }
+
+    pub fn pointer_up(&mut self, id : i32, x : f32, y : f32, shift_key : bool) -> bool {
+        if self.end_minimap_drag(id) {
+            return true;
+        }
+        let response = self.interaction.pointer_up(&self.coordinate_system, pointer_event(id, x, y, shift_key));
+        self.handle_response(response)
+    }
+
+    pub fn pointer_cancel(&mut self, id : i32) -> bool {
+        if self.end_minimap_drag(id) {
+            return true;
+        }
+        let response = self.interaction.pointer_cancel(id);
+        self.handle_response(response)
+    }
+
+    /// A wheel event at (x, y), delta_mode as in WheelEvent.deltaMode.
+    pub fn wheel(&mut self, x : f32, y : f32, delta_x : f32, delta_y : f32, delta_mode : u32, ctrl_key : bool) -> bool {
+        self.stop_animation();
+        let event = WheelEvent {
+            position : point(x, y),
+            delta : Vector::new(delta_x, delta_y),
+            delta_mode : DeltaMode::from_dom(delta_mode),
+            ctrl_key,
+            time : js_sys::Date::now(),
+        };
+        let response = self.interaction.wheel(&mut self.coordinate_system, event);
+        self.handle_response(response)
+    }
+
+    /// Show an overview of the whole chart, width by height pixels in the given corner, with the current view outlined.
+    /// Clicking or dragging in it moves the view there.
+    pub fn show_minimap(&mut self, width : f32, height : f32, corner : Corner) -> Result<(), ChartError> {
+        if !(width >= 1.0 && height >= 1.0 && width.is_finite() && height.is_finite()) {
+            return Err(ChartError::InvalidInput(format!("Invalid minimap size {} x {}.", width, height)));
+        }
+        self.minimap = Some(Minimap::new(width, height, corner));
+        Ok(())
+    }
+
+    pub fn hide_minimap(&mut self) {
+        self.minimap = None;
+        self.minimap_pointer = None;
+    }
+
+    /// The box zoom rectangle [xmin, ymin, xmax, ymax] in screen coordinates while shift dragging.
+    pub fn box_zoom_rectangle(&self) -> Option<Vec<f32>> {
+        self.interaction.box_zoom_rectangle().map(|rectangle|
+            vec![rectangle.min.x, rectangle.min.y, rectangle.max.x, rectangle.max.y]
+        )
+    }
+
+    fn handle_response(&mut self, response : Response) -> bool {
+        match response {
+            Response::None => false,
+            Response::Redraw => true,
+            Response::Release(velocity) => {
+                self.start_kinetic_pan(velocity);
+                true
+            }
+            Response::AnimateTo(target) => {
+                self.animation = Some(ViewportAnimation::new(
+                    &self.coordinate_system, &target, js_sys::Date::now(), INTERACTION_ANIMATION_DURATION, Easing::EaseInOut
+                ));
+                true
+            }
+        }
+    }
+
+    fn start_kinetic_pan(&mut self, velocity : Vector) {
+        let kinetic_pan = KineticPan::new(velocity);
+        if self.coordinate_system.elastic_bounds() && kinetic_pan.is_needed(&self.coordinate_system) {
+            self.kinetic_pan = Some(kinetic_pan);
+        }
+    }
+
     pub fn set_max_xrange(&mut self, xmin: f32, xmax: f32) {
         self.coordinate_system.set_max_xrange(xmin, xmax);
     }
@@ -142,14 +506,27 @@ impl Canvas {
     }
 
     pub fn translate(&mut self, delta : JsPoint) {
+        self.stop_animation();
+        self.pan_velocity.add(js_sys::Date::now(), (&delta).into());
         self.coordinate_system.translate(delta);
     }
 
-    pub fn scale_around(&mut self, scale: f32, center: JsPoint) -> Result<(), JsValue> {
+    pub fn scale_around(&mut self, scale: f32, center: JsPoint) -> Result<(), ChartError> {
+        self.stop_animation();
         self.coordinate_system.scale_around(scale, center)?;
+        // Zooming out can go past the bounds, spring back.
+        self.start_kinetic_pan(Vector::zero());
         Ok(())
     }
 
+    /// Draw the chart point (x, y) at lattice coordinates (a x + b y + tx, c x + d y + ty), for example
+    /// (1, -1, 0, 1, 0, 0) draws (t, s) gradings in Adams (t - s, s) position. The current ranges are in lattice
+    /// coordinates, the max ranges stay in chart coordinates. Glyphs stay upright.
+    pub fn set_lattice(&mut self, a : f32, b : f32, c : f32, d : f32, tx : f32, ty : f32) -> Result<(), ChartError> {
+        self.stop_animation();
+        self.coordinate_system.set_lattice(a, b, c, d, tx, ty)
+    }
+
     pub fn set_glyph_scale(&mut self, glyph_scale : f32){
         self.coordinate_system.glyph_scale = glyph_scale;
     }
@@ -167,7 +544,7 @@ impl Canvas {
         self.coordinate_system.transform.transform_point(p.into()).into()
     }
 
-    fn resize(&mut self, new_dimensions : BufferDimensions) -> Result<(), JsValue> {
+    fn resize(&mut self, new_dimensions : BufferDimensions) -> Result<(), ChartError> {
         if new_dimensions == self.coordinate_system.buffer_dimensions {
             return Ok(());
         }
@@ -194,8 +571,29 @@ impl Canvas {
         }
     }
 
-    pub fn start_frame(&mut self) -> Result<(), JsValue> {
+    pub fn start_frame(&mut self) -> Result<(), ChartError> {
+        if self.webgl.is_context_lost() {
+            return Err(ChartError::ContextLost);
+        }
         self.resize(self.webgl.dimensions()?)?;
+        let now = js_sys::Date::now();
+        self.chart_shaders.set_time(now);
+        // Long pauses between frames would make kinetic panning jump.
+        let dt = (now - self.last_frame_time).clamp(0.0, MAX_FRAME_TIME);
+        self.last_frame_time = now;
+        if let Some(animation) = self.animation {
+            if animation.apply(&mut self.coordinate_system, now) {
+                self.animation = None;
+                self.start_kinetic_pan(Vector::zero());
+            }
+        }
+        let response = self.interaction.update(now);
+        self.handle_response(response);
+        if let Some(kinetic_pan) = &mut self.kinetic_pan {
+            if kinetic_pan.step(&mut self.coordinate_system, dt) {
+                self.kinetic_pan = None;
+            }
+        }
         self.webgl.clear_color(1.0, 1.0, 1.0, 1.0);
         self.webgl.clear(WebGl2RenderingContext::COLOR_BUFFER_BIT);
         
@@ -206,19 +604,218 @@ impl Canvas {
 
     pub fn clear(&mut self){
         self.clear_glyphs();
-        self.clear_edges();
     }
 
+    /// Edges refer to the glyphs at their ends, so this removes the edges too.
     pub fn clear_glyphs(&mut self) {
+        self.clear_edges();
+        self.nodes.clear();
+        self.focused = None;
         self.chart_shaders.clear_glyphs();
     }
 
     pub fn clear_edges(&mut self) {
+        self.edges.clear();
         self.chart_shaders.clear_edges();
     }
 
-    pub fn add_glyph(&mut self, point : &JsPoint, glyph : &Glyph, scale : f32,  &stroke_color : &Vec4,  &fill_color : &Vec4 ) -> Result<(), JsValue>  {
-        self.chart_shaders.add_glyph_instance(GlyphInstance::new(glyph.clone(), point.into(), scale,  stroke_color, fill_color))?;
+    /// Returns the id of the new node, for use with add_edge.
+    pub fn add_glyph(&mut self, point : &JsPoint, glyph : &Glyph, scale : f32,  &stroke_color : &Vec4,  &fill_color : &Vec4 ) -> Result<u32, ChartError>  {
+        self.add_node(GlyphInstance::new(glyph.clone(), point.into(), scale,  stroke_color, fill_color))
+    }
+
+    // colors : flat array of rgba values [r0, g0, b0, a0, r1, g1, ...] for the color slots of the glyph.
+    // Slots that aren't given repeat the last color given.
+    pub fn add_glyph_with_colors(&mut self, point : &JsPoint, glyph : &Glyph, scale : f32, colors : &[f32]) -> Result<u32, ChartError>  {
+        self.add_node(GlyphInstance::with_colors(glyph.clone(), point.into(), scale, color_slots(colors)?))
+    }
+
+    /// Move the node to point over duration milliseconds, the edges attached to it follow along.
+    pub fn move_node(&mut self, id : u32, point : &JsPoint, duration : f32) -> Result<(), ChartError> {
+        let colors = self.node(id)?.colors;
+        self.transition_node(id, point.into(), colors, duration)
+    }
+
+    /// Change the colors of the node over duration milliseconds, colors are given as in add_glyph_with_colors.
+    pub fn set_node_colors(&mut self, id : u32, colors : &[f32], duration : f32) -> Result<(), ChartError> {
+        let center = self.node(id)?.center;
+        self.transition_node(id, center, color_slots(colors)?, duration)
+    }
+
+    /// Change the color of the edge over duration milliseconds.
+    pub fn set_edge_color(&mut self, id : u32, color : &Vec4, duration : f32) -> Result<(), ChartError> {
+        let transition = self.transition(duration)?;
+        let num_edges = self.edges.len();
+        let edge = self.edges.get_mut(id as usize).ok_or_else(||
+            ChartError::InvalidInput(format!("No edge with id {}: there are {} edges.", id, num_edges))
+        )?;
+        edge.options.color = *color;
+        self.chart_shaders.transition_edge_color(id as usize, *color, transition);
+        Ok(())
+    }
+
+    /// Add an edge between the nodes with ids start and end, as returned by add_glyph.
+    /// Returns the id of the new edge.
+    pub fn add_edge(&mut self, start : u32, end : u32, options : &EdgeOptions) -> Result<u32, ChartError> {
+        self.add_edge_between(start as usize, end as usize, options.clone())
+    }
+
+    /// Only draw nodes and edges whose page range contains page. Nothing is rebuilt, the shaders skip the rest.
+    pub fn set_page(&mut self, page : i32) {
+        self.page = page;
+        self.chart_shaders.set_page(page);
+    }
+
+    pub fn page(&self) -> i32 {
+        self.page
+    }
+
+    /// Draw the node only on pages first_page through last_page. Edges to the node keep their own page range.
+    pub fn set_node_page_range(&mut self, id : u32, first_page : i32, last_page : i32) -> Result<(), ChartError> {
+        let num_nodes = self.nodes.len();
+        let node = self.nodes.get_mut(id as usize).ok_or_else(||
+            ChartError::InvalidInput(format!("No node with id {}: there are {} nodes.", id, num_nodes))
+        )?;
+        node.page_range = [first_page, last_page];
+        self.chart_shaders.set_glyph_page_range(id as usize, node.page_range);
+        Ok(())
+    }
+
+    /// Draw the edge only on pages first_page through last_page.
+    pub fn set_edge_page_range(&mut self, id : u32, first_page : i32, last_page : i32) -> Result<(), ChartError> {
+        let num_edges = self.edges.len();
+        let edge = self.edges.get_mut(id as usize).ok_or_else(||
+            ChartError::InvalidInput(format!("No edge with id {}: there are {} edges.", id, num_edges))
+        )?;
+        edge.options.page_range = [first_page, last_page];
+        self.chart_shaders.set_edge_page_range(id as usize, edge.options.page_range);
+        Ok(())
+    }
+
+    /// Add a layer to draw nodes and edges in, see set_node_layer and EdgeOptions::set_layer. Layers with higher z_order
+    /// are drawn on top. Everything starts out in the default layer 0, which has z_order 0.
+    /// Returns the index of the new layer.
+    pub fn add_layer(&mut self, name : &str, z_order : i32) -> Result<u32, ChartError> {
+        if self.find_layer(name).is_some() {
+            return Err(ChartError::InvalidInput(format!("There already is a layer named {:?}.", name)));
+        }
+        self.chart_shaders.add_layer(Layer::new(name.to_string(), z_order))
+    }
+
+    pub fn find_layer(&self, name : &str) -> Option<u32> {
+        self.chart_shaders.layers().iter().position(|layer| layer.name == name).map(|index| index as u32)
+    }
+
+    pub fn set_layer_z_order(&mut self, layer : u32, z_order : i32) -> Result<(), ChartError> {
+        self.chart_shaders.layer_mut(layer)?.z_order = z_order;
+        Ok(())
+    }
+
+    /// Hidden layers are neither drawn nor found by object_underneath_pixel.
+    pub fn set_layer_visible(&mut self, layer : u32, visible : bool) -> Result<(), ChartError> {
+        self.chart_shaders.layer_mut(layer)?.visible = visible;
+        Ok(())
+    }
+
+    pub fn set_layer_opacity(&mut self, layer : u32, opacity : f32) -> Result<(), ChartError> {
+        if !(0.0 ..= 1.0).contains(&opacity) {
+            return Err(ChartError::InvalidInput(format!("Layer opacity should be between 0 and 1, got {}.", opacity)));
+        }
+        self.chart_shaders.layer_mut(layer)?.opacity = opacity;
+        Ok(())
+    }
+
+    pub fn set_node_layer(&mut self, id : u32, layer : u32) -> Result<(), ChartError> {
+        self.chart_shaders.check_layer(layer)?;
+        let num_nodes = self.nodes.len();
+        let node = self.nodes.get_mut(id as usize).ok_or_else(||
+            ChartError::InvalidInput(format!("No node with id {}: there are {} nodes.", id, num_nodes))
+        )?;
+        node.layer = layer;
+        self.chart_shaders.set_glyph_layer(id as usize, layer);
+        Ok(())
+    }
+
+    pub fn set_edge_layer(&mut self, id : u32, layer : u32) -> Result<(), ChartError> {
+        self.chart_shaders.check_layer(layer)?;
+        let num_edges = self.edges.len();
+        let edge = self.edges.get_mut(id as usize).ok_or_else(||
+            ChartError::InvalidInput(format!("No edge with id {}: there are {} edges.", id, num_edges))
+        )?;
+        edge.options.layer = layer;
+        self.chart_shaders.set_edge_layer(id as usize, layer);
+        Ok(())
+    }
+
+    pub fn export_scene(&self) -> Result<String, ChartError> {
+        let mut glyph_indices : BTreeMap<GlyphUuid, usize> = BTreeMap::new();
+        let mut glyphs = Vec::new();
+        let nodes = self.nodes.iter().map(|node| {
+            let glyph = *glyph_indices.entry(node.glyph.uuid).or_insert_with(|| {
+                glyphs.push(node.glyph.definition());
+                glyphs.len() - 1
+            });
+            NodeDefinition {
+                glyph,
+                position : node.center.to_array(),
+                scale : node.scale,
+                colors : node.colors,
+                page_range : node.page_range,
+                layer : node.layer,
+                label : node.label.clone(),
+            }
+        }).collect();
+
+        let mut arrow_indices : BTreeMap<Uuid, usize> = BTreeMap::new();
+        let mut arrows = Vec::new();
+        let mut arrow_index = |arrow : &Arrow| *arrow_indices.entry(arrow.uuid).or_insert_with(|| {
+            arrows.push(arrow.definition());
+            arrows.len() - 1
+        });
+        let edges = self.edges.iter().map(|&Edge { start, end, ref options }| EdgeDefinition {
+            start,
+            end,
+            start_tip : options.start_tip.as_ref().map(&mut arrow_index),
+            end_tip : options.end_tip.as_ref().map(&mut arrow_index),
+            bend_angle : options.angle.radians,
+            thickness : options.thickness,
+            color : options.color,
+            dash_pattern : options.dash_pattern.clone(),
+            boundary : options.boundary,
+            page_range : options.page_range,
+            layer : options.layer,
+        }).collect();
+        let layers = self.chart_shaders.layers().iter().map(|layer| LayerDefinition {
+            name : layer.name.clone(),
+            z_order : layer.z_order,
+            visible : layer.visible,
+            opacity : layer.opacity,
+        }).collect();
+
+        Scene {
+            version : SCENE_VERSION,
+            glyphs,
+            arrows,
+            nodes,
+            edges,
+            layers,
+            coordinate_system : self.coordinate_system.definition(),
+            page : self.page,
+        }.to_json()
+    }
+
+    /// Replace everything on the canvas with a scene saved by export_scene.
+    /// If the scene is invalid, the canvas is left as it was.
+    pub fn import_scene(&mut self, json : &str) -> Result<(), ChartError> {
+        let scene = Scene::from_json(json)?;
+        let contents = SceneContents::new(&scene)?;
+        let mut coordinate_system = self.coordinate_system;
+        coordinate_system.set_definition(&scene.coordinate_system)?;
+        self.set_contents(contents)?;
+        self.stop_animation();
+        self.coordinate_system = coordinate_system;
+        self.update_scissor();
+        self.set_page(scene.page);
         Ok(())
     }
 
@@ -229,16 +826,16 @@ impl Canvas {
         start_glyph : &Glyph, end_glyph : &Glyph, 
         scale : f32,
         edge_options : &EdgeOptions
-    ) -> Result<(), JsValue> {        
+    ) -> Result<(), ChartError> {        
         self.clear();
         let start : Point = start_point.into();
         let end : Point = end_point.into();
         let start_glyph = GlyphInstance::new(start_glyph.clone(), start, scale,  Vec4::new(0.0, 0.0, 0.0, 0.5), Vec4::new(1.0, 0.0, 0.0, 0.5));
         let end_glyph = GlyphInstance::new(end_glyph.clone(), end, scale,  Vec4::new(0.0, 1.0, 0.0, 0.5), Vec4::new(0.0, 0.0, 1.0, 0.5));
-        self.chart_shaders.add_glyph_instance(start_glyph.clone())?;
-        self.chart_shaders.add_glyph_instance(end_glyph.clone())?;
+        let start_id = self.add_node(start_glyph)?;
+        let end_id = self.add_node(end_glyph)?;
 
-        self.chart_shaders.add_edge(start_glyph, end_glyph, edge_options)?;
+        self.add_edge(start_id, end_id, edge_options)?;
  
         Ok(())
     }
@@ -248,9 +845,9 @@ impl Canvas {
         glyph1 : &Glyph, glyph2 : &Glyph, 
         xy_max : usize,  scale : f32, 
         edge_options : &EdgeOptions
-    ) -> Result<(), JsValue> {
+    ) -> Result<(), ChartError> {
         self.clear();
-        let mut glyph_instances = Vec::new();
+        let mut node_ids = Vec::new();
 
         for x in 0..xy_max {
             for y in 0..xy_max {
@@ -261,8 +858,7 @@ impl Canvas {
 
                 let glyph = if (x + y) % 2 == 1 { glyph1 } else { glyph2 };
                 let glyph_instance = GlyphInstance::new(glyph.clone(), point(x as f32, y as f32), scale, Vec4::new(0.0, 0.0, 0.0, 0.5), Vec4::new(0.0, 0.0, 1.0, 0.5));
-                self.chart_shaders.add_glyph_instance(glyph_instance.clone())?;
-                glyph_instances.push(glyph_instance);
+                node_ids.push(self.add_node(glyph_instance)?);
             }
         }
         let x_max = xy_max;
@@ -272,23 +868,24 @@ impl Canvas {
             for y in 0..y_max {
                 let source = {
                     let y = 0;
-                    glyph_instances[x * y_max + y].clone()
+                    node_ids[x * y_max + y]
                 };
                 let target = {
                     let x = x - 1;
-                    glyph_instances[x * y_max + y].clone()
+                    node_ids[x * y_max + y]
                 };
-                self.chart_shaders.add_edge(source, target, edge_options)?;
+                self.add_edge(source, target, edge_options)?;
             }
         }
         Ok(())
     }
 
     pub fn get_test_buffer(&self) -> JsBuffer {
-        let mut test_buffer = Vec::new();
-        test_buffer.push(Point::new(0.0, 0.0));
-        test_buffer.push(Point::new(100.0, 100.0));
-        test_buffer.push(Point::new(300.0, 50.0));
+        let test_buffer = vec![
+            Point::new(0.0, 0.0),
+            Point::new(100.0, 100.0),
+            Point::new(300.0, 50.0),
+        ];
         JsBuffer::new(test_buffer)
     }
 
@@ -297,18 +894,500 @@ impl Canvas {
     }
 
     
-    pub fn render(&mut self) -> Result<(), JsValue> {
+    pub fn render(&mut self) -> Result<(), ChartError> {
         self.webgl.premultiplied_blend_mode();
         self.disable_clip();
         self.enable_clip();
         self.minor_grid_shader.draw(self.coordinate_system)?;
         self.major_grid_shader.draw(self.coordinate_system)?;
         self.chart_shaders.draw(self.coordinate_system)?;
+        if let Some(minimap) = self.minimap {
+            self.draw_minimap(minimap)?;
+        }
         Ok(())
     }
 
-    pub fn object_underneath_pixel(&self,  p : JsPoint) -> Result<Option<u32>, JsValue> {
-        self.chart_shaders.object_underneath_pixel(self.coordinate_system, p.into())
+    pub fn object_underneath_pixel(&self,  p : JsPoint) -> Result<Option<u32>, ChartError> {
+        self.chart_shaders.object_underneath_pixel(self.coordinate_system, p)
+    }
+
+    /// Render the range [xmin, xmax, ymin, ymax] (in lattice coordinates) into a width by height pixel png on a white
+    /// background. Glyphs, edges and grid lines are density times as many pixels as css pixels, so 300 dpi is a density
+    /// of 300 / 96. Images larger than the biggest renderbuffer or viewport are drawn in several tiles.
+    pub fn render_to_png(&mut self, width : u32, height : u32, range : &[f32], density : f64) -> Result<Vec<u8>, ChartError> {
+        if range.len() != 4 {
+            return Err(ChartError::invalid_input(format!("Expected a range [xmin, xmax, ymin, ymax], got {} values.", range.len())));
+        }
+        let range = Box2D::new(point(range[0], range[2]), point(range[1], range[3]));
+        let image_coordinate_system = export::image_coordinate_system(&self.coordinate_system, width, height, density, range)?;
+        let max_renderbuffer_size = self.webgl.get_parameter(WebGl2RenderingContext::MAX_RENDERBUFFER_SIZE)?.as_f64().unwrap_or(MIN_RENDERBUFFER_SIZE) as u32;
+        // Tiles larger than the viewport would be drawn partially.
+        let max_viewport_dims = self.webgl.get_parameter(WebGl2RenderingContext::MAX_VIEWPORT_DIMS)?
+            .dyn_into::<js_sys::Int32Array>().map(|dims| dims.to_vec()).unwrap_or_default();
+        let max_size = max_viewport_dims.iter().fold(max_renderbuffer_size, |max_size, &dim| max_size.min(dim.max(1) as u32));
+        let tiles = export::tiles(width, height, max_size);
+
+        let framebuffer = self.webgl.create_framebuffer();
+        let renderbuffer = self.webgl.create_renderbuffer();
+        self.webgl.bind_renderbuffer(WebGl2RenderingContext::RENDERBUFFER, renderbuffer.as_ref());
+        self.webgl.renderbuffer_storage(WebGl2RenderingContext::RENDERBUFFER, WebGl2RenderingContext::RGBA8, width.min(max_size) as i32, height.min(max_size) as i32);
+        self.webgl.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, framebuffer.as_ref());
+        self.webgl.framebuffer_renderbuffer(WebGl2RenderingContext::FRAMEBUFFER, WebGl2RenderingContext::COLOR_ATTACHMENT0, WebGl2RenderingContext::RENDERBUFFER, renderbuffer.as_ref());
+
+        let mut image = Image::new(width, height);
+        let result = self.render_tiles(image_coordinate_system, &mut image, &tiles, framebuffer.as_ref());
+
+        self.webgl.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, None);
+        self.webgl.delete_framebuffer(framebuffer.as_ref());
+        self.webgl.delete_renderbuffer(renderbuffer.as_ref());
+        self.webgl.viewport_dimensions(self.coordinate_system.buffer_dimensions);
+        self.update_scissor();
+        result?;
+        image.encode_png(density)
     }
 }
 
+impl Canvas {
+    fn transition(&mut self, duration : f32) -> Result<Transition, ChartError> {
+        if !(duration >= 0.0 && duration.is_finite()) {
+            return Err(ChartError::InvalidInput(format!("Invalid transition duration {}.", duration)));
+        }
+        let transition = Transition::new(js_sys::Date::now(), duration as f64);
+        self.transitions_end = self.transitions_end.max(transition.end_time());
+        Ok(transition)
+    }
+
+    // Draw each tile into the bottom left of the framebuffer and read it back.
+    fn render_tiles(&mut self, image_coordinate_system : CoordinateSystem, image : &mut Image, tiles : &[Tile], framebuffer : Option<&WebGlFramebuffer>) -> Result<(), ChartError> {
+        self.webgl.premultiplied_blend_mode();
+        for &tile in tiles {
+            let coordinate_system = export::tile_coordinate_system(&image_coordinate_system, tile);
+            let (width, height) = (tile.width as i32, tile.height as i32);
+            self.webgl.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, framebuffer);
+            self.webgl.viewport(0, 0, width, height);
+            self.webgl.scissor(0, 0, width, height);
+            self.webgl.clear_color(1.0, 1.0, 1.0, 1.0);
+            self.webgl.clear(WebGl2RenderingContext::COLOR_BUFFER_BIT);
+            self.minor_grid_shader.draw(coordinate_system)?;
+            self.major_grid_shader.draw(coordinate_system)?;
+            self.chart_shaders.draw_overview(coordinate_system)?;
+            let mut pixels = vec![0; tile.width as usize * tile.height as usize * 4];
+            self.webgl.read_pixels_with_opt_u8_array(
+                0, 0, width, height,
+                WebGl2RenderingContext::RGBA,
+                WebGl2RenderingContext::UNSIGNED_BYTE,
+                Some(&mut pixels)
+            )?;
+            image.copy_tile(tile, &pixels)?;
+        }
+        Ok(())
+    }
+
+    // The minimap shows all visible nodes, or the current view if there are none.
+    fn minimap_coordinate_system(&self, minimap : Minimap) -> CoordinateSystem {
+        let cs = &self.coordinate_system;
+        let positions : Vec<Point> = (0 .. self.nodes.len()).filter(|&index| self.is_node_visible(index))
+            .map(|index| cs.lattice.transform_point(self.nodes[index].center))
+            .collect();
+        let content = if positions.is_empty() {
+            Box2D::new(cs.current_min_xy(), cs.current_max_xy())
+        } else {
+            Box2D::from_points(positions)
+        };
+        minimap.coordinate_system(cs, content)
+    }
+
+    fn center_on_minimap_point(&mut self, minimap : Minimap, screen_point : Point) {
+        let minimap_coordinate_system = self.minimap_coordinate_system(minimap);
+        let delta = minimap.center_delta(&self.coordinate_system, &minimap_coordinate_system, screen_point);
+        self.coordinate_system.translate(delta.into());
+    }
+
+    fn end_minimap_drag(&mut self, id : i32) -> bool {
+        if self.minimap_pointer != Some(id) {
+            return false;
+        }
+        self.minimap_pointer = None;
+        self.start_kinetic_pan(Vector::zero());
+        true
+    }
+
+    // The minimap is drawn from the same buffers as the main view, into its own viewport and scissor rectangle.
+    fn draw_minimap(&mut self, minimap : Minimap) -> Result<(), ChartError> {
+        let rect = minimap.screen_rect(&self.coordinate_system);
+        let inner = rect.inflate(-1.0, -1.0);
+        self.fill_rect(rect, rect, MINIMAP_BORDER_COLOR);
+        self.fill_rect(inner, inner, MINIMAP_BACKGROUND_COLOR);
+
+        let minimap_coordinate_system = self.minimap_coordinate_system(minimap);
+        let [x, y, width, height] = self.device_rect(rect);
+        self.webgl.viewport(x, y, width, height);
+        let [x, y, width, height] = self.device_rect(inner);
+        self.webgl.scissor(x, y, width, height);
+        self.chart_shaders.draw_overview(minimap_coordinate_system)?;
+
+        let (min, max) = (self.coordinate_system.current_min_xy(), self.coordinate_system.current_max_xy());
+        let to_minimap = |p : Point| point(minimap_coordinate_system.transform_x(p.x), minimap_coordinate_system.transform_y(p.y)) + rect.min.to_vector();
+        let view = Box2D::from_points([to_minimap(min), to_minimap(max)]);
+        let t = MINIMAP_VIEW_THICKNESS;
+        for side in [
+            Box2D::new(view.min, point(view.max.x, view.min.y + t)),
+            Box2D::new(point(view.min.x, view.max.y - t), view.max),
+            Box2D::new(view.min, point(view.min.x + t, view.max.y)),
+            Box2D::new(point(view.max.x - t, view.min.y), view.max),
+        ] {
+            self.fill_rect(side, inner, MINIMAP_VIEW_COLOR);
+        }
+
+        self.webgl.viewport_dimensions(self.coordinate_system.buffer_dimensions);
+        self.update_scissor();
+        Ok(())
+    }
+
+    // Clear the part of rect inside clip to color, rectangles are in css pixels from the top left.
+    fn fill_rect(&self, rect : Box2D<f32>, clip : Box2D<f32>, color : Vec4) {
+        let rect = match rect.intersection(&clip) {
+            Some(rect) => rect,
+            None => return,
+        };
+        let [x, y, width, height] = self.device_rect(rect);
+        self.webgl.scissor(x, y, width, height);
+        self.webgl.clear_color(color.x * color.w, color.y * color.w, color.z * color.w, color.w);
+        self.webgl.clear(WebGl2RenderingContext::COLOR_BUFFER_BIT);
+    }
+
+    // [x, y, width, height] in device pixels from the bottom left, as for viewport and scissor.
+    fn device_rect(&self, rect : Box2D<f32>) -> [i32; 4] {
+        let dimensions = self.coordinate_system.buffer_dimensions;
+        let density = dimensions.density() as f32;
+        let x = (rect.min.x * density).round() as i32;
+        let y = ((dimensions.height() as f32 - rect.max.y) * density).round() as i32;
+        let width = (rect.max.x * density).round() as i32 - x;
+        let height = ((dimensions.height() as f32 - rect.min.y) * density).round() as i32 - y;
+        [x, y, width, height]
+    }
+
+    // On the current page and in a visible layer.
+    fn is_node_visible(&self, index : usize) -> bool {
+        let node = &self.nodes[index];
+        on_page(node.page_range, self.page) && self.chart_shaders.layers()[node.layer as usize].visible
+    }
+
+    // Screen positions of the visible nodes by index.
+    fn visible_node_positions(&self) -> Vec<(usize, Point)> {
+        (0 .. self.nodes.len()).filter(|&index| self.is_node_visible(index))
+            .map(|index| (index, self.coordinate_system.transform_point(self.nodes[index].center)))
+            .collect()
+    }
+
+    fn set_focus(&mut self, index : Option<usize>) {
+        self.focused = index;
+        self.chart_shaders.set_focus(index);
+        if let Some(index) = index {
+            self.scroll_into_view(index);
+        }
+    }
+
+    // Pan so that the node is at least FOCUS_PADDING pixels inside the margins, if it fits.
+    fn scroll_into_view(&mut self, index : usize) {
+        let node = &self.nodes[index];
+        let scale = node.scale * self.coordinate_system.glyph_scale;
+        let center = self.coordinate_system.transform_point(node.center);
+        let extent = Box2D::from_points(node.glyph.boundary().iter().map(|v| center + *v * scale))
+            .inflate(FOCUS_PADDING, FOCUS_PADDING);
+        let (screen_x_min, screen_x_max) = self.coordinate_system.screen_x_range();
+        let (screen_y_min, screen_y_max) = self.coordinate_system.screen_y_range();
+        let shift = |min : f32, max : f32, screen_min : f32, screen_max : f32| {
+            if min < screen_min {
+                screen_min - min
+            } else if max > screen_max {
+                (screen_max - max).max(screen_min - min)
+            } else {
+                0.0
+            }
+        };
+        let delta = Vector::new(
+            shift(extent.min.x, extent.max.x, screen_x_min, screen_x_max),
+            shift(extent.min.y, extent.max.y, screen_y_min, screen_y_max),
+        );
+        if delta == Vector::zero() {
+            return;
+        }
+        let mut target = self.coordinate_system;
+        target.translate(delta.into());
+        self.kinetic_pan = None;
+        self.animation = Some(ViewportAnimation::new(
+            &self.coordinate_system, &target, js_sys::Date::now(), INTERACTION_ANIMATION_DURATION, Easing::EaseInOut
+        ));
+    }
+
+    fn node(&self, id : u32) -> Result<&GlyphInstance, ChartError> {
+        self.nodes.get(id as usize).ok_or_else(||
+            ChartError::InvalidInput(format!("No node with id {}: there are {} nodes.", id, self.nodes.len()))
+        )
+    }
+
+    fn transition_node(&mut self, id : u32, center : Point, colors : [Vec4; NUM_COLOR_SLOTS], duration : f32) -> Result<(), ChartError> {
+        self.node(id)?;
+        let transition = self.transition(duration)?;
+        let id = id as usize;
+        let edges : Vec<_> = self.edges.iter().enumerate()
+            .filter(|(_, edge)| edge.start == id || edge.end == id)
+            .map(|(index, edge)| (index, edge.start == id, edge.end == id))
+            .collect();
+        let node = &mut self.nodes[id];
+        node.center = center;
+        node.colors = colors;
+        self.chart_shaders.transition_glyph_instance(id, center, &colors, &edges, transition);
+        Ok(())
+    }
+
+    pub(crate) fn layers(&self) -> &[Layer] {
+        self.chart_shaders.layers()
+    }
+
+    /// Replace the layers, nodes and edges. If the contents don't check out, the canvas is left as it was.
+    pub(crate) fn set_contents(&mut self, contents : SceneContents) -> Result<(), ChartError> {
+        contents.check(self.chart_shaders.tessellation_cache())?;
+        let SceneContents { layers, nodes, edges } = contents;
+        // The rest only fails if the shader data overflows.
+        self.chart_shaders.set_layers(layers)?;
+        self.clear();
+        for node in nodes {
+            self.add_node(node)?;
+        }
+        for Edge { start, end, options } in edges {
+            self.add_edge_between(start, end, options)?;
+        }
+        Ok(())
+    }
+
+    fn add_node(&mut self, node : GlyphInstance) -> Result<u32, ChartError> {
+        let id = checked_index(self.nodes.len(), "nodes")?;
+        self.chart_shaders.add_glyph_instance(node.clone())?;
+        self.nodes.push(node);
+        Ok(id)
+    }
+
+    fn add_edge_between(&mut self, start : usize, end : usize, options : EdgeOptions) -> Result<u32, ChartError> {
+        let id = checked_index(self.edges.len(), "edges")?;
+        let num_nodes = self.nodes.len();
+        let node = |id : usize| self.nodes.get(id).cloned().ok_or_else(||
+            ChartError::InvalidInput(format!("No node with id {}: there are {} nodes.", id, num_nodes))
+        );
+        let (start_node, end_node) = (node(start)?, node(end)?);
+        self.chart_shaders.check_layer(options.layer)?;
+        self.chart_shaders.add_edge(start_node, end_node, &options)?;
+        self.edges.push(Edge { start, end, options });
+        Ok(id)
+    }
+}
+
+// Everything to put on a canvas, built up front and checked so that adding it doesn't fail halfway through.
+// Edges refer to nodes by their index in nodes.
+pub(crate) struct SceneContents {
+    pub(crate) layers : Vec<Layer>,
+    pub(crate) nodes : Vec<GlyphInstance>,
+    pub(crate) edges : Vec<Edge>,
+}
+
+impl SceneContents {
+    fn new(scene : &Scene) -> Result<Self, ChartError> {
+        let layers = if scene.layers.is_empty() {
+            vec![Layer::new("default".to_string(), 0)]
+        } else {
+            scene.layers.iter().map(|layer| Layer {
+                name : layer.name.clone(),
+                z_order : layer.z_order,
+                visible : layer.visible,
+                opacity : layer.opacity,
+            }).collect()
+        };
+        let glyphs = scene.glyphs.iter().map(Glyph::from_definition).collect::<Result<Vec<_>, _>>()?;
+        let arrows = scene.arrows.iter().map(Arrow::from_definition).collect::<Result<Vec<_>, _>>()?;
+        let nodes = scene.nodes.iter().map(|node| {
+            let glyph = &glyphs[scene_index(node.glyph, glyphs.len(), "glyph")?];
+            let mut instance = GlyphInstance::with_colors(glyph.clone(), node.position.into(), node.scale, node.colors);
+            instance.page_range = node.page_range;
+            instance.layer = scene_index(node.layer as usize, layers.len(), "layer")? as u32;
+            instance.label = node.label.clone();
+            Ok(instance)
+        }).collect::<Result<Vec<_>, ChartError>>()?;
+        let arrow = |index : Option<usize>| -> Result<Option<Arrow>, ChartError> {
+            index.map(|index| Ok(arrows[scene_index(index, arrows.len(), "arrow")?].clone())).transpose()
+        };
+        let edges = scene.edges.iter().map(|edge| {
+            let mut options = EdgeOptions::new();
+            options.start_tip = arrow(edge.start_tip)?;
+            options.end_tip = arrow(edge.end_tip)?;
+            options.angle = Angle::radians(edge.bend_angle);
+            options.thickness = edge.thickness;
+            options.color = edge.color;
+            options.dash_pattern = edge.dash_pattern.clone();
+            options.boundary = edge.boundary;
+            options.page_range = edge.page_range;
+            options.layer = scene_index(edge.layer as usize, layers.len(), "layer")? as u32;
+            Ok(Edge {
+                start : scene_index(edge.start, nodes.len(), "node")?,
+                end : scene_index(edge.end, nodes.len(), "node")?,
+                options
+            })
+        }).collect::<Result<Vec<_>, ChartError>>()?;
+
+        Ok(Self { layers, nodes, edges })
+    }
+
+    pub(crate) fn check(&self, tessellation_cache : &TessellationCache) -> Result<(), ChartError> {
+        if self.layers.is_empty() {
+            return Err(ChartError::invalid_input("There has to be at least one layer."));
+        }
+        checked_index::<u32>(self.layers.len() - 1, "layers")?;
+        checked_index::<u32>(self.nodes.len(), "nodes")?;
+        checked_index::<u32>(self.edges.len(), "edges")?;
+        let check_layer = |layer : u32| scene_index(layer as usize, self.layers.len(), "layer");
+        // Bad paths only show up when tessellating. The tessellations are cached for when the nodes and edges are added.
+        for node in &self.nodes {
+            check_layer(node.layer)?;
+            tessellation_cache.glyph(&node.glyph)?;
+        }
+        for edge in &self.edges {
+            scene_index(edge.start, self.nodes.len(), "node")?;
+            scene_index(edge.end, self.nodes.len(), "node")?;
+            check_layer(edge.options.layer)?;
+            for arrow in edge.options.start_tip.iter().chain(&edge.options.end_tip) {
+                tessellation_cache.arrow(arrow)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn scene_index(index : usize, len : usize, what : &str) -> Result<usize, ChartError> {
+    if index < len {
+        Ok(index)
+    } else {
+        Err(ChartError::InvalidInput(format!("Scene refers to {} {} but only has {}.", what, index, len)))
+    }
+}
+
+// colors : flat array of rgba values [r0, g0, b0, a0, r1, g1, ...] for the color slots of a glyph.
+// Slots that aren't given repeat the last color given.
+fn color_slots(colors : &[f32]) -> Result<[Vec4; NUM_COLOR_SLOTS], ChartError> {
+    if colors.is_empty() || !colors.len().is_multiple_of(4) || colors.len() > 4 * NUM_COLOR_SLOTS {
+        return Err(ChartError::invalid_input(format!(
+            "Expected between 1 and {} rgba colors, got {} values.", NUM_COLOR_SLOTS, colors.len()
+        )));
+    }
+    let mut slots = [Vec4::new(0.0, 0.0, 0.0, 0.0); NUM_COLOR_SLOTS];
+    for (i, slot) in slots.iter_mut().enumerate() {
+        let c = &colors[(4 * i).min(colors.len() - 4)..];
+        *slot = Vec4::new(c[0], c[1], c[2], c[3]);
+    }
+    Ok(slots)
+}
+
+fn pointer_event(id : i32, x : f32, y : f32, shift_key : bool) -> PointerEvent {
+    PointerEvent { id, position : point(x, y), time : js_sys::Date::now(), shift_key }
+}
+
+fn on_page(page_range : [i32; 2], page : i32) -> bool {
+    page_range[0] <= page && page <= page_range[1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::glyph::{GlyphBuilder, GlyphBoundary};
+    use crate::scene::CoordinateSystemDefinition;
+
+    fn scene() -> Scene {
+        let mut glyph = GlyphBuilder::from_svg_path("M 0 0 L 10 0 L 10 10 Z", 1.0, true).unwrap();
+        glyph.boxed(2.0);
+        let node = |layer| NodeDefinition {
+            glyph : 0,
+            position : [0.0, 0.0],
+            scale : 1.0,
+            colors : [BLACK; NUM_COLOR_SLOTS],
+            page_range : [0, 0],
+            layer,
+            label : None,
+        };
+        Scene {
+            version : SCENE_VERSION,
+            glyphs : vec![glyph.build().unwrap().definition()],
+            arrows : vec![Arrow::test_arrow().definition()],
+            nodes : vec![node(0), node(1)],
+            edges : vec![EdgeDefinition {
+                start : 0,
+                end : 1,
+                start_tip : None,
+                end_tip : Some(0),
+                bend_angle : 0.0,
+                thickness : 1.0,
+                color : BLACK,
+                dash_pattern : vec![],
+                boundary : GlyphBoundary::ConvexHull,
+                page_range : [0, 0],
+                layer : 1,
+            }],
+            layers : vec![
+                LayerDefinition { name : "bottom".to_string(), z_order : 0, visible : true, opacity : 1.0 },
+                LayerDefinition { name : "top".to_string(), z_order : 1, visible : true, opacity : 1.0 },
+            ],
+            coordinate_system : CoordinateSystemDefinition {
+                origin : [0.0, 0.0],
+                scale : [10.0, 10.0],
+                glyph_scale : 1.0,
+                margins : [0; 4],
+                natural_scale_ratio : 1.0,
+                max_scale : 100.0,
+                min_xy_boundary : [None, None],
+                max_xy_boundary : [None, None],
+                lattice : [1.0, 0.0, 0.0, 1.0, 0.0, 0.0],
+            },
+            page : 0,
+        }
+    }
+
+    fn checked_contents(scene : &Scene) -> Result<SceneContents, ChartError> {
+        let contents = SceneContents::new(scene)?;
+        contents.check(&TessellationCache::new())?;
+        Ok(contents)
+    }
+
+    #[test]
+    fn scene_contents() {
+        let contents = checked_contents(&scene()).unwrap();
+        assert_eq!((contents.layers.len(), contents.nodes.len(), contents.edges.len()), (2, 2, 1));
+        assert_eq!(contents.nodes[1].layer, 1);
+        assert!(contents.edges[0].options.end_tip.is_some());
+
+        let mut single_layer = scene();
+        single_layer.layers.clear();
+        single_layer.nodes.truncate(1);
+        single_layer.edges.clear();
+        let contents = checked_contents(&single_layer).unwrap();
+        assert_eq!(contents.layers.len(), 1);
+    }
+
+    #[test]
+    fn invalid_scene_contents() {
+        let invalid : Vec<fn(&mut Scene)> = vec![
+            |scene| scene.glyphs[0].convex_hull_component = 2,
+            |scene| scene.glyphs[0].components[0].path = "M 0 0 L 1".to_string(),
+            |scene| scene.arrows[0].path = "Q 1 2".to_string(),
+            |scene| scene.nodes[0].glyph = 1,
+            |scene| scene.nodes[1].layer = 2,
+            |scene| scene.edges[0].end = 2,
+            |scene| scene.edges[0].start_tip = Some(1),
+            |scene| scene.edges[0].layer = 2,
+            |scene| scene.layers.truncate(1),
+        ];
+        for (i, make_invalid) in invalid.into_iter().enumerate() {
+            let mut scene = scene();
+            make_invalid(&mut scene);
+            let result = checked_contents(&scene);
+            assert!(matches!(result, Err(ChartError::InvalidInput(_))), "case {}", i);
+        }
+    }
+}
@@ -1,6 +1,8 @@
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 
 
+#[cfg(feature = "wasm")]
 #[wasm_bindgen]
 extern "C" {
     // Use `js_namespace` here to bind `console.log(..)` instead of just `log(..)`
@@ -11,8 +13,15 @@ extern "C" {
     pub fn log_str(s: &str);    
 }
 
+// Outside of the browser there is no console, log to stderr instead.
+#[cfg(not(feature = "wasm"))]
+#[allow(dead_code)]
+pub fn log_str(s: &str) {
+    eprintln!("{}", s);
+}
+
 #[macro_export]
 macro_rules! log {
-    () => { log_str("") };
-    ($($arg:tt)*) => { crate::console_log::log_str(&format!($($arg)*)) };
+    () => { $crate::console_log::log_str("") };
+    ($($arg:tt)*) => { $crate::console_log::log_str(&format!($($arg)*)) };
 }
\ No newline at end of file
@@ -7,6 +7,7 @@ use std::borrow::Borrow;
 use euclid::default::Box2D;
 
 use lyon::geom::math::{Point, Vector, Angle};
+use lyon::path::{PathEvent, iterator::PathIterator};
 
 use footile::{Path2D, PathOp, Plotter, FillRule, Transform};
 use pix::{Raster, el::Pixel, chan::Channel, matte::Matte8};
@@ -14,6 +15,11 @@ use pix::{Raster, el::Pixel, chan::Channel, matte::Matte8};
 
 pub const ANGLE_RESOLUTION : usize = 180;
 
+// Tolerance used to flatten curves before taking their convex hull, in glyph units.
+const FLATTENING_TOLERANCE : f32 = 0.01;
+// Number of sides of the polygon used to approximate the pen when accounting for stroke width.
+const PEN_POLYGON_SIDES : usize = 16;
+
 fn raster_midpoint<P: Pixel>(raster : &Raster<P>) -> Point {
 	Point::new((raster.width()/2) as f32, (raster.height()/2) as f32)
 }
@@ -26,7 +32,7 @@ fn raster_contains_point<P: Pixel>(raster : &Raster<P>, point : Point) -> bool {
 fn raster_to_convex_hull_polygon<P: Pixel>(raster : &Raster<P>, precision : f32) -> Vec<Vector> {
 	let mut convex_hull = sample_raster_outline(raster, Point::origin(), ANGLE_RESOLUTION);
 	average_nearby_points(&mut convex_hull, precision);
-	graham_scan(&mut convex_hull);
+	convex_hull_polygon(&mut convex_hull);
 	// convex_hull.shrink_to_fit();
 	convex_hull
 }
@@ -91,59 +97,170 @@ fn orientation(p : Vector, q : Vector, r : Vector) -> f32 {
 	Vector::cross(r - q, q - p)
 }
 
-fn compare_magnitudes(p : Vector, q : Vector) -> Ordering {
-	p.length().partial_cmp(&q.length()).unwrap()
+// Unlike total_cmp this treats 0.0 and -0.0 as equal. Only meaningful for non-NaN values.
+fn compare_floats(a : f32, b : f32) -> Ordering {
+	a.partial_cmp(&b).unwrap_or(Ordering::Equal)
 }
 
-#[derive(PartialEq,PartialOrd)]
-struct NonNan(f32);
-
-impl NonNan {
-    fn new(val: f32) -> Option<NonNan> {
-        if val.is_nan() {
-            None
-        } else {
-            Some(NonNan(val))
-        }
-    }
+// Append the lower (or upper) half of the hull of points, which must be sorted by x then y.
+// Points that aren't strictly convex turns are popped, so collinear points are dropped.
+fn push_hull_chain<'a, T : Iterator<Item=&'a Vector>>(hull : &mut Vec<Vector>, points : T) {
+	let chain_start = hull.len();
+	for &p in points {
+		while hull.len() >= chain_start + 2 && orientation(hull[hull.len() - 2], hull[hull.len() - 1], p) >= 0.0 {
+			hull.pop();
+		}
+		hull.push(p);
+	}
+	// The last point of each chain is the first point of the other one.
+	hull.pop();
 }
 
-impl Eq for NonNan {}
+// Replaces points with the vertices of their convex hull, using Andrew's monotone chain algorithm.
+// Unlike a Graham scan this doesn't sort by angle, so it is not thrown off by nearly collinear points.
+// Points must be finite. Fewer than three distinct points, or collinear points, give a degenerate hull
+// with fewer than three vertices.
+fn convex_hull_polygon(points : &mut Vec<Vector>) {
+	debug_assert!(points.iter().all(|p| p.x.is_finite() && p.y.is_finite()));
+	points.sort_by(|p, q| compare_floats(p.x, q.x).then_with(|| compare_floats(p.y, q.y)));
+	points.dedup();
+	if points.len() < 3 {
+		return;
+	}
+	let mut hull : Vec<Vector> = Vec::with_capacity(points.len() + 1);
+	push_hull_chain(&mut hull, points.iter());
+	push_hull_chain(&mut hull, points.iter().rev());
+	*points = hull;
+}
 
-impl Ord for NonNan {
-    fn cmp(&self, other: &NonNan) -> Ordering {
-        self.partial_cmp(other).unwrap()
-    }
+// Points of the flattened path, each replaced by a polygon circumscribing the pen if the path is stroked.
+fn stroked_path_points<T : Iterator<Item=PathEvent>>(path : T, stroke_width : f32) -> Vec<Vector> {
+	let mut points : Vec<Vector> = path.flattened(FLATTENING_TOLERANCE).filter_map(|event| match event {
+		PathEvent::Begin { at } => Some(at.to_vector()),
+		PathEvent::Line { to, .. } => Some(to.to_vector()),
+		_ => None
+	}).collect();
+	if stroke_width > 0.0 {
+		// Miter joins may stick out a bit further than this, we ignore that.
+		let pen_radius = stroke_width / 2.0 / f32::cos(std::f32::consts::PI / PEN_POLYGON_SIDES as f32);
+		let angle_step = Angle::two_pi() / (PEN_POLYGON_SIDES as f32);
+		let pen : Vec<Vector> = (0 .. PEN_POLYGON_SIDES).map(|i|
+			Vector::from_angle_and_length(angle_step * (i as f32), pen_radius)
+		).collect();
+		points = points.iter().flat_map(|&p| pen.iter().map(move |&offset| p + offset)).collect();
+	}
+	points
 }
 
+// Is point strictly inside the convex polygon? Degenerate polygons contain nothing.
+fn polygon_contains(polygon : &[Vector], point : Vector) -> bool {
+	if polygon.len() < 3 {
+		return false;
+	}
+	let sides = polygon.iter().zip(polygon.iter().cycle().skip(1)).map(|(&p, &q)| Vector::cross(q - p, point - p));
+	let (mut left, mut right) = (true, true);
+	for side in sides {
+		left &= side > 0.0;
+		right &= side < 0.0;
+	}
+	left || right
+}
 
-fn graham_scan(points : &mut Vec<Vector>) {
-	
-	// Find minimum Y
-	let (min_idx, _) = points.iter().enumerate().min_by_key(|(_idx, v)| NonNan::new(v.y).unwrap()).unwrap();
+// Distance from center along direction to the boundary of the convex polygon.
+// Assumes center is inside the polygon. For a degenerate polygon (a segment) this is the distance to the segment
+// if the ray crosses it and zero otherwise.
+fn ray_polygon_distance(polygon : &[Vector], center : Vector, direction : Vector) -> f32 {
+	let mut result : f32 = 0.0;
+	if polygon.len() < 2 {
+		return result;
+	}
+	for (i, &p) in polygon.iter().enumerate() {
+		let q = polygon[(i + 1) % polygon.len()];
+		let edge = q - p;
+		let denominator = Vector::cross(direction, edge);
+		if denominator == 0.0 {
+			continue;
+		}
+		// Solve center + t * direction = p + s * edge.
+		let t = Vector::cross(p - center, edge) / denominator;
+		let s = Vector::cross(p - center, direction) / denominator;
+		if (0.0 ..= 1.0).contains(&s) {
+			result = result.max(t);
+		}
+	}
+	result
+}
 
-	// Put minimum at zero
-	points.swap(0, min_idx);
+fn sample_polygon_outline(polygon : &[Vector], center : Vector, point_count : usize) -> Vec<Vector> {
+	let angle_step = Angle::two_pi() / (point_count as f32);
+	(0 .. point_count).map(|i| {
+		let direction = Vector::from_angle_and_length(angle_step * (i as f32), 1.0);
+		direction * ray_polygon_distance(polygon, center, direction)
+	}).collect()
+}
 
-	let compare_point = points[0];
-	points.sort_by(	
-		move |&p1, &p2| // sort first by the handedness of (compare_point, p1, p2) then by distance from compare_pt.
-		orientation(compare_point, p1, p2).partial_cmp(&0.0).unwrap().then_with(|| compare_magnitudes(p1 - compare_point, p2 - compare_point))
-	);
+// Largest t >= 0 such that center + t * direction is within radius of the segment from p to q, if there is one.
+// With radius zero this is where the ray crosses the segment.
+fn ray_capsule_far_distance(center : Vector, direction : Vector, p : Vector, q : Vector, radius : f32) -> Option<f32> {
+	let mut result : Option<f32> = None;
+	let mut include = |t : f32| if t >= 0.0 {
+		result = Some(result.map_or(t, |r| r.max(t)));
+	};
+	let edge = q - p;
+	let denominator = Vector::cross(direction, edge);
+	if denominator != 0.0 {
+		let normal = Vector::new(-edge.y, edge.x).normalize() * radius;
+		for &offset in &[normal, -normal] {
+			// Solve center + t * direction = p + offset + s * edge.
+			let t = Vector::cross(p + offset - center, edge) / denominator;
+			let s = Vector::cross(p + offset - center, direction) / denominator;
+			if (0.0 ..= 1.0).contains(&s) {
+				include(t);
+			}
+		}
+	}
+	if radius > 0.0 {
+		// Round caps at the ends of the segment.
+		for &end in &[p, q] {
+			let projection = direction.dot(end - center);
+			let discriminant = projection * projection - (end - center).square_length() + radius * radius;
+			if discriminant >= 0.0 {
+				include(projection + discriminant.sqrt());
+			}
+		}
+	}
+	result
+}
 
-	// Create & initialize stack
-	let mut stack_length : usize = 3;
-	for i in 3 .. points.len() {
-		// Seems like this could lead to an infinite loop here...
-		// Luckily, Rust will panic if stack_index becomes less than 2
-		while orientation(points[stack_length - 2], points[stack_length - 1], points[i]) >= 0.0 {
-			stack_length -= 1;
+/// Sample the distance from center to the outermost ink of the glyph in ANGLE_RESOLUTION directions.
+/// Unlike the convex hull this follows concave parts of the glyph. Each component is given as its path,
+/// whether it is filled, and its stroke width (zero if it isn't stroked). In directions that don't meet any
+/// ink the distance is zero, so edges coming from that direction run all the way to the center.
+pub fn sample_star_outline<T : Iterator<Item=PathEvent>>(
+	components : impl Iterator<Item=(T, bool, f32)>, center : Point
+) -> Vec<Vector> {
+	let mut segments : Vec<(Vector, Vector, f32)> = Vec::new();
+	for (path, fill, stroke_width) in components {
+		let radius = stroke_width / 2.0;
+		for event in path.flattened(FLATTENING_TOLERANCE) {
+			match event {
+				PathEvent::Begin { at } if radius > 0.0 => segments.push((at.to_vector(), at.to_vector(), radius)),
+				PathEvent::Line { from, to } => segments.push((from.to_vector(), to.to_vector(), radius)),
+				// Filled paths are implicitly closed.
+				PathEvent::End { last, first, close } if close || fill => segments.push((last.to_vector(), first.to_vector(), radius)),
+				_ => {}
+			}
 		}
-		points[stack_length] = points[i];
-		stack_length += 1;
 	}
-	// Shrink list to new length (panic if somehow output_idx > points.len())
-	points.resize_with(stack_length, || unreachable!());
+	let center = center.to_vector();
+	let angle_step = Angle::two_pi() / (ANGLE_RESOLUTION as f32);
+	(0 .. ANGLE_RESOLUTION).map(|i| {
+		let direction = Vector::from_angle_and_length(angle_step * (i as f32), 1.0);
+		segments.iter()
+			.filter_map(|&(p, q, radius)| ray_capsule_far_distance(center, direction, p, q, radius))
+			.fold(None, |max : Option<f32>, t| Some(max.map_or(t, |m| m.max(t))))
+			.map_or(Vector::zero(), |t| direction * t)
+	}).collect()
 }
 
 fn rasterize_polygon(polygon : &[Vector], width : u32, height : u32) -> Raster<Matte8> {
@@ -162,13 +279,46 @@ fn rasterize_polygon(polygon : &[Vector], width : u32, height : u32) -> Raster<M
 
 pub struct ConvexHull {
 	pub outline : Vec<Vector>,
-	raster_scale : f32,
-	bounding_box : Box2D<f32>
+	// The outline is relative to this point, which is inside the hull.
+	center : Point,
 }
 
 impl ConvexHull {
 
-	pub fn from_path<T>(path : T, bounding_box : Box2D<f32>) -> Self
+	/// Compute the hull exactly from the (flattened) path, enlarged by the pen if the path is stroked.
+	/// An empty path, a single point or a straight line give a degenerate hull, non-finite coordinates are an error.
+	pub fn from_path<T : Iterator<Item=PathEvent>>(path : T, stroke_width : f32, bounding_box : Box2D<f32>) -> Result<Self, String> {
+		if !stroke_width.is_finite() || !bounding_box.min.to_vector().is_finite() || !bounding_box.max.to_vector().is_finite() {
+			return Err("Glyph has non-finite size or stroke width.".to_string());
+		}
+		let mut polygon = stroked_path_points(path, stroke_width);
+		if polygon.iter().any(|p| !p.is_finite()) {
+			return Err("Glyph path has non-finite coordinates.".to_string());
+		}
+		convex_hull_polygon(&mut polygon);
+		// Rays are cast from the center, so it has to be inside the hull. The middle of the bounding box usually is,
+		// but not if the hull component is off to one side of the glyph.
+		let mut center = bounding_box.center().to_vector();
+		if !polygon_contains(&polygon, center) && !polygon.is_empty() {
+			center = polygon.iter().fold(Vector::zero(), |total, &p| total + p) / polygon.len() as f32;
+		}
+		Ok(Self {
+			outline : sample_polygon_outline(&polygon, center, ANGLE_RESOLUTION),
+			center : center.to_point(),
+		})
+	}
+
+	/// Hull of a glyph with nothing in it: every boundary point is the center.
+	pub fn empty(bounding_box : Box2D<f32>) -> Self {
+		Self {
+			outline : vec![Vector::zero(); ANGLE_RESOLUTION],
+			center : bounding_box.center(),
+		}
+	}
+
+	/// Compute the hull by rasterizing the path. This is slower and less precise than from_path,
+	/// but doesn't depend on the stroke of the path being known.
+	pub fn from_path_raster<T>(path : T, bounding_box : Box2D<f32>) -> Self
 	where T: IntoIterator, T::Item: Borrow<PathOp>, 
 	{
 		let width_and_height = bounding_box.max - bounding_box.min;
@@ -193,33 +343,263 @@ impl ConvexHull {
 		}
 		Self {
 			outline,
-			raster_scale,
-			bounding_box
+			center : bounding_box.center(),
 		}
 	}
 
-	#[allow(dead_code)]
-	fn into_raster_coords(&self, mut point : Vector) -> Point {
-		point *= self.raster_scale;
-		self.center() + point
-	}
-
-	// This is pre-applied to self.outline.
-	#[allow(dead_code)]
-	fn from_raster_coords(&self, p : Point) -> Vector {
-		let mut v = p - self.center();
-		v /= self.raster_scale;
-		v
-	}
-
 	#[allow(dead_code)]
 	pub fn find_boundary_point(&self, angle : Angle) -> Vector {
 		let index = ((ANGLE_RESOLUTION as f32) * (angle.positive()/Angle::two_pi())) as usize;
 		self.outline[index]
 	}
 
+	/// The glyph is drawn and its outline sampled relative to this point. It is the middle of the bounding box unless
+	/// that is outside of the hull.
 	pub fn center(&self) -> Point {
-		self.bounding_box.max.lerp(self.bounding_box.min,  0.5)
+		self.center
 	}
 }
 
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use lyon::geom::math::{point, vector};
+
+	// Small seeded generator so that failures are reproducible.
+	struct XorShift(u64);
+
+	impl XorShift {
+		fn next(&mut self) -> u64 {
+			self.0 ^= self.0 << 13;
+			self.0 ^= self.0 >> 7;
+			self.0 ^= self.0 << 17;
+			self.0
+		}
+
+		// Uniform in [0, 1).
+		fn float(&mut self) -> f32 {
+			(self.next() >> 40) as f32 / (1u64 << 24) as f32
+		}
+
+		fn below(&mut self, n : usize) -> usize {
+			(self.next() % n as u64) as usize
+		}
+	}
+
+	// Clouds of a few different shapes: uniform in a square, on a circle, on a coarse grid (lots of collinear
+	// points) and with many repeated points.
+	fn random_cloud(rng : &mut XorShift) -> Vec<Vector> {
+		let n = rng.below(60);
+		let mut points : Vec<Vector> = match rng.below(4) {
+			0 => (0 .. n).map(|_| vector(rng.float() * 200.0 - 100.0, rng.float() * 200.0 - 100.0)).collect(),
+			1 => (0 .. n).map(|_| Vector::from_angle_and_length(Angle::two_pi() * rng.float(), 50.0)).collect(),
+			2 => (0 .. n).map(|_| vector(rng.below(5) as f32, rng.below(5) as f32)).collect(),
+			_ => (0 .. n).map(|_| vector(rng.below(3) as f32 * 0.5, rng.float().round())).collect(),
+		};
+		for _ in 0 .. rng.below(5) {
+			if !points.is_empty() {
+				let duplicate = points[rng.below(points.len())];
+				points.push(duplicate);
+			}
+		}
+		points
+	}
+
+	fn check_hull(input : &[Vector], hull : &[Vector]) {
+		assert!(hull.iter().all(|p| input.contains(p)), "hull {:?} has points not in {:?}", hull, input);
+		if hull.len() < 3 {
+			// Degenerate: everything is on the segment between the (at most two) hull points.
+			if let &[p, q] = hull {
+				for &r in input {
+					assert!(Vector::cross(q - p, r - p).abs() <= 1e-3 * (q - p).length().max(1.0), "{:?} not on {:?}", r, hull);
+				}
+			}
+			return;
+		}
+		let edges : Vec<(Vector, Vector)> = hull.iter().zip(hull.iter().cycle().skip(1)).map(|(&p, &q)| (p, q)).collect();
+		for (i, &(p, q)) in edges.iter().enumerate() {
+			let (_, r) = edges[(i + 1) % edges.len()];
+			// Counter-clockwise (y up) and strictly convex: every turn is to the left.
+			assert!(Vector::cross(q - p, r - q) > 0.0, "not a convex counter-clockwise polygon: {:?}", hull);
+			for &s in input {
+				let tolerance = 1e-4 * (q - p).length() * (s - p).length().max(1.0);
+				assert!(Vector::cross(q - p, s - p) >= -tolerance, "{:?} is outside of {:?}", s, hull);
+			}
+		}
+	}
+
+	#[test]
+	fn random_clouds() {
+		let mut rng = XorShift(0x2545_F491_4F6C_DD1D);
+		for _ in 0 .. 2000 {
+			let input = random_cloud(&mut rng);
+			let mut hull = input.clone();
+			convex_hull_polygon(&mut hull);
+			check_hull(&input, &hull);
+		}
+	}
+
+	#[test]
+	fn degenerate_polygons() {
+		let cases : Vec<Vec<Vector>> = vec![
+			vec![],
+			vec![vector(1.0, 2.0)],
+			vec![vector(1.0, 2.0); 5],
+			vec![vector(0.0, 0.0), vector(1.0, 1.0), vector(2.0, 2.0), vector(3.0, 3.0)],
+			vec![vector(0.0, 0.0), vector(0.0, 1.0), vector(0.0, 0.0), vector(0.0, -4.0)],
+		];
+		for input in cases {
+			let mut hull = input.clone();
+			convex_hull_polygon(&mut hull);
+			assert!(hull.len() <= 2);
+			check_hull(&input, &hull);
+		}
+		// Collinear points are dropped from the sides of a proper polygon.
+		let mut hull = vec![vector(0.0, 0.0), vector(1.0, 0.0), vector(2.0, 0.0), vector(2.0, 2.0), vector(0.0, 2.0), vector(1.0, 1.0)];
+		convex_hull_polygon(&mut hull);
+		assert_eq!(hull, vec![vector(0.0, 0.0), vector(2.0, 0.0), vector(2.0, 2.0), vector(0.0, 2.0)]);
+	}
+
+	// Built by hand, lyon's path builder rejects non-finite points.
+	fn polyline(points : &[Point]) -> Vec<PathEvent> {
+		let mut result = Vec::new();
+		if let Some(&first) = points.first() {
+			result.push(PathEvent::Begin { at : first });
+			for pair in points.windows(2) {
+				result.push(PathEvent::Line { from : pair[0], to : pair[1] });
+			}
+			result.push(PathEvent::End { last : points[points.len() - 1], first, close : false });
+		}
+		result
+	}
+
+	fn unit_box() -> Box2D<f32> {
+		Box2D::new(point(-1.0, -1.0), point(1.0, 1.0))
+	}
+
+	#[test]
+	fn degenerate_paths() {
+		let paths = vec![
+			polyline(&[]),
+			polyline(&[point(0.0, 0.0)]),
+			polyline(&[point(0.0, 0.0), point(0.0, 0.0)]),
+			polyline(&[point(-1.0, -1.0), point(0.0, 0.0), point(1.0, 1.0)]),
+		];
+		for path in &paths {
+			for &stroke_width in &[0.0, 0.5] {
+				let hull = ConvexHull::from_path(path.iter().copied(), stroke_width, unit_box()).unwrap();
+				assert_eq!(hull.outline.len(), ANGLE_RESOLUTION);
+				assert!(hull.outline.iter().all(|v| v.is_finite()));
+			}
+		}
+		// A stroked point is a disk.
+		let hull = ConvexHull::from_path(polyline(&[point(0.0, 0.0)]).into_iter(), 1.0, unit_box()).unwrap();
+		assert!(hull.outline.iter().all(|v| (v.length() - 0.5).abs() < 0.01));
+	}
+
+	#[test]
+	fn non_finite_input_is_an_error() {
+		let path = polyline(&[point(0.0, 0.0), point(f32::NAN, 1.0), point(1.0, 0.0)]);
+		assert!(ConvexHull::from_path(path.iter().copied(), 0.0, unit_box()).is_err());
+		let path = polyline(&[point(0.0, 0.0), point(f32::INFINITY, 1.0), point(1.0, 0.0)]);
+		assert!(ConvexHull::from_path(path.iter().copied(), 1.0, unit_box()).is_err());
+		let path = polyline(&[point(0.0, 0.0), point(1.0, 1.0)]);
+		assert!(ConvexHull::from_path(path.iter().copied(), f32::NAN, unit_box()).is_err());
+		let nan_box = Box2D::new(point(f32::NAN, 0.0), point(1.0, 1.0));
+		assert!(ConvexHull::from_path(path.iter().copied(), 0.0, nan_box).is_err());
+	}
+
+	#[test]
+	fn outline_of_random_polygons() {
+		// Every sampled boundary point is on the hull, and every input point is within the sampled outline's radius
+		// in its direction (up to the angular resolution).
+		let mut rng = XorShift(0x9E37_79B9_7F4A_7C15);
+		for _ in 0 .. 200 {
+			let points : Vec<Point> = (0 .. 3 + rng.below(20)).map(|_| point(rng.float() * 2.0 - 1.0, rng.float() * 2.0 - 1.0)).collect();
+			let mut polygon : Vec<Vector> = points.iter().map(|p| p.to_vector()).collect();
+			convex_hull_polygon(&mut polygon);
+			if polygon.len() < 3 {
+				continue;
+			}
+			let hull = ConvexHull::from_path(polyline(&points).into_iter(), 0.0, unit_box()).unwrap();
+			let center = hull.center().to_vector();
+			assert!(polygon_contains(&polygon, center));
+			for &v in &hull.outline {
+				let boundary = center + v;
+				let on_edge = polygon.iter().zip(polygon.iter().cycle().skip(1)).any(|(&p, &q)| {
+					Vector::cross(q - p, boundary - p).abs() < 1e-4 && (boundary - p).dot(boundary - q) <= 1e-4
+				});
+				assert!(on_edge, "{:?} is not on the boundary of {:?}", boundary, polygon);
+			}
+		}
+	}
+
+	fn direction(index : usize) -> Vector {
+		Vector::from_angle_and_length(Angle::two_pi() * (index as f32 / ANGLE_RESOLUTION as f32), 1.0)
+	}
+
+	#[test]
+	fn star_outline_of_concave_glyph() {
+		// An L, with the center in the notch between its arms.
+		let l = [point(0.0, 0.0), point(4.0, 0.0), point(4.0, 1.0), point(1.0, 1.0), point(1.0, 4.0), point(0.0, 4.0)];
+		let bounding_box = Box2D::new(point(0.0, 0.0), point(4.0, 4.0));
+		let hull = ConvexHull::from_path(polyline(&l).into_iter(), 0.0, bounding_box).unwrap();
+		assert_eq!(hull.center(), point(2.0, 2.0));
+		let star = sample_star_outline(std::iter::once((polyline(&l).into_iter(), true, 0.0)), hull.center());
+		assert_eq!(star.len(), ANGLE_RESOLUTION);
+		for (i, (&star, &hull)) in star.iter().zip(&hull.outline).enumerate() {
+			assert!(star.length() <= hull.length() + 1e-4, "{}: {:?} is outside of {:?}", i, star, hull);
+			if i <= ANGLE_RESOLUTION / 4 {
+				// Up and to the right there is no ink, although the hull's diagonal side is there.
+				assert_eq!(star, Vector::zero());
+				assert!(hull.length() > 0.7);
+			} else if (ANGLE_RESOLUTION / 2 ..= 3 * ANGLE_RESOLUTION / 4).contains(&i) {
+				// Down and to the left the glyph is convex.
+				assert!((star - hull).length() < 1e-4, "{}: {:?} is not {:?}", i, star, hull);
+			}
+		}
+	}
+
+	#[test]
+	fn star_outline_misses() {
+		let square = [point(5.0, 5.0), point(6.0, 5.0), point(6.0, 6.0), point(5.0, 6.0)];
+		let star = sample_star_outline(std::iter::once((polyline(&square).into_iter(), true, 0.0)), Point::origin());
+		// Only the directions around the diagonal meet the square, at its far side.
+		let far = star[ANGLE_RESOLUTION / 8];
+		assert!((far.x - 6.0).abs() < 1e-4 && (5.0 .. 6.0).contains(&far.y), "{:?}", far);
+		assert_eq!(star[ANGLE_RESOLUTION / 16], Vector::zero());
+		assert!(star[ANGLE_RESOLUTION / 4 ..].iter().all(|&v| v == Vector::zero()));
+		assert!(sample_star_outline(std::iter::empty::<(std::vec::IntoIter<PathEvent>, bool, f32)>(), Point::origin()).iter().all(|&v| v == Vector::zero()));
+
+		let (p, q) = (vector(1.0, -1.0), vector(1.0, 1.0));
+		assert_eq!(ray_capsule_far_distance(Vector::zero(), vector(1.0, 0.0), p, q, 0.0), Some(1.0));
+		assert_eq!(ray_capsule_far_distance(Vector::zero(), vector(-1.0, 0.0), p, q, 0.0), None);
+		assert_eq!(ray_capsule_far_distance(Vector::zero(), vector(0.0, 1.0), p, q, 0.0), None);
+		assert_eq!(ray_capsule_far_distance(Vector::zero(), vector(0.0, 1.0), p, q, 0.5), None);
+	}
+
+	#[test]
+	fn star_outline_of_strokes() {
+		// A stroked segment extends by half the stroke width to the sides and with round caps past its ends.
+		let segment = [point(-1.0, 0.0), point(1.0, 0.0)];
+		let star = sample_star_outline(std::iter::once((polyline(&segment).into_iter(), false, 0.5)), Point::origin());
+		assert!((star[0] - vector(1.25, 0.0)).length() < 1e-4);
+		assert!((star[ANGLE_RESOLUTION / 2] - vector(-1.25, 0.0)).length() < 1e-4);
+		assert!((star[ANGLE_RESOLUTION / 4] - vector(0.0, 0.25)).length() < 1e-4);
+		assert!((star[3 * ANGLE_RESOLUTION / 4] - vector(0.0, -0.25)).length() < 1e-4);
+		// Just off the axis the ray leaves through the cap around (1, 0).
+		let d = direction(1);
+		let projection = d.dot(vector(1.0, 0.0));
+		let expected = projection + (projection * projection - 1.0 + 0.25 * 0.25).sqrt();
+		assert!((star[1].length() - expected).abs() < 1e-4, "{:?} is not {}", star[1], expected);
+		assert!((star[1].normalize() - d).length() < 1e-4);
+
+		// Without a stroke the ray misses a segment it runs along, and nothing is drawn for an unstroked point.
+		let star = sample_star_outline(std::iter::once((polyline(&segment).into_iter(), false, 0.0)), Point::origin());
+		assert_eq!(star[0], Vector::zero());
+		assert!((star[ANGLE_RESOLUTION / 4]).length() < 1e-4);
+		// A stroked point is a disk.
+		let star = sample_star_outline(std::iter::once((polyline(&[point(0.0, 0.0)]).into_iter(), false, 0.5)), Point::origin());
+		assert!(star.iter().all(|v| (v.length() - 0.25).abs() < 1e-4));
+	}
+}
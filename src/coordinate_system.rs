@@ -1,9 +1,14 @@
 use lyon::geom::math::{Point, point, Vector, vector, Transform};
+use euclid::default::Box2D;
 use std::cmp::Ordering;
 
-use wasm_bindgen::prelude::*;
+// How many pixels past the bounds an elastic drag has to go before it only moves the chart at half speed.
+const ELASTIC_LENGTH : f32 = 100.0;
+
 
 use crate::vector::JsPoint;
+use crate::error::ChartError;
+use crate::scene::CoordinateSystemDefinition;
 
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub struct BufferDimensions {
@@ -39,8 +44,47 @@ impl BufferDimensions {
 }
 
 
+/// Something for CoordinateSystem::zoom_to_fit to keep in view.
+#[derive(Clone, Copy, Debug)]
+pub enum FitTarget {
+    /// A point in chart coordinates with an extent around it in pixels, y pointing down.
+    Point { position : Point, extent : Box2D<f32> },
+    /// The middle of a circular arc between two points in chart coordinates, bent by angle radians and
+    /// thickness pixels thick, as drawn by the edge shader.
+    Arc { start : Point, end : Point, angle : f32, thickness : f32 },
+}
+
+impl FitTarget {
+    // The screen space box covered for the given lattice and scale, relative to the origin.
+    fn screen_box(&self, lattice : &Transform, scale : Vector) -> Box2D<f32> {
+        let to_screen = |p : Point| {
+            let p = lattice.transform_point(p);
+            point(p.x * scale.x, -p.y * scale.y)
+        };
+        match *self {
+            FitTarget::Point { position, extent } => extent.translate(to_screen(position).to_vector()),
+            FitTarget::Arc { start, end, angle, thickness } => {
+                let (start, end) = (to_screen(start), to_screen(end));
+                let chord = end - start;
+                if chord.square_length() == 0.0 {
+                    return Box2D::new(start, start);
+                }
+                let normal = vector(-chord.y, chord.x).normalize();
+                let apex = start.lerp(end, 0.5) + normal * (chord.length() / 2.0 * (angle / 2.0).tan());
+                let half_thickness = vector(thickness / 2.0, thickness / 2.0);
+                Box2D::new(apex - half_thickness, apex + half_thickness)
+            }
+        }
+    }
+}
+
+// The coordinate system maps a chart point p to the screen as origin + (scale.x * q.x, -scale.y * q.y) where q = lattice(p).
+// Ranges, zooming and panning all happen in the lattice coordinates q, so with the identity lattice they are chart coordinates.
 #[derive(Clone, Copy, Debug)]
 pub struct CoordinateSystem {
+    // chart coordinates to lattice coordinates, for skewed charts or other grading conventions
+    pub(crate) lattice : Transform,
+    inverse_lattice : Transform,
 	pub(crate) origin : Point,
     pub(crate) scale : Vector,
     pub(crate) glyph_scale : f32,
@@ -55,15 +99,26 @@ pub struct CoordinateSystem {
     pub(crate) buffer_dimensions : BufferDimensions,
 
 
-    natural_scale_ratio : f32, 
+    pub(crate) natural_scale_ratio : f32, 
     max_scale : f32, 
+    // in chart coordinates
     min_xy_boundary : Point,
     max_xy_boundary : Point,
+    // If set, translate and scale_around may leave the viewport past the bounds and a KineticPan has to bring it back.
+    elastic_bounds : bool,
+}
+
+impl Default for CoordinateSystem {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl CoordinateSystem {
     pub fn new() -> Self {
         CoordinateSystem {
+            lattice : Transform::identity(),
+            inverse_lattice : Transform::identity(),
             // user affine coordinate transformation
             origin : point(0.0, 0.0),
             scale : vector(100.0, 100.0),
@@ -82,16 +137,66 @@ impl CoordinateSystem {
             max_scale : 1000.0,
             min_xy_boundary : point(f32::NEG_INFINITY, f32::NEG_INFINITY),
             max_xy_boundary : point(f32::INFINITY, f32::INFINITY),
+            elastic_bounds : false,
             glyph_scale : 1.0,
         }
     }
 
 
+    /// The viewport and margins, everything except the size of the canvas.
+    pub fn definition(&self) -> CoordinateSystemDefinition {
+        CoordinateSystemDefinition {
+            origin : self.origin.to_array(),
+            scale : self.scale.to_array(),
+            glyph_scale : self.glyph_scale,
+            margins : [self.left_margin, self.right_margin, self.bottom_margin, self.top_margin],
+            natural_scale_ratio : self.natural_scale_ratio,
+            max_scale : self.max_scale,
+            min_xy_boundary : [finite(self.min_xy_boundary.x), finite(self.min_xy_boundary.y)],
+            max_xy_boundary : [finite(self.max_xy_boundary.x), finite(self.max_xy_boundary.y)],
+            lattice : self.lattice.to_array(),
+        }
+    }
+
+    pub fn set_definition(&mut self, definition : &CoordinateSystemDefinition) -> Result<(), ChartError> {
+        let [a, c, b, d, tx, ty] = definition.lattice;
+        self.set_lattice(a, b, c, d, tx, ty)?;
+        self.origin = definition.origin.into();
+        self.scale = definition.scale.into();
+        self.glyph_scale = definition.glyph_scale;
+        let [left, right, bottom, top] = definition.margins;
+        self.set_margins(left, right, bottom, top);
+        self.natural_scale_ratio = definition.natural_scale_ratio;
+        self.max_scale = definition.max_scale;
+        let [xmin, ymin] = definition.min_xy_boundary;
+        let [xmax, ymax] = definition.max_xy_boundary;
+        self.min_xy_boundary = point(xmin.unwrap_or(f32::NEG_INFINITY), ymin.unwrap_or(f32::NEG_INFINITY));
+        self.max_xy_boundary = point(xmax.unwrap_or(f32::INFINITY), ymax.unwrap_or(f32::INFINITY));
+        Ok(())
+    }
+
+    /// Set the map from chart to lattice coordinates to (x, y) -> (a x + b y + tx, c x + d y + ty).
+    /// Glyphs stay upright, only their positions and the grid are transformed.
+    pub fn set_lattice(&mut self, a : f32, b : f32, c : f32, d : f32, tx : f32, ty : f32) -> Result<(), ChartError> {
+        let lattice = Transform::new(a, c, b, d, tx, ty);
+        let inverse = lattice.inverse().filter(|inverse| inverse.to_array().iter().all(|x| x.is_finite()))
+            .ok_or_else(|| ChartError::InvalidInput(format!("The lattice transform [[{}, {}], [{}, {}]] is not invertible.", a, b, c, d)))?;
+        self.lattice = lattice;
+        self.inverse_lattice = inverse;
+        Ok(())
+    }
+
+    pub fn glyph_scale(&self) -> f32 {
+        self.glyph_scale
+    }
+
     pub fn transform_point(&self, point : Point) -> Point {
-        let Point {x, y, ..} = point;
+        let Point {x, y, ..} = self.lattice.transform_point(point);
         Point::new(self.transform_x(x), self.transform_y(y))
     }
 
+    // transform_x and transform_y and their inverses work in lattice coordinates.
+
     pub fn transform_x(&self, x : f32) -> f32 {
         self.origin.x + x * self.scale.x
     }
@@ -102,10 +207,10 @@ impl CoordinateSystem {
 
     pub fn inverse_transform_point(&self, point : Point) -> Point {
         let Point {x, y, ..} = point;
-        Point::new(
+        self.inverse_lattice.transform_point(Point::new(
             self.inverse_transform_x(x),
             self.inverse_transform_y(y)
-        )
+        ))
     }
 
     pub fn inverse_transform_x(&self, x : f32) -> f32 {
@@ -153,6 +258,37 @@ impl CoordinateSystem {
         point(self.inverse_transform_x(screen_x_max), self.inverse_transform_y(screen_y_min))
     }
 
+    /// Bounding box in chart coordinates of the visible region.
+    pub fn visible_chart_box(&self) -> Box2D<f32> {
+        let (min, max) = (self.current_min_xy(), self.current_max_xy());
+        let corners = [min, point(min.x, max.y), point(max.x, min.y), max];
+        Box2D::from_points(corners.iter().map(|&corner| self.inverse_lattice.transform_point(corner)))
+    }
+
+    // Bounding box in lattice coordinates of the region that panning is limited to.
+    fn lattice_boundary(&self) -> (Point, Point) {
+        let (min, max) = (self.min_xy_boundary, self.max_xy_boundary);
+        let m = self.lattice;
+        // Each lattice coordinate is a sum of terms coefficient * chart coordinate, each ranging over an interval that
+        // may be infinite. Zero coefficients contribute nothing even when the chart range is infinite.
+        let term = |coefficient : f32, low : f32, high : f32| {
+            if coefficient == 0.0 {
+                (0.0, 0.0)
+            } else {
+                let (a, b) = (coefficient * low, coefficient * high);
+                (a.min(b), a.max(b))
+            }
+        };
+        let axis = |cx : f32, cy : f32, translation : f32| {
+            let (x_low, x_high) = term(cx, min.x, max.x);
+            let (y_low, y_high) = term(cy, min.y, max.y);
+            (x_low + y_low + translation, x_high + y_high + translation)
+        };
+        let (x_low, x_high) = axis(m.m11, m.m21, m.m31);
+        let (y_low, y_high) = axis(m.m12, m.m22, m.m32);
+        (point(x_low, y_low), point(x_high, y_high))
+    }
+
     pub fn set_current_xrange(&mut self, xmin : f32, xmax : f32){
         let (screen_x_min, screen_x_max) = self.screen_x_range();
         self.scale.x = (screen_x_max - screen_x_min) / (xmax - xmin);
@@ -181,18 +317,44 @@ impl CoordinateSystem {
 
     pub fn translate(&mut self, delta : JsPoint) {
         let delta : Vector = delta.into();
-        self.origin += delta;
-        self.enforce_translation_bounds();
+        if !self.elastic_bounds {
+            self.origin += delta;
+            self.enforce_translation_bounds();
+            return;
+        }
+        // Past the bounds, the further out the chart is the less it follows the drag.
+        let overscroll = self.overscroll();
+        let resist = |delta : f32, overscroll : f32| {
+            if delta * overscroll > 0.0 {
+                delta / (1.0 + overscroll.abs() / ELASTIC_LENGTH)
+            } else {
+                delta
+            }
+        };
+        self.origin += vector(resist(delta.x, overscroll.x), resist(delta.y, overscroll.y));
+    }
+
+    pub fn elastic_bounds(&self) -> bool {
+        self.elastic_bounds
+    }
+
+    /// Let panning and zooming out go past the bounds, with resistance. Turning this off snaps back into the bounds.
+    pub fn set_elastic_bounds(&mut self, elastic_bounds : bool) {
+        self.elastic_bounds = elastic_bounds;
+        if !elastic_bounds {
+            self.enforce_scale_out_bounds();
+            self.enforce_translation_bounds();
+        }
     }
 
-    pub fn scale_around(&mut self, scale : f32, center : JsPoint) -> Result<(), JsValue> {
+    pub fn scale_around(&mut self, scale : f32, center : JsPoint) -> Result<(), ChartError> {
         // ensure maximum scale
         let mut scale = f32::min(scale, self.max_scale / f32::max(self.scale.x, self.scale.y));
         // Now if we scale in we have to ensure that we restore the natural aspect ratio before scaling both directions.
         if scale > 1.0 {
             let scale_ratio = self.scale.y / self.scale.x;
             match scale_ratio.partial_cmp(&self.natural_scale_ratio) {
-                None => { return Err("NaN occurred somehow?".into()); },
+                None => { return Err(ChartError::invalid_input("NaN occurred somehow?")); },
                 Some(Ordering::Equal) => {},
                 Some(Ordering::Less) => { // stretched in the y direction
                     // How much would we have to scale by to correct the stretch?
@@ -209,12 +371,76 @@ impl CoordinateSystem {
                 },
             }
         }
+        if !self.elastic_bounds {
+            self.scale_around_raw(scale, center);
+            self.enforce_scale_out_bounds();
+            self.enforce_translation_bounds();
+            return Ok(());
+        }
+        // Zoomed out past the bounds, the further out the chart is the less it follows the zoom, like translate.
+        let overscroll = self.scale_overscroll();
+        let overscroll = overscroll.x.max(overscroll.y);
+        if scale < 1.0 && overscroll > 0.0 {
+            scale = scale.powf(1.0 / (1.0 + overscroll / ELASTIC_LENGTH));
+        }
         self.scale_around_raw(scale, center);
-        self.enforce_scale_out_bounds();
         Ok(())
     }
 
 
+    /// Show all of targets as large as possible with padding pixels to spare inside the margins, at the natural aspect ratio
+    /// and within max_scale. Does nothing if there are no targets.
+    pub fn zoom_to_fit(&mut self, targets : &[FitTarget], padding : f32) {
+        if targets.is_empty() {
+            return;
+        }
+        let (screen_x_min, screen_x_max) = self.screen_x_range();
+        let (screen_y_min, screen_y_max) = self.screen_y_range();
+        let available : Vector = vector(screen_x_max - screen_x_min, screen_y_max - screen_y_min) - vector(2.0 * padding, 2.0 * padding);
+        let lattice = self.lattice;
+        let content_box = |scale : Vector| {
+            let mut boxes = targets.iter().map(|target| target.screen_box(&lattice, scale));
+            let first = boxes.next().unwrap();
+            // Not Box2D::union, which skips the empty boxes of points without extent.
+            boxes.fold(first, |a, b| Box2D::new(a.min.min(b.min), a.max.max(b.max)))
+        };
+        let scale_for = |s : f32| vector(s, s * self.natural_scale_ratio);
+        // If the glyphs alone are too big for the screen, fit their positions instead.
+        let unscaled = content_box(scale_for(0.0)).size();
+        let slack : Vector = vector(
+            if unscaled.width > available.x { unscaled.width } else { 0.0 },
+            if unscaled.height > available.y { unscaled.height } else { 0.0 },
+        );
+        let fits = |s : f32| {
+            let size = content_box(scale_for(s)).size();
+            size.width - slack.x <= available.x && size.height - slack.y <= available.y
+        };
+        // The content grows with the scale, so find the largest scale that fits by bisection.
+        let max_s = self.max_scale / f32::max(1.0, self.natural_scale_ratio);
+        let s = if fits(max_s) {
+            max_s
+        } else {
+            let (mut low, mut high) = (0.0, max_s);
+            for _ in 0 .. 50 {
+                let mid = (low + high) / 2.0;
+                if fits(mid) { low = mid; } else { high = mid; }
+            }
+            low
+        };
+        if s <= 0.0 {
+            return;
+        }
+        self.scale = scale_for(s);
+        // Center the content.
+        let content = content_box(self.scale);
+        self.origin = point(
+            screen_x_min + padding + (available.x - content.width()) / 2.0 - content.min.x,
+            screen_y_min + padding + (available.y - content.height()) / 2.0 - content.min.y,
+        );
+        self.enforce_scale_out_bounds();
+        self.enforce_translation_bounds();
+    }
+
     fn scale_around_raw(&mut self, scale : f32, center : JsPoint){
         let center : Point = center.into();
         self.origin += (center - self.origin) * (1.0 - scale);
@@ -238,32 +464,317 @@ impl CoordinateSystem {
     }
 
 
-    // Ensure that we don't scroll off sides of region
-    fn enforce_translation_bounds(&mut self){
+    /// How many pixels the origin is past the bounds, zero if the viewport is within them.
+    pub(crate) fn overscroll(&self) -> Vector {
         let cur_min = self.current_min_xy();
         let cur_max = self.current_max_xy();
-        let bound_min = self.min_xy_boundary;
-        let bound_max = self.max_xy_boundary;
+        let (bound_min, bound_max) = self.lattice_boundary();
         let max_correction = Vector::min(bound_max - cur_max, vector(0.0, 0.0));
         let min_correction = Vector::max(bound_min - cur_min, vector(0.0, 0.0));
         let mut correction = max_correction + min_correction;
         correction.x *= self.scale.x;
         correction.y *= -self.scale.y;
-        self.origin -= correction;
+        correction
+    }
+
+    // Ensure that we don't scroll off sides of region
+    pub(crate) fn enforce_translation_bounds(&mut self){
+        self.origin -= self.overscroll();
+    }
+
+    /// How many pixels of the screen the bounds leave uncovered when zoomed out past them, zero if they cover it.
+    pub(crate) fn scale_overscroll(&self) -> Vector {
+        let (bound_min, bound_max) = self.lattice_boundary();
+        let max_xy_range = bound_max - bound_min;
+        let (screen_x_min, screen_x_max) = self.screen_x_range();
+        let (screen_y_min, screen_y_max) = self.screen_y_range();
+        vector(
+            (screen_x_max - screen_x_min - max_xy_range.x * self.scale.x).max(0.0),
+            (screen_y_max - screen_y_min - max_xy_range.y * self.scale.y).max(0.0),
+        )
+    }
+
+    /// Zoom each axis that is zoomed out past the bounds around the center of the screen, until scale_overscroll is
+    /// overscroll. Axes within the bounds stay as they are.
+    pub(crate) fn set_scale_overscroll(&mut self, overscroll : Vector) {
+        let current = self.scale_overscroll();
+        let (bound_min, bound_max) = self.lattice_boundary();
+        let max_xy_range = bound_max - bound_min;
+        let (screen_x_min, screen_x_max) = self.screen_x_range();
+        let (screen_y_min, screen_y_max) = self.screen_y_range();
+        let center = JsPoint { x : (screen_x_min + screen_x_max) / 2.0, y : (screen_y_min + screen_y_max) / 2.0 };
+        if current.x > 0.0 {
+            let scale = (screen_x_max - screen_x_min - overscroll.x.max(0.0)) / max_xy_range.x;
+            self.scale_around_x_raw(scale / self.scale.x, center);
+        }
+        if current.y > 0.0 {
+            let scale = (screen_y_max - screen_y_min - overscroll.y.max(0.0)) / max_xy_range.y;
+            self.scale_around_y_raw(scale / self.scale.y, center);
+        }
     }
 
     fn enforce_scale_out_bounds(&mut self){
         // Fix scale before doing translation bounds to prevent thrashing / weird behavior when range is too big.
         let cur_xy_range = self.current_max_xy() - self.current_min_xy();
-        let max_xy_range = self.max_xy_boundary - self.min_xy_boundary;
+        let (bound_min, bound_max) = self.lattice_boundary();
+        let max_xy_range = bound_max - bound_min;
         if cur_xy_range.x > max_xy_range.x {
-            self.set_current_xrange(self.min_xy_boundary.x, self.max_xy_boundary.x);
+            self.set_current_xrange(bound_min.x, bound_max.x);
         }
         if cur_xy_range.y > max_xy_range.y {
-            self.set_current_yrange(self.min_xy_boundary.y, self.max_xy_boundary.y);
+            self.set_current_yrange(bound_min.y, bound_max.y);
         }
-        self.enforce_translation_bounds();
     }
 
 }
 
+fn finite(x : f32) -> Option<f32> {
+    if x.is_finite() { Some(x) } else { None }
+}
+
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // 600 by 400 pixels without margins, showing exactly the max ranges [0, 10] x [0, 10].
+    pub(crate) fn bounded_coordinate_system(elastic_bounds : bool) -> CoordinateSystem {
+        let mut coordinate_system = CoordinateSystem::new();
+        coordinate_system.buffer_dimensions = BufferDimensions::new(600, 400, 1.0);
+        coordinate_system.set_margins(0, 0, 0, 0);
+        coordinate_system.set_max_xrange(0.0, 10.0);
+        coordinate_system.set_max_yrange(0.0, 10.0);
+        coordinate_system.set_current_xrange(0.0, 10.0);
+        coordinate_system.set_current_yrange(0.0, 10.0);
+        coordinate_system.set_elastic_bounds(elastic_bounds);
+        coordinate_system
+    }
+
+    pub(crate) fn assert_within_bounds(coordinate_system : &CoordinateSystem) {
+        let (min, max) = (coordinate_system.current_min_xy(), coordinate_system.current_max_xy());
+        assert!(min.x >= -1e-3 && min.y >= -1e-3 && max.x <= 10.0 + 1e-3 && max.y <= 10.0 + 1e-3, "{:?} {:?}", min, max);
+    }
+
+    #[test]
+    fn scale_out_bounds() {
+        let center = JsPoint { x : 100.0, y : 300.0 };
+        let mut coordinate_system = bounded_coordinate_system(false);
+        coordinate_system.scale_around(0.5, center).unwrap();
+        assert_within_bounds(&coordinate_system);
+        assert_eq!(coordinate_system.scale_overscroll(), Vector::zero());
+        assert_eq!(coordinate_system.overscroll(), Vector::zero());
+    }
+
+    #[test]
+    fn elastic_scale_out_bounds() {
+        let center = JsPoint { x : 100.0, y : 300.0 };
+        let mut coordinate_system = bounded_coordinate_system(true);
+        // Not snapped back, the first step out of the bounds has no resistance yet.
+        coordinate_system.scale_around(0.5, center).unwrap();
+        assert_eq!(coordinate_system.scale, vector(30.0, 20.0));
+        assert_eq!(coordinate_system.scale_overscroll(), vector(300.0, 200.0));
+        // Further out it follows the zoom less.
+        coordinate_system.scale_around(0.5, center).unwrap();
+        let resisted = 0.5f32.powf(1.0 / 4.0);
+        assert!((coordinate_system.scale.x - 30.0 * resisted).abs() < 1e-3, "{:?}", coordinate_system.scale);
+        assert!((coordinate_system.scale.y - 20.0 * resisted).abs() < 1e-3, "{:?}", coordinate_system.scale);
+        // Zooming back in isn't resisted.
+        coordinate_system.scale_around(2.0, center).unwrap();
+        assert!((coordinate_system.scale.x - 60.0 * resisted).abs() < 1e-3, "{:?}", coordinate_system.scale);
+
+        // The bounds come back once elastic bounds are turned off.
+        coordinate_system.set_elastic_bounds(false);
+        assert_within_bounds(&coordinate_system);
+        assert_eq!(coordinate_system.scale_overscroll(), Vector::zero());
+    }
+
+    #[test]
+    fn set_scale_overscroll() {
+        let mut coordinate_system = bounded_coordinate_system(true);
+        coordinate_system.set_current_xrange(2.0, 8.0);
+        coordinate_system.set_current_yrange(-10.0, 20.0);
+        let scale_overscroll = coordinate_system.scale_overscroll();
+        assert!(scale_overscroll.x == 0.0 && (scale_overscroll.y - 400.0 * 2.0 / 3.0).abs() < 1e-3);
+        coordinate_system.set_scale_overscroll(vector(100.0, 100.0));
+        // Only the axis that is zoomed out too far changes, around the center of the screen.
+        assert_eq!(coordinate_system.scale.x, 100.0);
+        assert!((coordinate_system.scale_overscroll().y - 100.0).abs() < 1e-3);
+        assert!((coordinate_system.inverse_transform_y(200.0) - 5.0).abs() < 1e-3);
+        coordinate_system.set_scale_overscroll(Vector::zero());
+        assert!((coordinate_system.scale.y - 40.0).abs() < 1e-3);
+    }
+
+    // 600 by 400 pixels with the default margins, without bounds.
+    fn screen_coordinate_system() -> CoordinateSystem {
+        let mut coordinate_system = CoordinateSystem::new();
+        coordinate_system.buffer_dimensions = BufferDimensions::new(600, 400, 1.0);
+        coordinate_system.set_current_xrange(0.0, 10.0);
+        coordinate_system.set_current_yrange(0.0, 10.0);
+        coordinate_system
+    }
+
+    fn node(x : f32, y : f32, radius : f32) -> FitTarget {
+        FitTarget::Point { position : point(x, y), extent : Box2D::new(point(-radius, -radius), point(radius, radius)) }
+    }
+
+    // Is the screen box inside of the margins with padding pixels to spare, up to rounding?
+    fn assert_within_padding(coordinate_system : &CoordinateSystem, screen_box : Box2D<f32>, padding : f32) {
+        let (screen_x_min, screen_x_max) = coordinate_system.screen_x_range();
+        let (screen_y_min, screen_y_max) = coordinate_system.screen_y_range();
+        assert!(screen_box.min.x >= screen_x_min + padding - 1e-2 && screen_box.max.x <= screen_x_max - padding + 1e-2
+            && screen_box.min.y >= screen_y_min + padding - 1e-2 && screen_box.max.y <= screen_y_max - padding + 1e-2,
+            "{:?} is not within the padding", screen_box
+        );
+    }
+
+    #[test]
+    fn zoom_to_fit() {
+        let mut coordinate_system = screen_coordinate_system();
+        let targets = [node(0.0, 0.0, 5.0), node(10.0, 5.0, 8.0), node(3.0, 8.0, 5.0), node(4.0, 2.0, 20.0)];
+        coordinate_system.zoom_to_fit(&targets, 10.0);
+        let (screen_x_min, screen_x_max) = coordinate_system.screen_x_range();
+        let boxes : Vec<Box2D<f32>> = targets.iter().map(|target| match *target {
+            FitTarget::Point { position, extent } => extent.translate(coordinate_system.transform_point(position).to_vector()),
+            _ => unreachable!(),
+        }).collect();
+        for &screen_box in &boxes {
+            assert_within_padding(&coordinate_system, screen_box, 10.0);
+        }
+        // As large as possible: the content is wider than it is high, so it fills the width.
+        let content = boxes.iter().fold(boxes[0], |a, b| Box2D::new(a.min.min(b.min), a.max.max(b.max)));
+        assert!((content.width() - (screen_x_max - screen_x_min - 20.0)).abs() < 1e-2, "{:?}", content);
+        assert!((coordinate_system.scale.y / coordinate_system.scale.x - coordinate_system.natural_scale_ratio).abs() < 1e-6);
+    }
+
+    #[test]
+    fn zoom_to_fit_keeps_aspect_ratio() {
+        let mut coordinate_system = screen_coordinate_system();
+        coordinate_system.set_current_yrange(0.0, 20.0);
+        let natural_scale_ratio = coordinate_system.natural_scale_ratio;
+        assert!((natural_scale_ratio - 380.0 / 540.0 / 2.0).abs() < 1e-5);
+        coordinate_system.zoom_to_fit(&[node(1.0, 1.0, 0.0), node(2.0, 10.0, 0.0)], 0.0);
+        assert_eq!(coordinate_system.natural_scale_ratio, natural_scale_ratio);
+        assert!((coordinate_system.scale.y / coordinate_system.scale.x - natural_scale_ratio).abs() < 1e-5);
+        // Tall content fills the height.
+        let (screen_y_min, screen_y_max) = coordinate_system.screen_y_range();
+        assert!((coordinate_system.transform_point(point(2.0, 10.0)).y - screen_y_min).abs() < 1e-2);
+        assert!((coordinate_system.transform_point(point(1.0, 1.0)).y - screen_y_max).abs() < 1e-2);
+    }
+
+    #[test]
+    fn zoom_to_fit_max_scale() {
+        // A single node can be scaled up without bounds, so it stops at max_scale, in the middle of the screen.
+        let mut coordinate_system = screen_coordinate_system();
+        coordinate_system.zoom_to_fit(&[node(3.0, 4.0, 10.0)], 10.0);
+        assert_eq!(coordinate_system.scale, vector(1000.0, 1000.0 * coordinate_system.natural_scale_ratio));
+        let (screen_x_min, screen_x_max) = coordinate_system.screen_x_range();
+        let (screen_y_min, screen_y_max) = coordinate_system.screen_y_range();
+        let center = point(screen_x_min + screen_x_max, screen_y_min + screen_y_max) / 2.0;
+        assert!((coordinate_system.transform_point(point(3.0, 4.0)) - center).length() < 1e-2);
+    }
+
+    #[test]
+    fn zoom_to_fit_arc() {
+        // Only the bend of the edge keeps the scale from going up to the width of the screen.
+        let mut coordinate_system = screen_coordinate_system();
+        coordinate_system.buffer_dimensions = BufferDimensions::new(600, 200, 1.0);
+        coordinate_system.set_margins(0, 0, 0, 0);
+        let arc = FitTarget::Arc { start : point(0.0, 0.0), end : point(10.0, 0.0), angle : std::f32::consts::FRAC_PI_2, thickness : 0.0 };
+        coordinate_system.zoom_to_fit(&[node(0.0, 0.0, 0.0), node(10.0, 0.0, 0.0), arc], 10.0);
+        // A right angle bend reaches half the length of the chord away from it, so the chord is twice the height.
+        assert!((coordinate_system.scale.x - 36.0).abs() < 1e-2, "{:?}", coordinate_system.scale);
+        let (start, end) = (coordinate_system.transform_point(point(0.0, 0.0)), coordinate_system.transform_point(point(10.0, 0.0)));
+        let apex = start.lerp(end, 0.5) + vector(0.0, (end.x - start.x) / 2.0);
+        assert!((start.y - 10.0).abs() < 1e-2 && (apex.y - 190.0).abs() < 1e-2, "{:?} {:?}", start, apex);
+        assert_within_padding(&coordinate_system, Box2D::from_points([start, end, apex]), 10.0);
+    }
+
+    #[test]
+    fn zoom_to_fit_nothing() {
+        let mut coordinate_system = screen_coordinate_system();
+        let (origin, scale) = (coordinate_system.origin, coordinate_system.scale);
+        coordinate_system.zoom_to_fit(&[], 10.0);
+        assert_eq!((coordinate_system.origin, coordinate_system.scale), (origin, scale));
+        // Padding that leaves no room.
+        coordinate_system.zoom_to_fit(&[node(0.0, 0.0, 1.0), node(1.0, 1.0, 1.0)], 1000.0);
+        assert_eq!((coordinate_system.origin, coordinate_system.scale), (origin, scale));
+        // Everything at one spot.
+        coordinate_system.zoom_to_fit(&[node(2.0, 2.0, 0.0), node(2.0, 2.0, 0.0)], 10.0);
+        assert!(coordinate_system.origin.to_vector().is_finite() && coordinate_system.scale.x == 1000.0);
+    }
+
+    // (x, y) -> (x + y / 2 + 2, y - 1)
+    fn skewed_coordinate_system() -> CoordinateSystem {
+        let mut coordinate_system = screen_coordinate_system();
+        coordinate_system.set_lattice(1.0, 0.5, 0.0, 1.0, 2.0, -1.0).unwrap();
+        coordinate_system
+    }
+
+    #[test]
+    fn lattice_round_trip() {
+        let coordinate_system = skewed_coordinate_system();
+        for &p in &[point(0.0, 0.0), point(3.0, 4.0), point(-2.5, 7.0), point(10.0, -3.0)] {
+            let screen = coordinate_system.transform_point(p);
+            let expected = point(coordinate_system.transform_x(p.x + p.y / 2.0 + 2.0), coordinate_system.transform_y(p.y - 1.0));
+            assert!((screen - expected).length() < 1e-3, "{:?} is not {:?}", screen, expected);
+            assert!((coordinate_system.inverse_transform_point(screen) - p).length() < 1e-4);
+        }
+        let mut definition_copy = CoordinateSystem::new();
+        definition_copy.set_definition(&coordinate_system.definition()).unwrap();
+        assert_eq!(definition_copy.lattice, coordinate_system.lattice);
+    }
+
+    #[test]
+    fn singular_lattice() {
+        let mut coordinate_system = skewed_coordinate_system();
+        let lattice = coordinate_system.lattice;
+        assert!(matches!(coordinate_system.set_lattice(1.0, 2.0, 2.0, 4.0, 0.0, 0.0), Err(ChartError::InvalidInput(_))));
+        assert!(matches!(coordinate_system.set_lattice(0.0, 0.0, 0.0, 0.0, 1.0, 1.0), Err(ChartError::InvalidInput(_))));
+        assert!(matches!(coordinate_system.set_lattice(f32::NAN, 0.0, 0.0, 1.0, 0.0, 0.0), Err(ChartError::InvalidInput(_))));
+        assert_eq!(coordinate_system.lattice, lattice);
+    }
+
+    #[test]
+    fn skewed_bounds() {
+        let mut coordinate_system = skewed_coordinate_system();
+        coordinate_system.set_max_xrange(0.0, 10.0);
+        coordinate_system.set_max_yrange(0.0, 10.0);
+        assert_eq!(coordinate_system.lattice_boundary(), (point(2.0, -1.0), point(17.0, 9.0)));
+        let assert_within_lattice_bounds = |coordinate_system : &CoordinateSystem| {
+            let (min, max) = (coordinate_system.current_min_xy(), coordinate_system.current_max_xy());
+            assert!(min.x >= 2.0 - 1e-3 && min.y >= -1.0 - 1e-3 && max.x <= 17.0 + 1e-3 && max.y <= 9.0 + 1e-3, "{:?} {:?}", min, max);
+        };
+        for &delta in &[vector(1e4, 0.0), vector(-1e4, 1e4), vector(0.0, -1e4)] {
+            coordinate_system.translate(delta.into());
+            assert_within_lattice_bounds(&coordinate_system);
+        }
+        coordinate_system.scale_around(0.01, JsPoint { x : 300.0, y : 200.0 }).unwrap();
+        assert_within_lattice_bounds(&coordinate_system);
+        let range = coordinate_system.current_max_xy() - coordinate_system.current_min_xy();
+        assert!((range.x - 15.0).abs() < 1e-3 && (range.y - 10.0).abs() < 1e-3, "{:?}", range);
+
+        // A negative coefficient swaps the ends of the range.
+        coordinate_system.set_lattice(1.0, -0.5, 0.0, 1.0, 0.0, 0.0).unwrap();
+        assert_eq!(coordinate_system.lattice_boundary(), (point(-5.0, 0.0), point(10.0, 10.0)));
+    }
+
+    #[test]
+    fn visible_chart_box() {
+        let mut coordinate_system = skewed_coordinate_system();
+        coordinate_system.set_lattice(1.0, 0.5, -0.25, 1.0, 2.0, -1.0).unwrap();
+        let visible = coordinate_system.visible_chart_box();
+        let (screen_x_min, screen_x_max) = coordinate_system.screen_x_range();
+        let (screen_y_min, screen_y_max) = coordinate_system.screen_y_range();
+        let corners = [
+            point(screen_x_min, screen_y_min), point(screen_x_max, screen_y_min),
+            point(screen_x_min, screen_y_max), point(screen_x_max, screen_y_max),
+        ];
+        let chart_corners : Vec<Point> = corners.iter().map(|&corner| coordinate_system.inverse_transform_point(corner)).collect();
+        for p in &chart_corners {
+            assert!(visible.inflate(1e-3, 1e-3).contains(*p), "{:?} is not in {:?}", p, visible);
+        }
+        // And no larger than it has to be.
+        let tight = Box2D::from_points(chart_corners.iter().copied());
+        assert!((tight.min - visible.min).length() < 1e-3 && (tight.max - visible.max).length() < 1e-3, "{:?} {:?}", tight, visible);
+    }
+}
@@ -1,7 +1,97 @@
+use std::convert::TryFrom;
+use std::fmt;
+#[cfg(feature = "wasm")]
 use wasm_bindgen::JsValue;
 
 use lyon::tessellation::TessellationError;
 
-pub fn convert_tessellation_error(err : TessellationError) -> JsValue {
-    JsValue::from_str(&format!("{:?}", err))
+/// Everything that can go wrong in the chart. This is converted to a javascript Error at the wasm boundary, with
+/// a "kind" field holding the name of the variant so that callers can react to specific failures.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChartError {
+    ShaderCompile(String),
+    ProgramLink(String),
+    Tessellation(String),
+    /// More glyphs, arrows, vertices, etc than fit in the shader data.
+    CapacityOverflow(String),
+    ContextLost,
+    InvalidInput(String),
+    /// Failed to encode an exported image.
+    Encoding(String),
+    /// An exception thrown by a browser api.
+    Js(String),
+}
+
+impl ChartError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ChartError::ShaderCompile(_) => "ShaderCompile",
+            ChartError::ProgramLink(_) => "ProgramLink",
+            ChartError::Tessellation(_) => "Tessellation",
+            ChartError::CapacityOverflow(_) => "CapacityOverflow",
+            ChartError::ContextLost => "ContextLost",
+            ChartError::InvalidInput(_) => "InvalidInput",
+            ChartError::Encoding(_) => "Encoding",
+            ChartError::Js(_) => "Js",
+        }
+    }
+
+    pub fn invalid_input<S : Into<String>>(message : S) -> Self {
+        ChartError::InvalidInput(message.into())
+    }
+}
+
+impl fmt::Display for ChartError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChartError::ShaderCompile(log) => write!(f, "Failed to compile shader: {}", log),
+            ChartError::ProgramLink(log) => write!(f, "Failed to link shader program: {}", log),
+            ChartError::Tessellation(message) => write!(f, "Tessellation failed: {}", message),
+            ChartError::CapacityOverflow(message) => write!(f, "{}", message),
+            ChartError::ContextLost => write!(f, "The WebGL context was lost."),
+            ChartError::InvalidInput(message) => write!(f, "{}", message),
+            ChartError::Encoding(message) => write!(f, "Failed to encode image: {}", message),
+            ChartError::Js(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for ChartError {}
+
+impl From<TessellationError> for ChartError {
+    fn from(err : TessellationError) -> Self {
+        ChartError::Tessellation(format!("{:?}", err))
+    }
+}
+
+impl From<png::EncodingError> for ChartError {
+    fn from(err : png::EncodingError) -> Self {
+        ChartError::Encoding(err.to_string())
+    }
+}
+
+#[cfg(feature = "wasm")]
+impl From<JsValue> for ChartError {
+    fn from(value : JsValue) -> Self {
+        let message = value.as_string()
+            .or_else(|| js_sys::Reflect::get(&value, &"message".into()).ok().and_then(|m| m.as_string()))
+            .unwrap_or_else(|| format!("{:?}", value));
+        ChartError::Js(message)
+    }
+}
+
+#[cfg(feature = "wasm")]
+impl From<ChartError> for JsValue {
+    fn from(err : ChartError) -> Self {
+        let js_error = js_sys::Error::new(&err.to_string());
+        js_error.set_name("ChartError");
+        // Setting a property on a fresh Error object can't fail.
+        let _ = js_sys::Reflect::set(&js_error, &"kind".into(), &err.kind().into());
+        js_error.into()
+    }
+}
+
+// Indices are passed to the shaders as 32 bit integers, make sure they fit rather than letting them wrap around.
+pub fn checked_index<T : TryFrom<usize>>(index : usize, what : &str) -> Result<T, ChartError> {
+    T::try_from(index).map_err(|_| ChartError::CapacityOverflow(format!("Too many {}: index {} is out of range for the shaders.", what, index)))
 }
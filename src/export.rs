@@ -0,0 +1,254 @@
+use lyon::geom::math::{point, vector, Transform};
+use euclid::default::Box2D;
+
+#[allow(unused_imports)]
+use crate::log;
+use crate::error::ChartError;
+use crate::coordinate_system::{CoordinateSystem, BufferDimensions};
+
+/// Largest width or height in pixels of an exported image.
+pub const MAX_EXPORT_SIZE : u32 = 32768;
+/// Largest number of pixels of an exported image, 1 GiB of RGBA.
+pub const MAX_EXPORT_PIXELS : u64 = 1 << 28;
+// Css pixels per inch, for the resolution recorded in the png.
+const CSS_PIXELS_PER_INCH : f64 = 96.0;
+const METERS_PER_INCH : f64 = 0.0254;
+
+/// A rectangle of an exported image rendered in one pass, in pixels from the top left.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tile {
+    pub x : u32,
+    pub y : u32,
+    pub width : u32,
+    pub height : u32,
+}
+
+/// Cover a width by height image with tiles at most max_size pixels on a side, row by row from the top left.
+pub fn tiles(width : u32, height : u32, max_size : u32) -> Vec<Tile> {
+    let max_size = max_size.max(1);
+    let mut result = Vec::new();
+    for y in (0 .. height).step_by(max_size as usize) {
+        for x in (0 .. width).step_by(max_size as usize) {
+            result.push(Tile { x, y, width : max_size.min(width - x), height : max_size.min(height - y) });
+        }
+    }
+    result
+}
+
+/// A coordinate system showing the range (in lattice coordinates) on a whole width by height pixel image, with density
+/// pixels per css pixel. Glyphs, edges and grid lines keep their size in css pixels, so they grow with density.
+pub fn image_coordinate_system(main : &CoordinateSystem, width : u32, height : u32, density : f64, range : Box2D<f32>) -> Result<CoordinateSystem, ChartError> {
+    if width == 0 || height == 0 || width > MAX_EXPORT_SIZE || height > MAX_EXPORT_SIZE {
+        return Err(ChartError::InvalidInput(format!("Invalid image size {}x{}, each side must be between 1 and {}.", width, height, MAX_EXPORT_SIZE)));
+    }
+    if width as u64 * height as u64 > MAX_EXPORT_PIXELS {
+        return Err(ChartError::CapacityOverflow(format!("Image size {}x{} is more than {} pixels.", width, height, MAX_EXPORT_PIXELS)));
+    }
+    if !(density > 0.0 && density.is_finite()) {
+        return Err(ChartError::InvalidInput(format!("Invalid pixel density {}.", density)));
+    }
+    let Box2D { min, max } = range;
+    if !(min.x < max.x && min.y < max.y && min.to_array().iter().chain(&max.to_array()).all(|v| v.is_finite())) {
+        return Err(ChartError::InvalidInput(format!("Invalid range x : [{}, {}], y : [{}, {}].", min.x, max.x, min.y, max.y)));
+    }
+    let mut result = *main;
+    result.left_margin = 0;
+    result.right_margin = 0;
+    result.top_margin = 0;
+    result.bottom_margin = 0;
+    set_buffer_size(&mut result, width, height, density);
+    let css_width = (width as f64 / density) as f32;
+    let css_height = (height as f64 / density) as f32;
+    result.scale = vector(css_width / (max.x - min.x), css_height / (max.y - min.y));
+    result.origin = point(-min.x * result.scale.x, max.y * result.scale.y);
+    result.natural_scale_ratio = result.scale.y / result.scale.x;
+    Ok(result)
+}
+
+/// The part of the image coordinate system that lands on the tile, for drawing the tile into a viewport of its size.
+pub fn tile_coordinate_system(image : &CoordinateSystem, tile : Tile) -> CoordinateSystem {
+    let density = image.buffer_dimensions.density();
+    let mut result = *image;
+    set_buffer_size(&mut result, tile.width, tile.height, density);
+    result.origin -= vector(tile.x as f32, tile.y as f32) / density as f32;
+    result
+}
+
+// The buffer dimensions are whole css pixels, round them up so that the visible range covers the last pixels. The
+// transform maps the exact size to clip space.
+fn set_buffer_size(coordinate_system : &mut CoordinateSystem, width : u32, height : u32, density : f64) {
+    let css_width = width as f64 / density;
+    let css_height = height as f64 / density;
+    coordinate_system.buffer_dimensions = BufferDimensions::new(css_width.ceil() as i32, css_height.ceil() as i32, density);
+    coordinate_system.transform = Transform::scale(2.0 / css_width as f32, -2.0 / css_height as f32)
+        .then_translate(vector(-1.0, 1.0));
+}
+
+/// An RGBA image assembled from tiles.
+pub struct Image {
+    width : u32,
+    height : u32,
+    // Rows from the top, 4 bytes per pixel.
+    data : Vec<u8>,
+}
+
+impl Image {
+    pub fn new(width : u32, height : u32) -> Self {
+        Self { width, height, data : vec![0; width as usize * height as usize * 4] }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Copy pixels read back from the tile, which have rows from the bottom as WebGL reads them.
+    pub fn copy_tile(&mut self, tile : Tile, pixels : &[u8]) -> Result<(), ChartError> {
+        if tile.x + tile.width > self.width || tile.y + tile.height > self.height {
+            return Err(ChartError::InvalidInput(format!("Tile {:?} is outside of the {}x{} image.", tile, self.width, self.height)));
+        }
+        let row_length = tile.width as usize * 4;
+        if pixels.len() != row_length * tile.height as usize {
+            return Err(ChartError::InvalidInput(format!("Expected {} bytes for tile {:?}, got {}.", row_length * tile.height as usize, tile, pixels.len())));
+        }
+        for (i, row) in pixels.chunks_exact(row_length).rev().enumerate() {
+            let start = ((tile.y as usize + i) * self.width as usize + tile.x as usize) * 4;
+            self.data[start .. start + row_length].copy_from_slice(row);
+        }
+        Ok(())
+    }
+
+    /// Encode as png, recording density pixels per css pixel as the resolution.
+    pub fn encode_png(&self, density : f64) -> Result<Vec<u8>, ChartError> {
+        let mut result = Vec::new();
+        let mut encoder = png::Encoder::new(&mut result, self.width, self.height);
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        let pixels_per_meter = (density * CSS_PIXELS_PER_INCH / METERS_PER_INCH).round() as u32;
+        let mut phys = [0; 9];
+        phys[0 .. 4].copy_from_slice(&pixels_per_meter.to_be_bytes());
+        phys[4 .. 8].copy_from_slice(&pixels_per_meter.to_be_bytes());
+        phys[8] = 1; // The unit is meters.
+        writer.write_chunk(*b"pHYs", &phys)?;
+        writer.write_image_data(&self.data)?;
+        drop(writer);
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tile_coverage() {
+        for &(width, height, max_size) in &[(1000, 700, 256), (256, 512, 256), (1, 1, 256), (300, 5, 7)] {
+            let tiles = tiles(width, height, max_size);
+            let mut covered = vec![0; width as usize * height as usize];
+            for tile in &tiles {
+                assert!(tile.width >= 1 && tile.width <= max_size && tile.height >= 1 && tile.height <= max_size, "{:?}", tile);
+                assert!(tile.x + tile.width <= width && tile.y + tile.height <= height, "{:?}", tile);
+                for y in tile.y .. tile.y + tile.height {
+                    for x in tile.x .. tile.x + tile.width {
+                        covered[(y * width + x) as usize] += 1;
+                    }
+                }
+            }
+            assert!(covered.iter().all(|&count| count == 1), "{}x{} in tiles of {}", width, height, max_size);
+        }
+        let tiles = tiles(1000, 700, 256);
+        assert_eq!(tiles.len(), 4 * 3);
+        assert_eq!(tiles[3], Tile { x : 768, y : 0, width : 232, height : 256 });
+        assert_eq!(tiles[11], Tile { x : 768, y : 512, width : 232, height : 188 });
+    }
+
+    #[test]
+    fn tile_coordinate_systems() {
+        let range = Box2D::new(point(-2.0, 0.0), point(8.0, 5.0));
+        let density = 300.0 / 96.0;
+        let image = image_coordinate_system(&CoordinateSystem::new(), 1000, 500, density, range).unwrap();
+        let to_pixel = |coordinate_system : &CoordinateSystem, p| coordinate_system.transform_point(p) * density as f32;
+        assert!((to_pixel(&image, point(-2.0, 5.0)) - point(0.0, 0.0)).length() < 1e-3);
+        assert!((to_pixel(&image, point(8.0, 0.0)) - point(1000.0, 500.0)).length() < 1e-3);
+        for tile in tiles(1000, 500, 256) {
+            let tile_coordinate_system = tile_coordinate_system(&image, tile);
+            let p = point(3.0, 1.5);
+            let expected = to_pixel(&image, p) - vector(tile.x as f32, tile.y as f32);
+            assert!((to_pixel(&tile_coordinate_system, p) - expected).length() < 1e-2, "{:?}", tile);
+        }
+    }
+
+    #[test]
+    fn invalid_image_size() {
+        let range = Box2D::new(point(0.0, 0.0), point(1.0, 1.0));
+        let main = CoordinateSystem::new();
+        assert!(matches!(image_coordinate_system(&main, 0, 10, 1.0, range), Err(ChartError::InvalidInput(_))));
+        assert!(matches!(image_coordinate_system(&main, 10, MAX_EXPORT_SIZE + 1, 1.0, range), Err(ChartError::InvalidInput(_))));
+        assert!(matches!(image_coordinate_system(&main, 10, 10, 0.0, range), Err(ChartError::InvalidInput(_))));
+        assert!(matches!(image_coordinate_system(&main, 10, 10, 1.0, Box2D::new(point(1.0, 0.0), point(0.0, 1.0))), Err(ChartError::InvalidInput(_))));
+        // Within the size on each side but too many pixels in total.
+        assert!(matches!(image_coordinate_system(&main, MAX_EXPORT_SIZE, MAX_EXPORT_SIZE, 1.0, range), Err(ChartError::CapacityOverflow(_))));
+        assert!(image_coordinate_system(&main, MAX_EXPORT_SIZE, (MAX_EXPORT_PIXELS / MAX_EXPORT_SIZE as u64) as u32, 1.0, range).is_ok());
+    }
+
+    #[test]
+    fn copy_tile() {
+        let mut image = Image::new(5, 4);
+        let tile = Tile { x : 2, y : 1, width : 3, height : 2 };
+        // The bottom row of the tile comes first.
+        let pixels : Vec<u8> = (0 .. 2).flat_map(|row| vec![10 + row; 3 * 4]).collect();
+        image.copy_tile(tile, &pixels).unwrap();
+        let pixel = |x : usize, y : usize| image.data()[(y * 5 + x) * 4];
+        for y in 0 .. 4 {
+            for x in 0 .. 5 {
+                let expected = match (x, y) {
+                    (2 ..= 4, 1) => 11,
+                    (2 ..= 4, 2) => 10,
+                    _ => 0,
+                };
+                assert_eq!(pixel(x, y), expected, "({}, {})", x, y);
+            }
+        }
+        assert!(matches!(image.copy_tile(Tile { x : 3, ..tile }, &pixels), Err(ChartError::InvalidInput(_))));
+        assert!(matches!(image.copy_tile(tile, &pixels[4 ..]), Err(ChartError::InvalidInput(_))));
+    }
+
+    fn decode_png(bytes : &[u8]) -> (png::OutputInfo, Option<png::PixelDimensions>, Vec<u8>) {
+        let (info, mut reader) = png::Decoder::new(bytes).read_info().unwrap();
+        let mut data = vec![0; info.buffer_size()];
+        reader.next_frame(&mut data).unwrap();
+        let pixel_dims = reader.info().pixel_dims;
+        (info, pixel_dims, data)
+    }
+
+    #[test]
+    fn png_round_trip() {
+        let mut image = Image::new(3, 2);
+        let pixels : Vec<u8> = (0 .. 3 * 2 * 4).collect();
+        image.copy_tile(Tile { x : 0, y : 0, width : 3, height : 2 }, &pixels).unwrap();
+        let (info, pixel_dims, data) = decode_png(&image.encode_png(1.0).unwrap());
+        assert_eq!((info.width, info.height), (3, 2));
+        assert_eq!(info.color_type, png::ColorType::RGBA);
+        assert_eq!(info.bit_depth, png::BitDepth::Eight);
+        assert_eq!(data, image.data());
+        let pixel_dims = pixel_dims.unwrap();
+        assert_eq!((pixel_dims.xppu, pixel_dims.yppu), (3780, 3780));
+    }
+
+    #[test]
+    fn png_resolution() {
+        // 300 dpi.
+        let (_, pixel_dims, _) = decode_png(&Image::new(1, 1).encode_png(300.0 / 96.0).unwrap());
+        let pixel_dims = pixel_dims.unwrap();
+        assert_eq!((pixel_dims.xppu, pixel_dims.yppu), (11811, 11811));
+        assert_eq!(pixel_dims.unit, png::Unit::Meter);
+    }
+}
@@ -1,29 +1,35 @@
 #[allow(unused_imports)]
 use crate::log; 
-use crate::error::convert_tessellation_error;
+use crate::error::ChartError;
 
 use lazy_static::lazy_static;
 use arrayvec::ArrayVec;
 
 use std::rc::Rc;
 use uuid::Uuid;
+use serde::{Serialize, Deserialize};
 
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 use euclid::default::Box2D;
 use footile::{Pt, PathOp, Path2D};
 use fonterator::{self as font, Font}; // For parsing font file.
 use lyon::geom::math::{point, Point, vector, Vector, Angle, Transform};
 use lyon::path::{Path, PathEvent, iterator::PathIterator};
+use lyon::algorithms::aabb::bounding_rect;
 use lyon::tessellation::{
-    geometry_builder,
-    StrokeTessellator, StrokeOptions,
-    FillTessellator, FillOptions, VertexBuffers
+    geometry_builder::BuffersBuilder,
+    StrokeTessellator, StrokeOptions, StrokeAttributes,
+    FillTessellator, FillOptions, FillAttributes, VertexBuffers,
+    LineJoin as LyonLineJoin, LineCap as LyonLineCap
 };
 
 
 use crate::vector::{Vec4};
 
-use crate::convex_hull::ConvexHull;
+use crate::convex_hull::{ConvexHull, sample_star_outline};
+use crate::svg_path::{parse_svg_path_events, write_svg_path};
+use crate::scene::{GlyphDefinition, GlyphComponentDefinition};
 
 const FONT_SIZE: f32 = 32.0;
 
@@ -135,40 +141,258 @@ fn lyon_path_to_footile_path<T : Iterator<Item=PathEvent>>(path : T) -> Vec<Path
     }).collect()
 }
 
+/// Number of colors that each glyph instance carries. Components of a glyph refer to these by index.
+pub const NUM_COLOR_SLOTS : usize = 4;
+/// Page range (first, last) of nodes and edges that are drawn on every page.
+pub const ALL_PAGES : [i32; 2] = [0, i32::MAX];
+
+const STROKE_COLOR_SLOT : u8 = 0;
+const FILL_COLOR_SLOT : u8 = 1;
+
+/// Where a component of a glyph gets its color from.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum ComponentColor {
+    /// One of the NUM_COLOR_SLOTS colors of the glyph instance.
+    Slot(u8),
+    /// A color that is the same for every instance of the glyph.
+    Fixed(Vec4),
+}
+
+/// A tessellated glyph vertex together with the color of the component it came from.
+#[derive(Clone, Copy, Debug)]
+pub struct GlyphVertex {
+    pub position : Point,
+    pub color : ComponentColor,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum LineJoin {
+    Miter,
+    MiterClip,
+    Round,
+    Bevel,
+}
+
+impl From<LineJoin> for LyonLineJoin {
+    fn from(join : LineJoin) -> Self {
+        match join {
+            LineJoin::Miter => LyonLineJoin::Miter,
+            LineJoin::MiterClip => LyonLineJoin::MiterClip,
+            LineJoin::Round => LyonLineJoin::Round,
+            LineJoin::Bevel => LyonLineJoin::Bevel,
+        }
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum LineCap {
+    Butt,
+    Square,
+    Round,
+}
+
+impl From<LyonLineJoin> for LineJoin {
+    fn from(join : LyonLineJoin) -> Self {
+        match join {
+            LyonLineJoin::Miter => LineJoin::Miter,
+            LyonLineJoin::MiterClip => LineJoin::MiterClip,
+            LyonLineJoin::Round => LineJoin::Round,
+            LyonLineJoin::Bevel => LineJoin::Bevel,
+        }
+    }
+}
+
+impl From<LineCap> for LyonLineCap {
+    fn from(cap : LineCap) -> Self {
+        match cap {
+            LineCap::Butt => LyonLineCap::Butt,
+            LineCap::Square => LyonLineCap::Square,
+            LineCap::Round => LyonLineCap::Round,
+        }
+    }
+}
+
+impl From<LyonLineCap> for LineCap {
+    fn from(cap : LyonLineCap) -> Self {
+        match cap {
+            LyonLineCap::Butt => LineCap::Butt,
+            LyonLineCap::Square => LineCap::Square,
+            LyonLineCap::Round => LineCap::Round,
+        }
+    }
+}
+
+/// What edges are clipped against at their ends.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GlyphBoundary {
+    /// The convex hull of the glyph (or of its decoration).
+    ConvexHull = 0,
+    /// The outermost ink of the glyph in the direction of the edge, which follows concave parts of the glyph.
+    StarOutline = 1,
+}
+
+/// How a glyph component is stroked and filled.
+/// The defaults are the ones used for decorations: a stroke of width 4 and no fill.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct GlyphComponentOptions {
+    stroke_width : f32,
+    line_join : LineJoin,
+    line_cap : LineCap,
+    fill : bool,
+    tolerance : f32,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl GlyphComponentOptions {
+    pub fn new() -> Self {
+        Self {
+            stroke_width : 4.0,
+            line_join : LineJoin::Miter,
+            line_cap : LineCap::Butt,
+            fill : false,
+            tolerance : 0.2,
+        }
+    }
+
+    /// A stroke width of 0 means the component is not stroked.
+    pub fn set_stroke_width(&mut self, stroke_width : f32) {
+        self.stroke_width = stroke_width;
+    }
+
+    pub fn set_line_join(&mut self, line_join : LineJoin) {
+        self.line_join = line_join;
+    }
+
+    pub fn set_line_cap(&mut self, line_cap : LineCap) {
+        self.line_cap = line_cap;
+    }
+
+    pub fn set_fill(&mut self, fill : bool) {
+        self.fill = fill;
+    }
+
+    /// Maximum distance between a curve and its approximation by line segments.
+    pub fn set_tolerance(&mut self, tolerance : f32) {
+        self.tolerance = tolerance;
+    }
+}
+
+impl Default for GlyphComponentOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GlyphComponentOptions {
+    fn stix_default() -> Self {
+        Self {
+            stroke_width : 2.0,
+            fill : true,
+            ..Self::new()
+        }
+    }
+
+    fn stroke_options(&self) -> Option<StrokeOptions> {
+        if self.stroke_width <= 0.0 {
+            return None;
+        }
+        Some(StrokeOptions::default()
+            .with_line_width(self.stroke_width)
+            .with_line_join(self.line_join.into())
+            .with_line_cap(self.line_cap.into())
+            .with_tolerance(self.tolerance)
+        )
+    }
+
+    fn fill_options(&self) -> Option<FillOptions> {
+        if self.fill { Some(FillOptions::default().with_tolerance(self.tolerance)) } else { None }
+    }
+}
+
 struct GlyphComponent {
     path : Vec<PathEvent>,
+    options : GlyphComponentOptions,
     stroke : Option<StrokeOptions>, 
     fill : Option<FillOptions>,
+    stroke_color : ComponentColor,
+    fill_color : ComponentColor,
+}
+
+impl GlyphComponent {
+    fn new(path : Vec<PathEvent>, options : &GlyphComponentOptions) -> Self {
+        Self {
+            path,
+            options : *options,
+            stroke : options.stroke_options(),
+            fill : options.fill_options(),
+            stroke_color : ComponentColor::Slot(STROKE_COLOR_SLOT),
+            fill_color : ComponentColor::Slot(FILL_COLOR_SLOT),
+        }
+    }
 }
 
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub struct GlyphBuilder {
     paths : Vec<GlyphComponent>,
     bounding_box : Box2D<f32>,
-    convex_hull_path_idx : usize
+    convex_hull_path_idx : usize,
+    raster_convex_hull : bool,
 }
 
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 impl GlyphBuilder {
     pub fn from_stix(character : &str) -> Self {
+        Self::from_stix_with_options(character, &GlyphComponentOptions::stix_default())
+    }
+
+    pub fn from_stix_with_options(character : &str, options : &GlyphComponentOptions) -> Self {
         let path : Vec<_> = STIX_FONT.render(
             character,
             (512.0 - 64.0) / FONT_SIZE,
             font::TextAlign::Center
         ).0.collect();
         let bounding_box = pathop_bounding_box(path.iter());
-        let component = GlyphComponent {
-            path : footile_path_to_lyon_path(path.iter().copied()),
-            stroke : Some(StrokeOptions::default().with_line_width(2.0).with_tolerance(0.2)),
-            fill : Some(FillOptions::default().with_tolerance(0.2)),
-        };
+        let component = GlyphComponent::new(
+            footile_path_to_lyon_path(path.iter().copied()),
+            options,
+        );
         Self {
             paths : vec![component],
             bounding_box,
             convex_hull_path_idx : 0,
+            raster_convex_hull : false,
+        }
+    }
+
+    /// Build a glyph from the "d" attribute of an svg path element (for instance one exported by Inkscape).
+    /// The path is stroked with the given width (no stroke if stroke_width is 0) and optionally filled.
+    pub fn from_svg_path(d : &str, stroke_width : f32, fill : bool) -> Result<GlyphBuilder, ChartError> {
+        let mut options = GlyphComponentOptions::new();
+        options.set_stroke_width(stroke_width);
+        options.set_fill(fill);
+        Self::from_svg_path_with_options(d, &options)
+    }
+
+    pub fn from_svg_path_with_options(d : &str, options : &GlyphComponentOptions) -> Result<GlyphBuilder, ChartError> {
+        let path = parse_svg_path_events(d).map_err(ChartError::InvalidInput)?;
+        if path.is_empty() {
+            return Err(ChartError::invalid_input("Svg path data is empty."));
         }
+        let half_stroke_width = options.stroke_width.max(0.0) / 2.0;
+        let bounding_box = bounding_rect(path.iter().copied()).to_box2d().inflate(half_stroke_width, half_stroke_width);
+        let component = GlyphComponent::new(path, options);
+        Ok(Self {
+            paths : vec![component],
+            bounding_box,
+            convex_hull_path_idx : 0,
+            raster_convex_hull : false,
+        })
     }
 
     pub fn empty() -> Self {
@@ -176,10 +400,15 @@ impl GlyphBuilder {
             paths : vec![],
             bounding_box : Box2D::new(point(0.0, 0.0), point(0.0, 0.0)),
             convex_hull_path_idx : 0,
+            raster_convex_hull : false,
         }
     }
 
     pub fn boxed(&mut self, padding : f32) {
+        self.boxed_with_options(padding, &GlyphComponentOptions::new());
+    }
+
+    pub fn boxed_with_options(&mut self, padding : f32, options : &GlyphComponentOptions) {
         let padding = padding / 100.0;
         self.bounding_box = self.bounding_box.inflate(padding, padding);
         let Point { x : xmin, y : ymin, ..} = self.bounding_box.min;
@@ -190,115 +419,605 @@ impl GlyphBuilder {
             .line_to(xmax, ymax)
             .line_to(xmin, ymax)
             .close().finish();
-        let component = GlyphComponent {
-            path : footile_path_to_lyon_path(box_path.iter().copied()),
-            stroke : Some(StrokeOptions::default().with_line_width(4.0).with_tolerance(0.2)),
-            fill : None,
-        };        
-        self.convex_hull_path_idx = self.paths.len();
-        self.paths.push(component);
+        self.push_decoration(footile_path_to_lyon_path(box_path.iter().copied()), options);
+    }
+
+    /// Box with corners rounded off to the given radius (clamped to half the shorter side of the box).
+    pub fn rounded_boxed(&mut self, padding : f32, corner_radius : f32, options : &GlyphComponentOptions) {
+        let padding = padding / 100.0;
+        self.bounding_box = self.bounding_box.inflate(padding, padding);
+        let Point { x : xmin, y : ymin, ..} = self.bounding_box.min;
+        let Point { x : xmax, y : ymax, ..} = self.bounding_box.max;
+        let size = self.bounding_box.size();
+        let r = corner_radius.max(0.0).min(size.width / 2.0).min(size.height / 2.0);
+        let radii = vector(r, r);
+        let quarter_turn = Angle::frac_pi_2();
+        let mut box_path = Path::builder();
+        box_path.move_to(point(xmin + r, ymin));
+        box_path.line_to(point(xmax - r, ymin));
+        box_path.arc(point(xmax - r, ymin + r), radii, quarter_turn, Angle::zero());
+        box_path.line_to(point(xmax, ymax - r));
+        box_path.arc(point(xmax - r, ymax - r), radii, quarter_turn, Angle::zero());
+        box_path.line_to(point(xmin + r, ymax));
+        box_path.arc(point(xmin + r, ymax - r), radii, quarter_turn, Angle::zero());
+        box_path.line_to(point(xmin, ymin + r));
+        box_path.arc(point(xmin + r, ymin + r), radii, quarter_turn, Angle::zero());
+        box_path.close();
+        self.push_decoration(box_path.build().iter().collect(), options);
     }
 
     pub fn circled(&mut self, padding : f32) {
+        self.circled_with_options(padding, &GlyphComponentOptions::new());
+    }
+
+    pub fn circled_with_options(&mut self, padding : f32, options : &GlyphComponentOptions) {
         let padding = padding / 100.0;
         let bounding_box = self.bounding_box.inflate(padding, padding);
         let radius = bounding_box.min.distance_to(bounding_box.max)/2.0;
         let center = bounding_box.min.lerp(bounding_box.max, 0.5);
-        self.bounding_box = Box2D::new(center - vector(radius, radius), center + vector(radius, radius));
-        let mut circle_path = Path::builder();
-        circle_path.move_to(center - vector(radius, 0.0));
-        circle_path.arc(center, vector(radius, radius), Angle::two_pi(), Angle::zero());
-        circle_path.close();
-        let circle_path : Vec<_> = circle_path.build().iter().collect();
-        let component = GlyphComponent {
-            path : circle_path,
-            stroke : Some(StrokeOptions::default().with_line_width(4.0).with_tolerance(0.2)),
-            fill : None,
-        };            
-        self.convex_hull_path_idx = self.paths.len();
-        self.paths.push(component);
+        self.push_ellipse(center, vector(radius, radius), options);
+    }
+
+    /// Two concentric circles, the outer one gap further out than the inner one.
+    pub fn double_circled(&mut self, padding : f32, gap : f32, options : &GlyphComponentOptions) {
+        self.circled_with_options(padding, options);
+        let center = self.center();
+        let radius = (self.bounding_box.max.x - center.x) + gap / 100.0;
+        self.push_ellipse(center, vector(radius, radius), options);
+    }
+
+    /// Ellipse with the same aspect ratio as the (padded) bounding box, passing through its corners.
+    pub fn ellipsed(&mut self, padding : f32, options : &GlyphComponentOptions) {
+        let padding = padding / 100.0;
+        let bounding_box = self.bounding_box.inflate(padding, padding);
+        let center = bounding_box.min.lerp(bounding_box.max, 0.5);
+        let radii = (bounding_box.max - center) * std::f32::consts::SQRT_2;
+        self.push_ellipse(center, radii, options);
     }
 
-    pub fn build(self) -> Glyph {
-        let GlyphBuilder { paths, bounding_box, convex_hull_path_idx } = self;
-        let convex_hull = Rc::new(ConvexHull::from_path(lyon_path_to_footile_path(paths[convex_hull_path_idx].path.iter().copied()), bounding_box));
+    /// Smallest diamond (rhombus with horizontal and vertical diagonals) containing the (padded) bounding box.
+    pub fn diamond(&mut self, padding : f32, options : &GlyphComponentOptions) {
+        let padding = padding / 100.0;
+        let bounding_box = self.bounding_box.inflate(padding, padding);
+        let center = bounding_box.min.lerp(bounding_box.max, 0.5);
+        let half_diagonals = (bounding_box.max - center) * 2.0;
+        self.bounding_box = Box2D::new(center - half_diagonals, center + half_diagonals);
+        let mut diamond_path = Path::builder();
+        diamond_path.move_to(center - vector(half_diagonals.x, 0.0));
+        diamond_path.line_to(center - vector(0.0, half_diagonals.y));
+        diamond_path.line_to(center + vector(half_diagonals.x, 0.0));
+        diamond_path.line_to(center + vector(0.0, half_diagonals.y));
+        diamond_path.close();
+        self.push_decoration(diamond_path.build().iter().collect(), options);
+    }
+
+    /// Draw the stroke of a component in a fixed color rather than the stroke color of the glyph instance.
+    pub fn set_stroke_color(&mut self, component : usize, color : &Vec4) -> Result<(), ChartError> {
+        self.component_mut(component)?.stroke_color = ComponentColor::Fixed(*color);
+        Ok(())
+    }
+
+    /// Draw the fill of a component in a fixed color rather than the fill color of the glyph instance.
+    pub fn set_fill_color(&mut self, component : usize, color : &Vec4) -> Result<(), ChartError> {
+        self.component_mut(component)?.fill_color = ComponentColor::Fixed(*color);
+        Ok(())
+    }
+
+    /// Draw the stroke of a component with one of the colors of the glyph instance.
+    /// Slot 0 is the stroke color and slot 1 is the fill color.
+    pub fn set_stroke_color_slot(&mut self, component : usize, slot : u8) -> Result<(), ChartError> {
+        let slot = check_color_slot(slot)?;
+        self.component_mut(component)?.stroke_color = slot;
+        Ok(())
+    }
+
+    /// Draw the fill of a component with one of the colors of the glyph instance.
+    /// Slot 0 is the stroke color and slot 1 is the fill color.
+    pub fn set_fill_color_slot(&mut self, component : usize, slot : u8) -> Result<(), ChartError> {
+        let slot = check_color_slot(slot)?;
+        self.component_mut(component)?.fill_color = slot;
+        Ok(())
+    }
+
+    pub fn num_components(&self) -> usize {
+        self.paths.len()
+    }
+
+    /// Choose which component's outline is used to compute the convex hull that edges are clipped against.
+    /// By default this is the outermost decoration (box or circle) if there is one, otherwise the first component.
+    pub fn set_convex_hull_component(&mut self, index : usize) -> Result<(), ChartError> {
+        if index >= self.paths.len() {
+            return Err(ChartError::invalid_input(format!(
+                "Convex hull component index {} out of range: glyph has {} components.", index, self.paths.len()
+            )));
+        }
+        self.convex_hull_path_idx = index;
+        Ok(())
+    }
+
+    /// Add all of the components of other to this glyph. The convex hull component of this glyph is unchanged.
+    pub fn append(&mut self, other : GlyphBuilder) {
+        if self.paths.is_empty() {
+            *self = other;
+            return;
+        }
+        self.bounding_box = self.bounding_box.union(&other.bounding_box);
+        self.paths.extend(other.paths);
+    }
+
+    /// Append other to the right of this glyph, vertically centered, with a gap of spacing between their bounding boxes.
+    pub fn place_beside(&mut self, mut other : GlyphBuilder, spacing : f32) {
+        let dx = self.bounding_box.max.x + spacing - other.bounding_box.min.x;
+        let dy = self.center().y - other.center().y;
+        other.transform(&Transform::translation(dx, dy));
+        self.append(other);
+    }
+
+    /// Append other below this glyph, horizontally centered, with a gap of spacing between their bounding boxes.
+    pub fn place_below(&mut self, mut other : GlyphBuilder, spacing : f32) {
+        // Glyph coordinates are screen coordinates so y increases downwards.
+        let dx = self.center().x - other.center().x;
+        let dy = self.bounding_box.max.y + spacing - other.bounding_box.min.y;
+        other.transform(&Transform::translation(dx, dy));
+        self.append(other);
+    }
+
+    pub fn translate(&mut self, dx : f32, dy : f32) {
+        self.transform(&Transform::translation(dx, dy));
+    }
+
+    /// Rotate the glyph around the center of its bounding box. Positive angles rotate clockwise on screen.
+    pub fn rotate(&mut self, degrees : f32) {
+        let center = self.center().to_vector();
+        self.transform(&Transform::translation(-center.x, -center.y)
+            .then_rotate(Angle::degrees(degrees))
+            .then_translate(center)
+        );
+        // Rotating the old bounding box would overestimate the new one, so recompute it from the paths.
+        self.bounding_box = self.paths_bounding_box();
+    }
+
+    /// Scale the glyph around the center of its bounding box. Stroke widths are not scaled.
+    pub fn scale(&mut self, sx : f32, sy : f32) {
+        let center = self.center().to_vector();
+        self.transform(&Transform::translation(-center.x, -center.y)
+            .then_scale(sx, sy)
+            .then_translate(center)
+        );
+        // The bounding box includes half of the stroke width, which doesn't scale, so recompute it from the paths.
+        self.bounding_box = self.paths_bounding_box();
+    }
+
+    /// Compute the convex hull by rasterizing the glyph instead of from the path geometry.
+    /// This is slower and less precise, it is only useful for comparison.
+    pub fn set_raster_convex_hull(&mut self, raster_convex_hull : bool) {
+        self.raster_convex_hull = raster_convex_hull;
+    }
+
+    pub fn build(self) -> Result<Glyph, ChartError> {
+        let GlyphBuilder { paths, bounding_box, convex_hull_path_idx, raster_convex_hull } = self;
+        let convex_hull = match paths.get(convex_hull_path_idx) {
+            None => ConvexHull::empty(bounding_box),
+            Some(hull_component) if raster_convex_hull => {
+                if bounding_box.is_empty() {
+                    return Err(ChartError::invalid_input("Raster convex hull needs a glyph with nonzero width and height."));
+                }
+                ConvexHull::from_path_raster(lyon_path_to_footile_path(hull_component.path.iter().copied()), bounding_box)
+            }
+            Some(hull_component) => {
+                let stroke_width = hull_component.stroke.map_or(0.0, |options| options.line_width);
+                ConvexHull::from_path(hull_component.path.iter().copied(), stroke_width, bounding_box).map_err(ChartError::InvalidInput)?
+            }
+        };
+        let star_outline = sample_star_outline(
+            paths.iter().filter(|component| component.fill.is_some() || component.stroke.is_some()).map(|component| (
+                component.path.iter().copied(),
+                component.fill.is_some(),
+                component.stroke.map_or(0.0, |options| options.line_width)
+            )),
+            convex_hull.center()
+        );
+        let convex_hull = Rc::new(convex_hull);
+        let star_outline = Rc::new(star_outline);
         let paths = Rc::new(paths);
-        Glyph { 
+        Ok(Glyph { 
             paths,
             convex_hull,
+            star_outline,
+            bounding_box,
+            convex_hull_path_idx,
+            raster_convex_hull,
             uuid : GlyphUuid(Uuid::new_v4())
+        })
+    }
+}
+
+fn check_component_color(color : ComponentColor) -> Result<ComponentColor, ChartError> {
+    match color {
+        ComponentColor::Slot(slot) => check_color_slot(slot),
+        ComponentColor::Fixed(_) => Ok(color),
+    }
+}
+
+fn check_color_slot(slot : u8) -> Result<ComponentColor, ChartError> {
+    if slot as usize >= NUM_COLOR_SLOTS {
+        return Err(ChartError::invalid_input(format!("Color slot {} out of range: there are {} color slots.", slot, NUM_COLOR_SLOTS)));
+    }
+    Ok(ComponentColor::Slot(slot))
+}
+
+impl GlyphBuilder {
+    fn component_mut(&mut self, index : usize) -> Result<&mut GlyphComponent, ChartError> {
+        let num_components = self.paths.len();
+        self.paths.get_mut(index).ok_or_else(||
+            ChartError::invalid_input(format!("Component index {} out of range: glyph has {} components.", index, num_components))
+        )
+    }
+
+    fn push_ellipse(&mut self, center : Point, radii : Vector, options : &GlyphComponentOptions) {
+        self.bounding_box = Box2D::new(center - radii, center + radii);
+        let mut ellipse_path = Path::builder();
+        ellipse_path.move_to(center - vector(radii.x, 0.0));
+        ellipse_path.arc(center, radii, Angle::two_pi(), Angle::zero());
+        ellipse_path.close();
+        self.push_decoration(ellipse_path.build().iter().collect(), options);
+    }
+
+    /// Decorations surround everything else, so they become the convex hull component.
+    fn push_decoration(&mut self, path : Vec<PathEvent>, options : &GlyphComponentOptions) {
+        self.convex_hull_path_idx = self.paths.len();
+        self.paths.push(GlyphComponent::new(path, options));
+    }
+
+    fn center(&self) -> Point {
+        self.bounding_box.min.lerp(self.bounding_box.max, 0.5)
+    }
+
+    fn transform(&mut self, transform : &Transform) {
+        for component in &mut self.paths {
+            component.path = component.path.iter().map(|event| event.transformed(transform)).collect();
+        }
+        // Correct for translations and scalings, which are the only transforms that preserve axis-aligned boxes.
+        self.bounding_box = Box2D::from_points([
+            transform.transform_point(self.bounding_box.min),
+            transform.transform_point(self.bounding_box.max),
+        ]);
+    }
+
+    fn paths_bounding_box(&self) -> Box2D<f32> {
+        let mut result : Option<Box2D<f32>> = None;
+        for GlyphComponent { path, stroke, .. } in &self.paths {
+            let half_line_width = stroke.map_or(0.0, |options| options.line_width / 2.0);
+            let component_box = bounding_rect(path.iter().copied()).to_box2d().inflate(half_line_width, half_line_width);
+            result = Some(result.map_or(component_box, |b| b.union(&component_box)));
         }
+        result.unwrap_or(self.bounding_box)
     }
 }
 
 #[derive(Copy, Clone, Debug, PartialOrd, Ord, PartialEq, Eq)]
 pub struct GlyphUuid(Uuid);
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 #[derive(Clone)]
 pub struct Glyph {
     paths : Rc<Vec<GlyphComponent>>,
     convex_hull : Rc<ConvexHull>,
+    star_outline : Rc<Vec<Vector>>,
+    // Kept so that the glyph can be saved and rebuilt, see definition.
+    bounding_box : Box2D<f32>,
+    convex_hull_path_idx : usize,
+    raster_convex_hull : bool,
     pub(crate) uuid : GlyphUuid
 }
 
 impl Glyph { 
-    pub(crate) fn tessellate_fill(&self,
-        buffers : &mut VertexBuffers<Point, u16>,
+    pub fn tessellate_fill(&self,
+        buffers : &mut VertexBuffers<GlyphVertex, u16>,
         scale : f32
-    ) -> Result<(), JsValue> {
-        let mut vertex_builder = geometry_builder::simple_builder(buffers);
+    ) -> Result<(), ChartError> {
         let mut fill_tessellator = FillTessellator::new();
         let transform = Transform::identity().then_translate(- self.convex_hull.center().to_vector()).then_scale(scale, scale);
-        for &GlyphComponent { ref path, stroke : _stroke,  fill } in self.paths.iter() {
+        for &GlyphComponent { ref path, fill, fill_color, .. } in self.paths.iter() {
             if let Some(options) = fill {
+                let mut vertex_builder = BuffersBuilder::new(buffers, 
+                    |position : Point, _ : FillAttributes| GlyphVertex { position, color : fill_color }
+                );
                 let path = path.iter().copied().transformed(&transform);
-                fill_tessellator.tessellate(path, &options, &mut vertex_builder).map_err(convert_tessellation_error)?;
+                fill_tessellator.tessellate(path, &options, &mut vertex_builder)?;
             }
         }        
         Ok(())
     }
 
-    pub(crate) fn tessellate_stroke(&self,
-        buffers : &mut VertexBuffers<Point, u16>,
+    pub fn tessellate_stroke(&self,
+        buffers : &mut VertexBuffers<GlyphVertex, u16>,
         scale : f32
-    ) -> Result<(), JsValue> {
-        let mut vertex_builder = geometry_builder::simple_builder(buffers);
+    ) -> Result<(), ChartError> {
         let mut stroke_tessellator = StrokeTessellator::new();
         let transform = Transform::identity().then_translate(- self.convex_hull.center().to_vector()).then_scale(scale, scale);
-        for &GlyphComponent { ref path, stroke, fill : _fill } in &*self.paths {
+        for &GlyphComponent { ref path, stroke, stroke_color, .. } in &*self.paths {
             if let Some(options) = stroke {
+                let mut vertex_builder = BuffersBuilder::new(buffers, 
+                    |position : Point, _ : StrokeAttributes| GlyphVertex { position, color : stroke_color }
+                );
                 let path = path.iter().copied().transformed(&transform);
-                stroke_tessellator.tessellate(path, &options, &mut vertex_builder).map_err(convert_tessellation_error)?;
+                stroke_tessellator.tessellate(path, &options, &mut vertex_builder)?;
             }
         }
         Ok(())
     }
 
-    pub(crate) fn boundary(&self) -> &Vec<Vector> {
+    pub fn boundary(&self) -> &Vec<Vector> {
         &self.convex_hull.outline
     }
+
+    /// Distance to the outermost ink in each direction, see sample_star_outline.
+    pub fn star_outline(&self) -> &Vec<Vector> {
+        &self.star_outline
+    }
+
+    pub fn definition(&self) -> GlyphDefinition {
+        GlyphDefinition {
+            components : self.paths.iter().map(|component| GlyphComponentDefinition {
+                path : write_svg_path(component.path.iter().copied()),
+                options : component.options,
+                stroke_color : component.stroke_color,
+                fill_color : component.fill_color,
+            }).collect(),
+            bounding_box : [self.bounding_box.min.x, self.bounding_box.min.y, self.bounding_box.max.x, self.bounding_box.max.y],
+            convex_hull_component : self.convex_hull_path_idx,
+            raster_convex_hull : self.raster_convex_hull,
+        }
+    }
+
+    /// Rebuild a glyph saved with definition. The result is a new glyph, distinct from the one that was saved.
+    pub fn from_definition(definition : &GlyphDefinition) -> Result<Glyph, ChartError> {
+        // A glyph without components has no convex hull component either, it is left at 0.
+        if definition.convex_hull_component >= definition.components.len().max(1) {
+            return Err(ChartError::invalid_input(format!(
+                "Convex hull component index {} out of range: glyph has {} components.", definition.convex_hull_component, definition.components.len()
+            )));
+        }
+        let mut paths = Vec::with_capacity(definition.components.len());
+        for component in &definition.components {
+            let path = parse_svg_path_events(&component.path).map_err(ChartError::InvalidInput)?;
+            let mut glyph_component = GlyphComponent::new(path, &component.options);
+            glyph_component.stroke_color = check_component_color(component.stroke_color)?;
+            glyph_component.fill_color = check_component_color(component.fill_color)?;
+            paths.push(glyph_component);
+        }
+        let [xmin, ymin, xmax, ymax] = definition.bounding_box;
+        let builder = GlyphBuilder {
+            paths,
+            bounding_box : Box2D::new(point(xmin, ymin), point(xmax, ymax)),
+            convex_hull_path_idx : definition.convex_hull_component,
+            raster_convex_hull : definition.raster_convex_hull,
+        };
+        builder.build()
+    }
 }
 
 
+#[cfg(feature = "webgl")]
 #[derive(Clone)]
 pub struct GlyphInstance {
     pub(crate) glyph : Glyph,
     pub(crate) center : Point,
     pub(crate) scale : f32,
-    pub(crate) stroke_color : Vec4,
-    pub(crate) fill_color : Vec4,
+    pub(crate) colors : [Vec4; NUM_COLOR_SLOTS],
+    /// (first, last) page the instance is drawn on, see Canvas::set_page.
+    pub(crate) page_range : [i32; 2],
+    /// Index of the layer the instance is drawn in, see Canvas::add_layer.
+    pub(crate) layer : u32,
+    /// Name read out by screen readers when the instance has keyboard focus, see Canvas::focus_description.
+    pub(crate) label : Option<String>,
 }
 
 
+#[cfg(feature = "webgl")]
 impl GlyphInstance {
+    /// The extra color slots repeat the stroke and fill colors.
     pub fn new(glyph : Glyph, center : Point, scale : f32, stroke_color : Vec4, fill_color : Vec4) -> Self {
+        Self::with_colors(glyph, center, scale, [stroke_color, fill_color, stroke_color, fill_color])
+    }
+
+    pub fn with_colors(glyph : Glyph, center : Point, scale : f32, colors : [Vec4; NUM_COLOR_SLOTS]) -> Self {
         Self {
             glyph,
             center,
             scale,
-            stroke_color,
-            fill_color,
+            colors,
+            page_range : ALL_PAGES,
+            layer : 0,
+            label : None,
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_box_eq(actual : Box2D<f32>, expected : Box2D<f32>) {
+        let close = |a : Point, b : Point| (a - b).length() < 1e-4;
+        assert!(close(actual.min, expected.min) && close(actual.max, expected.max), "{:?} != {:?}", actual, expected);
+    }
+
+    #[test]
+    fn scale_keeps_stroke_padding() {
+        let mut builder = GlyphBuilder::from_svg_path("M 0 0 L 10 0 L 10 10 Z", 2.0, false).unwrap();
+        assert_box_eq(builder.bounding_box, Box2D::new(point(-1.0, -1.0), point(11.0, 11.0)));
+        builder.scale(3.0, 2.0);
+        // The path scales around (5, 5), the padding stays half the stroke width.
+        assert_box_eq(builder.bounding_box, Box2D::new(point(-11.0, -6.0), point(21.0, 16.0)));
+    }
+
+    #[test]
+    fn decoration_around_scaled_glyph() {
+        let mut scaled = GlyphBuilder::from_svg_path("M 0 0 L 10 10", 2.0, false).unwrap();
+        scaled.scale(2.0, 2.0);
+        scaled.boxed(0.0);
+        let mut expected = GlyphBuilder::from_svg_path("M -5 -5 L 15 15", 2.0, false).unwrap();
+        expected.boxed(0.0);
+        assert_box_eq(scaled.bounding_box, expected.bounding_box);
+    }
+
+    #[test]
+    fn definition_convex_hull_component() {
+        let mut builder = letter();
+        builder.boxed(2.0);
+        let mut definition = builder.build().unwrap().definition();
+        assert_eq!(definition.convex_hull_component, 1);
+        assert!(Glyph::from_definition(&definition).is_ok());
+        definition.convex_hull_component = 2;
+        assert!(matches!(Glyph::from_definition(&definition), Err(ChartError::InvalidInput(_))));
+        let empty = GlyphBuilder::empty().build().unwrap().definition();
+        assert!(Glyph::from_definition(&empty).is_ok());
+    }
+
+    // A filled and stroked letter-like shape, standing in for stix text.
+    fn letter() -> GlyphBuilder {
+        GlyphBuilder::from_svg_path_with_options(
+            "M 0 30 L 12 0 L 18 0 L 30 30 L 24 30 L 15 8 L 6 30 Z",
+            &GlyphComponentOptions::stix_default()
+        ).unwrap()
+    }
+
+    type StockGlyph = (&'static str, fn() -> GlyphBuilder);
+
+    fn stock_glyphs() -> Vec<StockGlyph> {
+        let mut result : Vec<StockGlyph> = vec![
+            ("letter", letter),
+            ("boxed", || { let mut builder = letter(); builder.boxed(15.0); builder }),
+            ("circled", || { let mut builder = letter(); builder.circled(10.0); builder }),
+            ("diamond", || { let mut builder = letter(); builder.diamond(10.0, &GlyphComponentOptions::new()); builder }),
+        ];
+        // Source snapshots may come without the font.
+        if !include_bytes!("../fonts/STIX2Math.otf").is_empty() {
+            result.push(("stix text", || GlyphBuilder::from_stix("A")));
+            result.push(("boxed stix text", || { let mut builder = GlyphBuilder::from_stix("x"); builder.boxed(15.0); builder }));
+        }
+        result
+    }
+
+    // The raster hull only sees the fill of the path, so compare it to the analytic hull without the stroke.
+    #[test]
+    fn analytic_hull_matches_raster_hull() {
+        for (name, make) in stock_glyphs() {
+            let builder = make();
+            let component = &builder.paths[builder.convex_hull_path_idx];
+            let bounding_box = builder.bounding_box;
+            let analytic = ConvexHull::from_path(component.path.iter().copied(), 0.0, bounding_box).unwrap();
+            let raster = ConvexHull::from_path_raster(lyon_path_to_footile_path(component.path.iter().copied()), bounding_box);
+            assert_eq!(analytic.center(), raster.center());
+            // from_path_raster draws the smaller side of the bounding box 100 pixels across.
+            let size = bounding_box.size();
+            let pixel = size.width.min(size.height) / 100.0;
+            let worst = analytic.outline.iter().zip(&raster.outline).map(|(a, r)| (*a - *r).length()).fold(0.0, f32::max);
+            // Scanning the raster cuts off up to three pixels at sharp corners.
+            assert!(worst <= 3.0 * pixel, "{}: hulls differ by {}, the pixel size is {}", name, worst, pixel);
+        }
+    }
+
+    #[test]
+    fn off_center_convex_hull_component() {
+        let mut builder = letter();
+        builder.place_beside(GlyphBuilder::from_svg_path("M 0 0 L 10 0 L 10 10 L 0 10 Z", 1.0, true).unwrap(), 20.0);
+        builder.set_convex_hull_component(1).unwrap();
+        let glyph = builder.build().unwrap();
+        // The square is at x from 51.5 to 61.5, y from 10 to 20, the middle of the bounding box is far to its left.
+        let center = glyph.convex_hull.center();
+        assert!((51.5 .. 61.5).contains(&center.x) && (10.0 .. 20.0).contains(&center.y), "{:?}", center);
+        assert!(glyph.boundary().iter().all(|v| v.length() >= 5.0 && v.length() < 10.0));
+    }
+
+    fn decoration_options(stroke_width : f32) -> GlyphComponentOptions {
+        let mut options = GlyphComponentOptions::new();
+        options.set_stroke_width(stroke_width);
+        options
+    }
+
+    // The path of the last component, and everything with the strokes.
+    fn decoration_boxes(builder : &GlyphBuilder) -> (Box2D<f32>, Box2D<f32>) {
+        let decoration = builder.paths.last().unwrap();
+        (bounding_rect(decoration.path.iter().copied()).to_box2d(), builder.paths_bounding_box())
+    }
+
+    #[test]
+    fn rounded_boxed() {
+        let inner = letter().bounding_box;
+        let padded = inner.inflate(2.0, 2.0);
+        for &corner_radius in &[5.0, 1000.0] {
+            let mut builder = letter();
+            builder.rounded_boxed(200.0, corner_radius, &decoration_options(3.0));
+            assert_eq!(builder.num_components(), 2);
+            assert_eq!(builder.convex_hull_path_idx, 1);
+            let (path_box, ink_box) = decoration_boxes(&builder);
+            assert_box_eq(builder.bounding_box, padded);
+            assert_box_eq(path_box, padded);
+            assert_box_eq(ink_box, padded.inflate(1.5, 1.5));
+            // The corners are cut off by at least (sqrt 2 - 1) r, where r is at most half the shorter side.
+            let r = corner_radius.min(padded.width() / 2.0).min(padded.height() / 2.0);
+            let corner = point(padded.max.x, padded.min.y);
+            let nearest = builder.paths[1].path.iter().copied().flattened(0.01)
+                .map(|event| event.from().distance_to(corner)).fold(f32::INFINITY, f32::min);
+            assert!(nearest >= r * (std::f32::consts::SQRT_2 - 1.0) - 0.02, "{}: {}", r, nearest);
+        }
+    }
+
+    #[test]
+    fn ellipsed() {
+        let padded = letter().bounding_box.inflate(1.0, 1.0);
+        let mut builder = letter();
+        builder.ellipsed(100.0, &decoration_options(2.0));
+        let (path_box, ink_box) = decoration_boxes(&builder);
+        let center = padded.center();
+        let radii = (padded.max - center) * std::f32::consts::SQRT_2;
+        assert_box_eq(path_box, Box2D::new(center - radii, center + radii));
+        assert_box_eq(builder.bounding_box, path_box);
+        assert_box_eq(ink_box, path_box.inflate(1.0, 1.0));
+        // Through the corners of the padded box.
+        for &corner in &[padded.min, padded.max, point(padded.min.x, padded.max.y), point(padded.max.x, padded.min.y)] {
+            let v = corner - center;
+            assert!(((v.x / radii.x).powi(2) + (v.y / radii.y).powi(2) - 1.0).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn double_circled() {
+        let padded = letter().bounding_box.inflate(1.0, 1.0);
+        let mut builder = letter();
+        builder.double_circled(100.0, 300.0, &decoration_options(2.0));
+        assert_eq!(builder.num_components(), 3);
+        // The outer circle is the convex hull component.
+        assert_eq!(builder.convex_hull_path_idx, 2);
+        let center = padded.center();
+        let inner_radius = padded.min.distance_to(padded.max) / 2.0;
+        let circle_box = |radius : f32| Box2D::new(center - vector(radius, radius), center + vector(radius, radius));
+        assert_box_eq(bounding_rect(builder.paths[1].path.iter().copied()).to_box2d(), circle_box(inner_radius));
+        let (path_box, ink_box) = decoration_boxes(&builder);
+        assert_box_eq(path_box, circle_box(inner_radius + 3.0));
+        assert_box_eq(builder.bounding_box, path_box);
+        assert_box_eq(ink_box, circle_box(inner_radius + 4.0));
+    }
+
+    #[test]
+    fn color_slots() {
+        let mut builder = letter();
+        builder.boxed(2.0);
+        let last = NUM_COLOR_SLOTS as u8 - 1;
+        builder.set_stroke_color_slot(1, last).unwrap();
+        builder.set_fill_color_slot(0, last).unwrap();
+        assert!(matches!(builder.paths[1].stroke_color, ComponentColor::Slot(slot) if slot == last));
+        assert!(matches!(builder.paths[0].fill_color, ComponentColor::Slot(slot) if slot == last));
+        for &slot in &[NUM_COLOR_SLOTS as u8, u8::MAX] {
+            assert!(matches!(builder.set_stroke_color_slot(0, slot), Err(ChartError::InvalidInput(_))));
+            assert!(matches!(builder.set_fill_color_slot(0, slot), Err(ChartError::InvalidInput(_))));
+        }
+        // Unchanged by the errors.
+        assert!(matches!(builder.paths[0].stroke_color, ComponentColor::Slot(STROKE_COLOR_SLOT)));
+        assert!(matches!(builder.paths[0].fill_color, ComponentColor::Slot(slot) if slot == last));
+        assert!(matches!(builder.set_stroke_color_slot(2, 0), Err(ChartError::InvalidInput(_))));
+        assert!(matches!(builder.set_fill_color(2, &Vec4::new(0.0, 0.0, 0.0, 1.0)), Err(ChartError::InvalidInput(_))));
+    }
+}
@@ -0,0 +1,642 @@
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use lyon::geom::math::{Point, Vector};
+use euclid::default::Box2D;
+
+#[allow(unused_imports)]
+use crate::log;
+use crate::coordinate_system::CoordinateSystem;
+use crate::animation::VelocityTracker;
+
+// Pixels a pressed pointer has to move before it starts panning, so that clicks don't pan.
+const DRAG_THRESHOLD : f32 = 3.0;
+// Two clicks this close in milliseconds and pixels make a double click.
+const DOUBLE_CLICK_TIME : f64 = 300.0;
+const DOUBLE_CLICK_DISTANCE : f32 = 8.0;
+const DOUBLE_CLICK_ZOOM : f32 = 2.0;
+// Smaller box zoom rectangles are ignored.
+const MIN_BOX_ZOOM_SIZE : f32 = 8.0;
+// Wheel zooming around a point this many pixels from a grid point zooms around the grid point.
+const WHEEL_SNAP_DISTANCE : f32 = 10.0;
+const WHEEL_ZOOM : f32 = 0.6;
+// Zoom per pixel of trackpad pinch.
+const PINCH_WHEEL_ZOOM : f32 = 0.01;
+// Wheel events further apart than this many milliseconds belong to different gestures.
+const WHEEL_GESTURE_GAP : f64 = 150.0;
+const LINE_HEIGHT : f32 = 16.0;
+const PAGE_HEIGHT : f32 = 800.0;
+
+/// How the deltas of a wheel event are measured, same as WheelEvent.deltaMode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeltaMode {
+    Pixel,
+    Line,
+    Page,
+}
+
+impl DeltaMode {
+    pub fn from_dom(delta_mode : u32) -> Self {
+        match delta_mode {
+            1 => DeltaMode::Line,
+            2 => DeltaMode::Page,
+            _ => DeltaMode::Pixel,
+        }
+    }
+}
+
+/// A pointer (mouse button, finger or pen) going down, moving or going up. Positions are in screen coordinates
+/// and times in milliseconds.
+#[derive(Clone, Copy, Debug)]
+pub struct PointerEvent {
+    pub id : i32,
+    pub position : Point,
+    pub time : f64,
+    pub shift_key : bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct WheelEvent {
+    pub position : Point,
+    pub delta : Vector,
+    pub delta_mode : DeltaMode,
+    /// Browsers report trackpad pinches as wheel events with the control key pressed.
+    pub ctrl_key : bool,
+    pub time : f64,
+}
+
+/// What the owner of the coordinate system has to do after the controller handled an event.
+#[derive(Clone, Copy, Debug)]
+pub enum Response {
+    None,
+    /// The coordinate system or the box zoom rectangle changed.
+    Redraw,
+    /// A gesture let go of the viewport, which may keep moving at this velocity in pixels per millisecond and
+    /// has to be brought back into bounds.
+    Release(Vector),
+    /// Animate the viewport to this coordinate system.
+    AnimateTo(CoordinateSystem),
+}
+
+#[derive(Clone, Copy, Debug)]
+enum State {
+    Idle,
+    // Down but hasn't moved far enough to pan yet.
+    Pressed { start : Point },
+    Panning,
+    Pinching { center : Point, distance : f32 },
+    BoxZoom { start : Point, end : Point },
+}
+
+/// Turns pointer and wheel events into panning and zooming of a coordinate system:
+///  * dragging pans, with the velocity at release reported for kinetic panning,
+///  * two pointers pinch zoom and pan,
+///  * shift dragging zooms into the rectangle,
+///  * double clicking zooms in, with shift out,
+///  * the mouse wheel zooms in steps, trackpads pan with two fingers and zoom by pinching.
+#[derive(Clone, Debug)]
+pub struct InteractionController {
+    state : State,
+    // The pointers that are down, in the order they went down.
+    pointers : Vec<(i32, Point)>,
+    velocity : VelocityTracker,
+    last_click : Option<(Point, f64)>,
+    // Time of the last wheel event, and whether its gesture comes from a trackpad.
+    last_wheel : Option<(f64, bool)>,
+    trackpad_panned : bool,
+}
+
+impl Default for InteractionController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InteractionController {
+    pub fn new() -> Self {
+        Self {
+            state : State::Idle,
+            pointers : Vec::new(),
+            velocity : VelocityTracker::new(),
+            last_click : None,
+            last_wheel : None,
+            trackpad_panned : false,
+        }
+    }
+
+    /// The rectangle being dragged out for box zoom, in screen coordinates.
+    pub fn box_zoom_rectangle(&self) -> Option<Box2D<f32>> {
+        match self.state {
+            State::BoxZoom { start, end } => Some(Box2D::from_points([start, end])),
+            _ => None,
+        }
+    }
+
+    pub fn pointer_down(&mut self, event : PointerEvent) -> Response {
+        self.pointers.retain(|&(id, _)| id != event.id);
+        self.pointers.push((event.id, event.position));
+        self.velocity.clear();
+        match self.pointers.len() {
+            1 if event.shift_key => {
+                self.state = State::BoxZoom { start : event.position, end : event.position };
+                Response::None
+            }
+            1 => {
+                self.state = State::Pressed { start : event.position };
+                Response::None
+            }
+            _ => {
+                let was_box_zoom = matches!(self.state, State::BoxZoom { .. });
+                self.start_pinch();
+                if was_box_zoom { Response::Redraw } else { Response::None }
+            }
+        }
+    }
+
+    pub fn pointer_move(&mut self, coordinate_system : &mut CoordinateSystem, event : PointerEvent) -> Response {
+        let index = match self.pointers.iter().position(|&(id, _)| id == event.id) {
+            Some(index) => index,
+            // Hovering
+            None => return Response::None,
+        };
+        let previous = self.pointers[index].1;
+        self.pointers[index].1 = event.position;
+        match self.state {
+            State::Idle => Response::None,
+            State::Pressed { start } => {
+                if (event.position - start).length() < DRAG_THRESHOLD {
+                    return Response::None;
+                }
+                self.state = State::Panning;
+                self.pan(coordinate_system, event.position - start, event.time);
+                Response::Redraw
+            }
+            State::Panning => {
+                self.pan(coordinate_system, event.position - previous, event.time);
+                Response::Redraw
+            }
+            State::Pinching { center, distance } => {
+                if index > 1 {
+                    return Response::None;
+                }
+                let (new_center, new_distance) = self.pinch();
+                if distance > 0.0 && new_distance > 0.0 {
+                    // Only fails if the scale is NaN, which the check above prevents.
+                    let _ = coordinate_system.scale_around(new_distance / distance, center.into());
+                }
+                coordinate_system.translate((new_center - center).into());
+                self.state = State::Pinching { center : new_center, distance : new_distance };
+                Response::Redraw
+            }
+            State::BoxZoom { start, .. } => {
+                self.state = State::BoxZoom { start, end : event.position };
+                Response::Redraw
+            }
+        }
+    }
+
+    pub fn pointer_up(&mut self, coordinate_system : &CoordinateSystem, event : PointerEvent) -> Response {
+        if !self.pointers.iter().any(|&(id, _)| id == event.id) {
+            return Response::None;
+        }
+        self.pointers.retain(|&(id, _)| id != event.id);
+        match self.state {
+            State::Idle => Response::None,
+            State::Pressed { .. } => {
+                self.state = State::Idle;
+                self.click(coordinate_system, event)
+            }
+            State::Panning => {
+                self.state = State::Idle;
+                let velocity = self.velocity.velocity(event.time);
+                self.velocity.clear();
+                Response::Release(velocity)
+            }
+            State::Pinching { .. } => {
+                match self.pointers.len() {
+                    0 => {
+                        self.state = State::Idle;
+                        Response::Release(Vector::zero())
+                    }
+                    // Keep panning with the remaining pointer, without a fling from the pinch.
+                    1 => {
+                        self.state = State::Panning;
+                        self.velocity.clear();
+                        Response::None
+                    }
+                    _ => {
+                        self.start_pinch();
+                        Response::None
+                    }
+                }
+            }
+            State::BoxZoom { start, .. } => {
+                self.state = State::Idle;
+                let rectangle = Box2D::from_points([start, event.position]);
+                if rectangle.width() < MIN_BOX_ZOOM_SIZE || rectangle.height() < MIN_BOX_ZOOM_SIZE {
+                    return match self.click(coordinate_system, event) {
+                        Response::None => Response::Redraw,
+                        response => response,
+                    };
+                }
+                let mut target = *coordinate_system;
+                target.set_current_xrange(
+                    coordinate_system.inverse_transform_x(rectangle.min.x),
+                    coordinate_system.inverse_transform_x(rectangle.max.x),
+                );
+                target.set_current_yrange(
+                    coordinate_system.inverse_transform_y(rectangle.max.y),
+                    coordinate_system.inverse_transform_y(rectangle.min.y),
+                );
+                Response::AnimateTo(target)
+            }
+        }
+    }
+
+    /// The pointer left without going up, for example the browser took over the touch.
+    pub fn pointer_cancel(&mut self, event_id : i32) -> Response {
+        if !self.pointers.iter().any(|&(id, _)| id == event_id) {
+            return Response::None;
+        }
+        self.pointers.clear();
+        self.velocity.clear();
+        self.state = State::Idle;
+        Response::Release(Vector::zero())
+    }
+
+    pub fn wheel(&mut self, coordinate_system : &mut CoordinateSystem, event : WheelEvent) -> Response {
+        let delta = match event.delta_mode {
+            DeltaMode::Pixel => event.delta,
+            DeltaMode::Line => event.delta * LINE_HEIGHT,
+            DeltaMode::Page => event.delta * PAGE_HEIGHT,
+        };
+        if event.ctrl_key {
+            let scale = (-delta.y.clamp(-50.0, 50.0) * PINCH_WHEEL_ZOOM).exp();
+            let _ = coordinate_system.scale_around(scale, event.position.into());
+            self.last_wheel = Some((event.time, true));
+            return Response::Release(Vector::zero());
+        }
+        let trackpad = match self.last_wheel {
+            Some((time, trackpad)) if event.time - time < WHEEL_GESTURE_GAP => trackpad,
+            _ => is_trackpad(&event),
+        };
+        self.last_wheel = Some((event.time, trackpad));
+        if trackpad {
+            coordinate_system.translate((-delta).into());
+            self.trackpad_panned = true;
+            return Response::Redraw;
+        }
+        if delta.y == 0.0 {
+            return Response::None;
+        }
+        // If we are close to a grid point lock on to it.
+        let nearest = coordinate_system.transform_point(coordinate_system.inverse_transform_point(event.position).round());
+        if nearest.distance_to(event.position) < WHEEL_SNAP_DISTANCE {
+            coordinate_system.translate((event.position - nearest).into());
+        }
+        let _ = coordinate_system.scale_around(WHEEL_ZOOM.powf(delta.y.signum()), event.position.into());
+        Response::Release(Vector::zero())
+    }
+
+    /// True while a trackpad pan may still be going on, update has to be called until it reports the release.
+    pub fn is_waiting_for_release(&self) -> bool {
+        self.trackpad_panned
+    }
+
+    /// Call every frame. Trackpad panning has no end event, it ends once the wheel events stop.
+    pub fn update(&mut self, time : f64) -> Response {
+        match self.last_wheel {
+            Some((last, _)) if self.trackpad_panned && time - last >= WHEEL_GESTURE_GAP => {
+                self.trackpad_panned = false;
+                Response::Release(Vector::zero())
+            }
+            _ => Response::None,
+        }
+    }
+
+    fn pan(&mut self, coordinate_system : &mut CoordinateSystem, delta : Vector, time : f64) {
+        coordinate_system.translate(delta.into());
+        self.velocity.add(time, delta);
+    }
+
+    fn start_pinch(&mut self) {
+        let (center, distance) = self.pinch();
+        self.state = State::Pinching { center, distance };
+    }
+
+    // Center of and distance between the first two pointers.
+    fn pinch(&self) -> (Point, f32) {
+        let (a, b) = (self.pointers[0].1, self.pointers[1].1);
+        (a.lerp(b, 0.5), (b - a).length())
+    }
+
+    fn click(&mut self, coordinate_system : &CoordinateSystem, event : PointerEvent) -> Response {
+        match self.last_click.take() {
+            Some((position, time)) if event.time - time < DOUBLE_CLICK_TIME
+                && (event.position - position).length() < DOUBLE_CLICK_DISTANCE =>
+            {
+                let scale = if event.shift_key { 1.0 / DOUBLE_CLICK_ZOOM } else { DOUBLE_CLICK_ZOOM };
+                let mut target = *coordinate_system;
+                let _ = target.scale_around(scale, event.position.into());
+                Response::AnimateTo(target)
+            }
+            _ => {
+                self.last_click = Some((event.position, event.time));
+                Response::None
+            }
+        }
+    }
+}
+
+// Mouse wheels scroll in lines or in large whole numbers of pixels along one axis, trackpads in small or fractional
+// amounts and often along both axes. Only the first event of a gesture is classified.
+fn is_trackpad(event : &WheelEvent) -> bool {
+    event.delta_mode == DeltaMode::Pixel
+        && (event.delta.x != 0.0 || event.delta.y.fract() != 0.0 || event.delta.y.abs() < 16.0)
+}
+
+/// What Canvas::handle_key did with a key press.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyAction {
+    /// Not a key for the chart, let the browser handle it.
+    Ignored,
+    /// Moved or cleared the focus.
+    Navigate,
+    /// Enter or space on the focused node.
+    Activate,
+}
+
+/// Direction of an arrow key, for moving the keyboard focus between nodes.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+impl Direction {
+    /// On screen, y points down.
+    pub fn vector(self) -> Vector {
+        match self {
+            Direction::Left => Vector::new(-1.0, 0.0),
+            Direction::Right => Vector::new(1.0, 0.0),
+            Direction::Up => Vector::new(0.0, -1.0),
+            Direction::Down => Vector::new(0.0, 1.0),
+        }
+    }
+}
+
+/// The candidate to move to from the screen position from in the given direction: of the ones on that side, the one
+/// with the smallest distance in the direction plus twice the sideways distance, so that nodes roughly in line win.
+pub fn nearest_in_direction<T, I>(from : Point, direction : Direction, candidates : I) -> Option<T>
+    where I : IntoIterator<Item = (T, Point)>
+{
+    let direction = direction.vector();
+    candidates.into_iter().filter_map(|(candidate, position)| {
+        let offset = position - from;
+        let along = offset.dot(direction);
+        let sideways = offset.cross(direction).abs();
+        (along > 0.0).then_some((candidate, along + 2.0 * sideways))
+    }).min_by(|(_, a), (_, b)| a.total_cmp(b)).map(|(candidate, _)| candidate)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lyon::geom::math::{point, vector};
+    use crate::coordinate_system::BufferDimensions;
+    use crate::animation::KineticPan;
+
+    // 600 by 400 pixels without margins or bounds, showing [0, 10] x [0, 10]: chart (x, y) is at screen (60 x, 400 - 40 y).
+    fn coordinate_system() -> CoordinateSystem {
+        let mut coordinate_system = CoordinateSystem::new();
+        coordinate_system.buffer_dimensions = BufferDimensions::new(600, 400, 1.0);
+        coordinate_system.set_margins(0, 0, 0, 0);
+        coordinate_system.set_current_xrange(0.0, 10.0);
+        coordinate_system.set_current_yrange(0.0, 10.0);
+        coordinate_system
+    }
+
+    fn pointer(id : i32, x : f32, y : f32, time : f64) -> PointerEvent {
+        PointerEvent { id, position : point(x, y), time, shift_key : false }
+    }
+
+    fn shift(event : PointerEvent) -> PointerEvent {
+        PointerEvent { shift_key : true, ..event }
+    }
+
+    fn wheel(x : f32, y : f32, delta : Vector, delta_mode : DeltaMode, time : f64) -> WheelEvent {
+        WheelEvent { position : point(x, y), delta, delta_mode, ctrl_key : false, time }
+    }
+
+    fn assert_close(actual : Point, expected : Point) {
+        assert!((actual - expected).length() < 1e-3, "{:?} is not {:?}", actual, expected);
+    }
+
+    #[test]
+    fn drag_threshold() {
+        let mut coordinate_system = coordinate_system();
+        let origin = coordinate_system.origin;
+        let mut controller = InteractionController::new();
+        assert!(matches!(controller.pointer_down(pointer(1, 100.0, 100.0, 0.0)), Response::None));
+        // Small moves don't pan.
+        assert!(matches!(controller.pointer_move(&mut coordinate_system, pointer(1, 101.0, 102.0, 10.0)), Response::None));
+        assert!(matches!(controller.state, State::Pressed { .. }));
+        assert_eq!(coordinate_system.origin, origin);
+        // Once past the threshold the whole way from where the pointer went down is panned.
+        assert!(matches!(controller.pointer_move(&mut coordinate_system, pointer(1, 103.0, 100.0, 20.0)), Response::Redraw));
+        assert!(matches!(controller.state, State::Panning));
+        assert_eq!(coordinate_system.origin, origin + vector(3.0, 0.0));
+        assert!(matches!(controller.pointer_move(&mut coordinate_system, pointer(1, 110.0, 95.0, 30.0)), Response::Redraw));
+        assert_eq!(coordinate_system.origin, origin + vector(10.0, -5.0));
+
+        // Moves of pointers that aren't down are hovering.
+        let mut controller = InteractionController::new();
+        assert!(matches!(controller.pointer_move(&mut coordinate_system, pointer(1, 200.0, 200.0, 40.0)), Response::None));
+        assert_eq!(coordinate_system.origin, origin + vector(10.0, -5.0));
+    }
+
+    #[test]
+    fn release_velocity() {
+        let mut coordinate_system = coordinate_system();
+        let mut controller = InteractionController::new();
+        controller.pointer_down(pointer(1, 100.0, 100.0, 0.0));
+        for i in 1 ..= 10 {
+            controller.pointer_move(&mut coordinate_system, pointer(1, 100.0 + 5.0 * i as f32, 100.0, 10.0 * i as f64));
+        }
+        let velocity = match controller.pointer_up(&coordinate_system, pointer(1, 150.0, 100.0, 105.0)) {
+            Response::Release(velocity) => velocity,
+            response => panic!("{:?}", response),
+        };
+        assert_close(velocity.to_point(), point(0.5, 0.0));
+
+        // Kinetic panning keeps going in the same direction.
+        coordinate_system.set_elastic_bounds(true);
+        let origin = coordinate_system.origin;
+        let mut kinetic_pan = KineticPan::new(velocity);
+        assert!(kinetic_pan.is_needed(&coordinate_system));
+        assert!(!kinetic_pan.step(&mut coordinate_system, 16.0));
+        let moved = coordinate_system.origin - origin;
+        assert!(moved.x > 0.0 && moved.x < 0.5 * 16.0 && moved.y == 0.0, "{:?}", moved);
+
+        // Pausing before the release doesn't fling.
+        controller.pointer_down(pointer(1, 100.0, 100.0, 1000.0));
+        controller.pointer_move(&mut coordinate_system, pointer(1, 150.0, 100.0, 1010.0));
+        controller.pointer_move(&mut coordinate_system, pointer(1, 200.0, 100.0, 1020.0));
+        assert!(matches!(controller.pointer_up(&coordinate_system, pointer(1, 200.0, 100.0, 1500.0)), Response::Release(velocity) if velocity == Vector::zero()));
+    }
+
+    #[test]
+    fn pinch() {
+        let mut coordinate_system = coordinate_system();
+        let mut controller = InteractionController::new();
+        controller.pointer_down(pointer(1, 100.0, 200.0, 0.0));
+        controller.pointer_down(pointer(2, 300.0, 200.0, 0.0));
+        let under_center = coordinate_system.inverse_transform_point(point(200.0, 200.0));
+        // Twice the distance, with the center moved right by 100 pixels.
+        assert!(matches!(controller.pointer_move(&mut coordinate_system, pointer(2, 500.0, 200.0, 10.0)), Response::Redraw));
+        assert_close(coordinate_system.scale.to_point(), point(120.0, 80.0));
+        assert_close(coordinate_system.transform_point(under_center), point(300.0, 200.0));
+        // Moving both pointers together pans.
+        controller.pointer_move(&mut coordinate_system, pointer(1, 100.0, 250.0, 20.0));
+        controller.pointer_move(&mut coordinate_system, pointer(2, 500.0, 250.0, 20.0));
+        assert_close(coordinate_system.scale.to_point(), point(120.0, 80.0));
+        assert_close(coordinate_system.transform_point(under_center), point(300.0, 250.0));
+
+        // Lifting one pointer keeps panning with the other one, without a fling.
+        assert!(matches!(controller.pointer_up(&coordinate_system, pointer(2, 500.0, 250.0, 30.0)), Response::None));
+        assert!(matches!(controller.state, State::Panning));
+        controller.pointer_move(&mut coordinate_system, pointer(1, 110.0, 250.0, 40.0));
+        assert_close(coordinate_system.transform_point(under_center), point(310.0, 250.0));
+        assert!(matches!(controller.pointer_up(&coordinate_system, pointer(1, 110.0, 250.0, 50.0)), Response::Release(_)));
+    }
+
+    fn click(controller : &mut InteractionController, coordinate_system : &CoordinateSystem, event : PointerEvent) -> Response {
+        controller.pointer_down(event);
+        controller.pointer_up(coordinate_system, event)
+    }
+
+    #[test]
+    fn double_click_zoom() {
+        let coordinate_system = coordinate_system();
+        let mut controller = InteractionController::new();
+        assert!(matches!(click(&mut controller, &coordinate_system, pointer(1, 100.0, 100.0, 0.0)), Response::None));
+        let target = match click(&mut controller, &coordinate_system, pointer(1, 104.0, 100.0, 250.0)) {
+            Response::AnimateTo(target) => target,
+            response => panic!("{:?}", response),
+        };
+        assert_close(target.scale.to_point(), point(120.0, 80.0));
+        let under_click = coordinate_system.inverse_transform_point(point(104.0, 100.0));
+        assert_close(target.transform_point(under_click), point(104.0, 100.0));
+
+        // Too slow or too far apart.
+        assert!(matches!(click(&mut controller, &coordinate_system, pointer(1, 100.0, 100.0, 1000.0)), Response::None));
+        assert!(matches!(click(&mut controller, &coordinate_system, pointer(1, 100.0, 100.0, 1300.0)), Response::None));
+        assert!(matches!(click(&mut controller, &coordinate_system, pointer(1, 110.0, 100.0, 1400.0)), Response::None));
+        // The third click of a triple click starts over.
+        let third = click(&mut controller, &coordinate_system, pointer(1, 110.0, 100.0, 1500.0));
+        assert!(matches!(third, Response::AnimateTo(_)));
+        assert!(matches!(click(&mut controller, &coordinate_system, pointer(1, 110.0, 100.0, 1600.0)), Response::None));
+
+        // Shift zooms out.
+        let target = match click(&mut controller, &coordinate_system, shift(pointer(1, 110.0, 100.0, 1700.0))) {
+            Response::AnimateTo(target) => target,
+            response => panic!("{:?}", response),
+        };
+        assert_close(target.scale.to_point(), point(30.0, 20.0));
+    }
+
+    #[test]
+    fn box_zoom() {
+        let mut coordinate_system = coordinate_system();
+        let origin = coordinate_system.origin;
+        let mut controller = InteractionController::new();
+        controller.pointer_down(shift(pointer(1, 300.0, 200.0, 0.0)));
+        assert!(matches!(controller.pointer_move(&mut coordinate_system, pointer(1, 60.0, 40.0, 10.0)), Response::Redraw));
+        assert_eq!(controller.box_zoom_rectangle(), Some(Box2D::new(point(60.0, 40.0), point(300.0, 200.0))));
+        assert_eq!(coordinate_system.origin, origin);
+        let target = match controller.pointer_up(&coordinate_system, pointer(1, 60.0, 40.0, 20.0)) {
+            Response::AnimateTo(target) => target,
+            response => panic!("{:?}", response),
+        };
+        assert_eq!(controller.box_zoom_rectangle(), None);
+        assert_close(target.current_min_xy(), point(1.0, 5.0));
+        assert_close(target.current_max_xy(), point(5.0, 9.0));
+
+        // Too small a rectangle only clears it.
+        controller.pointer_down(shift(pointer(1, 300.0, 200.0, 1000.0)));
+        controller.pointer_move(&mut coordinate_system, pointer(1, 305.0, 100.0, 1010.0));
+        assert!(matches!(controller.pointer_up(&coordinate_system, pointer(1, 305.0, 100.0, 1020.0)), Response::Redraw));
+        assert_eq!(controller.box_zoom_rectangle(), None);
+    }
+
+    #[test]
+    fn mouse_wheel() {
+        let mut coordinate_system = coordinate_system();
+        let mut controller = InteractionController::new();
+        // Far from a grid point: chart (1.5, 4.5) stays under the pointer.
+        let event = wheel(90.0, 220.0, vector(0.0, 100.0), DeltaMode::Pixel, 0.0);
+        assert!(matches!(controller.wheel(&mut coordinate_system, event), Response::Release(_)));
+        assert_close(coordinate_system.scale.to_point(), point(36.0, 24.0));
+        assert_close(coordinate_system.transform_point(point(1.5, 4.5)), point(90.0, 220.0));
+        assert!(!controller.is_waiting_for_release());
+        // Lines, zooming back in. Close to grid point (2, 5), which moves under the pointer and stays there.
+        let grid_point = coordinate_system.transform_point(point(2.0, 5.0));
+        let event = wheel(grid_point.x + 3.0, grid_point.y - 4.0, vector(0.0, -3.0), DeltaMode::Line, 1000.0);
+        controller.wheel(&mut coordinate_system, event);
+        assert_close(coordinate_system.scale.to_point(), point(60.0, 40.0));
+        assert_close(coordinate_system.transform_point(point(2.0, 5.0)), event.position);
+    }
+
+    #[test]
+    fn trackpad_wheel() {
+        let mut coordinate_system = coordinate_system();
+        let origin = coordinate_system.origin;
+        let mut controller = InteractionController::new();
+        // Small fractional deltas pan.
+        let event = wheel(100.0, 100.0, vector(0.0, 4.5), DeltaMode::Pixel, 0.0);
+        assert!(matches!(controller.wheel(&mut coordinate_system, event), Response::Redraw));
+        assert_eq!(coordinate_system.origin, origin - vector(0.0, 4.5));
+        assert!(controller.is_waiting_for_release());
+        // The rest of the gesture counts as trackpad too, even if it looks like a mouse wheel.
+        let event = wheel(100.0, 100.0, vector(0.0, 100.0), DeltaMode::Pixel, 100.0);
+        assert!(matches!(controller.wheel(&mut coordinate_system, event), Response::Redraw));
+        assert_eq!(coordinate_system.origin, origin - vector(0.0, 104.5));
+        assert_eq!(coordinate_system.scale, vector(60.0, 40.0));
+        // The pan ends once the events stop.
+        assert!(matches!(controller.update(200.0), Response::None));
+        assert!(matches!(controller.update(250.0), Response::Release(velocity) if velocity == Vector::zero()));
+        assert!(!controller.is_waiting_for_release());
+
+        // A new gesture is classified again.
+        let event = wheel(100.0, 100.0, vector(0.0, 100.0), DeltaMode::Pixel, 400.0);
+        assert!(matches!(controller.wheel(&mut coordinate_system, event), Response::Release(_)));
+        assert_close(coordinate_system.scale.to_point(), point(36.0, 24.0));
+        // Sideways scrolling only happens on trackpads.
+        let origin = coordinate_system.origin;
+        let event = wheel(100.0, 100.0, vector(20.0, 0.0), DeltaMode::Pixel, 1000.0);
+        assert!(matches!(controller.wheel(&mut coordinate_system, event), Response::Redraw));
+        assert_eq!(coordinate_system.origin, origin - vector(20.0, 0.0));
+
+        // Pinching zooms in proportion to the delta.
+        let event = WheelEvent { ctrl_key : true, ..wheel(100.0, 100.0, vector(0.0, -10.0), DeltaMode::Pixel, 2000.0) };
+        controller.wheel(&mut coordinate_system, event);
+        assert_close(coordinate_system.scale.to_point(), point(36.0 * 0.1f32.exp(), 24.0 * 0.1f32.exp()));
+    }
+
+    #[test]
+    fn classify_wheel() {
+        let trackpad = |delta : Vector, delta_mode| is_trackpad(&wheel(0.0, 0.0, delta, delta_mode, 0.0));
+        assert!(trackpad(vector(0.0, 2.0), DeltaMode::Pixel));
+        assert!(trackpad(vector(0.0, 33.5), DeltaMode::Pixel));
+        assert!(trackpad(vector(1.0, 100.0), DeltaMode::Pixel));
+        assert!(!trackpad(vector(0.0, 100.0), DeltaMode::Pixel));
+        assert!(!trackpad(vector(0.0, -53.0), DeltaMode::Pixel));
+        assert!(!trackpad(vector(0.0, 1.0), DeltaMode::Line));
+        assert!(!trackpad(vector(0.5, 0.5), DeltaMode::Page));
+    }
+}
@@ -3,37 +3,56 @@
 //#![allow(unused_imports)]
 
 mod console_log;
-mod error;
+pub mod error;
 
-mod convex_hull;
+pub mod convex_hull;
+pub mod svg_path;
 
-mod vector;
+pub mod vector;
 
-mod arrow;
+pub mod arrow;
 
+#[cfg(feature = "webgl")]
 mod webgl_wrapper;
+#[cfg(feature = "webgl")]
 mod shader;
 
-mod coordinate_system;
+pub mod coordinate_system;
+pub mod animation;
+pub mod interaction;
+pub mod minimap;
+pub mod export;
+#[cfg(feature = "webgl")]
 mod canvas;
+#[cfg(feature = "webgl")]
+mod spectral_sequence;
 
 
-mod glyph;
+pub mod glyph;
+pub mod tessellation_cache;
+pub mod scene;
 
+#[cfg(feature = "webgl")]
 use crate::canvas::Canvas;
+#[cfg(feature = "webgl")]
+use crate::error::ChartError;
 
 
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 
 
+#[cfg(feature = "webgl")]
 use web_sys::{WebGl2RenderingContext};
 
+#[cfg(feature = "webgl")]
 #[wasm_bindgen]
-pub fn get_rust_canvas(context : &WebGl2RenderingContext) -> Result<Canvas, JsValue> {
+pub fn get_rust_canvas(context : &WebGl2RenderingContext) -> Result<Canvas, ChartError> {
     console_error_panic_hook::set_once();
-    Ok(Canvas::new(context)?)
+    Canvas::new(context)
 }
 
+#[cfg(feature = "wasm")]
 #[wasm_bindgen]
 pub fn rust_main() {
     // When the `console_error_panic_hook` feature is enabled, we can call the
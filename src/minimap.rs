@@ -0,0 +1,170 @@
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use lyon::geom::math::{Point, point, Vector, vector};
+use euclid::default::Box2D;
+
+#[allow(unused_imports)]
+use crate::log;
+use crate::coordinate_system::{CoordinateSystem, BufferDimensions};
+
+// Pixels between the minimap and the margins of the chart.
+const MINIMAP_MARGIN : f32 = 10.0;
+// Pixels left around the content inside the minimap.
+const MINIMAP_PADDING : f32 = 6.0;
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// A small overview of the whole chart in a corner of the screen. Sizes are in css pixels.
+#[derive(Clone, Copy, Debug)]
+pub struct Minimap {
+    pub width : f32,
+    pub height : f32,
+    pub corner : Corner,
+}
+
+impl Minimap {
+    pub fn new(width : f32, height : f32, corner : Corner) -> Self {
+        Self { width, height, corner }
+    }
+
+    /// Where the minimap goes on the screen of the main coordinate system, inside its margins.
+    pub fn screen_rect(&self, main : &CoordinateSystem) -> Box2D<f32> {
+        let (screen_x_min, screen_x_max) = main.screen_x_range();
+        let (screen_y_min, screen_y_max) = main.screen_y_range();
+        let x = match self.corner {
+            Corner::TopLeft | Corner::BottomLeft => screen_x_min + MINIMAP_MARGIN,
+            Corner::TopRight | Corner::BottomRight => screen_x_max - MINIMAP_MARGIN - self.width,
+        };
+        let y = match self.corner {
+            Corner::TopLeft | Corner::TopRight => screen_y_min + MINIMAP_MARGIN,
+            Corner::BottomLeft | Corner::BottomRight => screen_y_max - MINIMAP_MARGIN - self.height,
+        };
+        Box2D::new(point(x, y), point(x + self.width, y + self.height))
+    }
+
+    /// A coordinate system for drawing the content box (in lattice coordinates) into the minimap as large as it fits at
+    /// the natural aspect ratio of main. Screen coordinates are relative to the top left corner of the minimap. Glyphs
+    /// shrink with the chart, but never grow.
+    pub fn coordinate_system(&self, main : &CoordinateSystem, content : Box2D<f32>) -> CoordinateSystem {
+        let mut result = *main;
+        result.buffer_dimensions = BufferDimensions::new(self.width as i32, self.height as i32, main.buffer_dimensions.density());
+        result.left_margin = 0;
+        result.right_margin = 0;
+        result.top_margin = 0;
+        result.bottom_margin = 0;
+        result.reset_transform();
+        let available : Vector = vector(self.width, self.height) - vector(2.0 * MINIMAP_PADDING, 2.0 * MINIMAP_PADDING);
+        let ratio = main.natural_scale_ratio;
+        let size = content.size();
+        let s = f32::min(available.x / size.width.max(1.0), available.y / (size.height.max(1.0) * ratio));
+        result.scale = vector(s, s * ratio);
+        let center = content.center();
+        result.origin = point(self.width / 2.0 - s * center.x, self.height / 2.0 + s * ratio * center.y);
+        result.glyph_scale = main.glyph_scale * f32::min(1.0, s / main.scale.x);
+        result
+    }
+
+    /// The lattice point under a point of the main screen, for the minimap coordinate system made by coordinate_system.
+    pub fn lattice_point(&self, main : &CoordinateSystem, minimap : &CoordinateSystem, screen_point : Point) -> Point {
+        let offset = screen_point - self.screen_rect(main).min;
+        point(minimap.inverse_transform_x(offset.x), minimap.inverse_transform_y(offset.y))
+    }
+
+    /// How far to translate main to bring the lattice point under a point of the main screen to the middle of it.
+    pub fn center_delta(&self, main : &CoordinateSystem, minimap : &CoordinateSystem, screen_point : Point) -> Vector {
+        let target = self.lattice_point(main, minimap, screen_point);
+        let (screen_x_min, screen_x_max) = main.screen_x_range();
+        let (screen_y_min, screen_y_max) = main.screen_y_range();
+        let middle = point(screen_x_min + screen_x_max, screen_y_min + screen_y_max) / 2.0;
+        middle - point(main.transform_x(target.x), main.transform_y(target.y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 600 by 400 pixels with the default margins, showing [0, 10] x [0, 5] skewed by (x, y) -> (x + y, y).
+    fn main_coordinate_system() -> CoordinateSystem {
+        let mut coordinate_system = CoordinateSystem::new();
+        coordinate_system.buffer_dimensions = BufferDimensions::new(600, 400, 1.0);
+        coordinate_system.set_current_xrange(0.0, 10.0);
+        coordinate_system.set_current_yrange(0.0, 5.0);
+        coordinate_system.set_lattice(1.0, 1.0, 0.0, 1.0, 0.0, 0.0).unwrap();
+        coordinate_system
+    }
+
+    #[test]
+    fn screen_rect() {
+        let main = main_coordinate_system();
+        let (screen_x_min, screen_x_max) = main.screen_x_range();
+        let (screen_y_min, screen_y_max) = main.screen_y_range();
+        for &corner in &[Corner::TopLeft, Corner::TopRight, Corner::BottomLeft, Corner::BottomRight] {
+            let rect = Minimap::new(120.0, 80.0, corner).screen_rect(&main);
+            assert_eq!(rect.size(), euclid::default::Size2D::new(120.0, 80.0));
+            assert!(rect.min.x >= screen_x_min && rect.max.x <= screen_x_max && rect.min.y >= screen_y_min && rect.max.y <= screen_y_max, "{:?}: {:?}", corner, rect);
+            let left = matches!(corner, Corner::TopLeft | Corner::BottomLeft);
+            let top = matches!(corner, Corner::TopLeft | Corner::TopRight);
+            assert_eq!(rect.min.x == screen_x_min + MINIMAP_MARGIN, left);
+            assert_eq!(rect.max.x == screen_x_max - MINIMAP_MARGIN, !left);
+            assert_eq!(rect.min.y == screen_y_min + MINIMAP_MARGIN, top);
+            assert_eq!(rect.max.y == screen_y_max - MINIMAP_MARGIN, !top);
+        }
+    }
+
+    #[test]
+    fn content_fits() {
+        let main = main_coordinate_system();
+        let minimap = Minimap::new(120.0, 80.0, Corner::BottomRight);
+        for &content in &[
+            Box2D::new(point(-3.0, 2.0), point(17.0, 5.0)),
+            Box2D::new(point(0.0, 0.0), point(1.0, 40.0)),
+            Box2D::new(point(2.0, 2.0), point(2.0, 2.0)),
+        ] {
+            let coordinate_system = minimap.coordinate_system(&main, content);
+            assert!((coordinate_system.scale.y / coordinate_system.scale.x - main.natural_scale_ratio).abs() < 1e-5);
+            let (min, max) = (content.min, content.max);
+            let (left, right) = (coordinate_system.transform_x(min.x), coordinate_system.transform_x(max.x));
+            let (top, bottom) = (coordinate_system.transform_y(max.y), coordinate_system.transform_y(min.y));
+            assert!(left >= MINIMAP_PADDING - 1e-3 && right <= 120.0 - MINIMAP_PADDING + 1e-3, "{:?}: {} {}", content, left, right);
+            assert!(top >= MINIMAP_PADDING - 1e-3 && bottom <= 80.0 - MINIMAP_PADDING + 1e-3, "{:?}: {} {}", content, top, bottom);
+            // Centered.
+            assert!(((left + right) / 2.0 - 60.0).abs() < 1e-3 && ((top + bottom) / 2.0 - 40.0).abs() < 1e-3);
+            // Glyphs never grow.
+            assert!(coordinate_system.glyph_scale <= main.glyph_scale);
+        }
+    }
+
+    #[test]
+    fn center_on_click() {
+        let mut main = main_coordinate_system();
+        let minimap = Minimap::new(120.0, 80.0, Corner::TopLeft);
+        let content = Box2D::new(point(-3.0, 2.0), point(17.0, 5.0));
+        let coordinate_system = minimap.coordinate_system(&main, content);
+        let rect = minimap.screen_rect(&main);
+        let click = rect.min + vector(30.0, 50.0);
+        let target = minimap.lattice_point(&main, &coordinate_system, click);
+        // The lattice point drawn under the click.
+        assert!((rect.min.x + coordinate_system.transform_x(target.x) - click.x).abs() < 1e-3);
+        assert!((rect.min.y + coordinate_system.transform_y(target.y) - click.y).abs() < 1e-3);
+
+        let delta = minimap.center_delta(&main, &coordinate_system, click);
+        main.translate(delta.into());
+        let (screen_x_min, screen_x_max) = main.screen_x_range();
+        let (screen_y_min, screen_y_max) = main.screen_y_range();
+        let middle = point(screen_x_min + screen_x_max, screen_y_min + screen_y_max) / 2.0;
+        let centered = point(main.inverse_transform_x(middle.x), main.inverse_transform_y(middle.y));
+        assert!((centered - target).length() < 1e-3, "{:?} is not {:?}", centered, target);
+        // In chart coordinates that is the inverse of the lattice.
+        let chart_point = main.inverse_transform_point(middle);
+        assert!((chart_point - point(target.x - target.y, target.y)).length() < 1e-3);
+    }
+}
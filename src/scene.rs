@@ -0,0 +1,301 @@
+// Serializable description of everything that was added to a chart, so that it can be saved and loaded again.
+// Glyphs and arrows are stored once and referred to by their index in the scene, nodes are referred to by their id.
+use serde::{Serialize, Deserialize};
+
+#[allow(unused_imports)]
+use crate::log;
+use crate::error::ChartError;
+use crate::vector::Vec4;
+use crate::glyph::{GlyphComponentOptions, ComponentColor, GlyphBoundary, LineJoin, LineCap, NUM_COLOR_SLOTS, ALL_PAGES};
+
+/// Increase this when the format changes, and add a migration from the previous version to Scene::from_json.
+/// Version 2 added edge colors, 3 page ranges, 4 layers, 5 the lattice and 6 node labels. The new fields have defaults
+/// that draw older scenes as before, so none of these needed a migration.
+pub const SCENE_VERSION : u32 = 6;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Scene {
+    pub version : u32,
+    pub glyphs : Vec<GlyphDefinition>,
+    pub arrows : Vec<ArrowDefinition>,
+    pub nodes : Vec<NodeDefinition>,
+    pub edges : Vec<EdgeDefinition>,
+    /// Nodes and edges refer to these by index. If there are none, everything is in a single default layer.
+    #[serde(default)]
+    pub layers : Vec<LayerDefinition>,
+    pub coordinate_system : CoordinateSystemDefinition,
+    /// The page shown, see NodeDefinition::page_range.
+    #[serde(default)]
+    pub page : i32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GlyphDefinition {
+    pub components : Vec<GlyphComponentDefinition>,
+    /// (xmin, ymin, xmax, ymax)
+    pub bounding_box : [f32; 4],
+    pub convex_hull_component : usize,
+    pub raster_convex_hull : bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GlyphComponentDefinition {
+    /// Svg path data.
+    pub path : String,
+    pub options : GlyphComponentOptions,
+    pub stroke_color : ComponentColor,
+    pub fill_color : ComponentColor,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ArrowDefinition {
+    /// Svg path data.
+    pub path : String,
+    pub stroke : Option<ArrowStrokeDefinition>,
+    /// Tolerance of the fill, if the arrow is filled.
+    pub fill : Option<f32>,
+    pub tip_end : f32,
+    pub back_end : f32,
+    pub visual_tip_end : f32,
+    pub visual_back_end : f32,
+    pub line_end : f32,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct ArrowStrokeDefinition {
+    pub line_width : f32,
+    pub line_join : LineJoin,
+    pub start_cap : LineCap,
+    pub end_cap : LineCap,
+    pub miter_limit : f32,
+    pub tolerance : f32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NodeDefinition {
+    /// Index into Scene::glyphs
+    pub glyph : usize,
+    pub position : [f32; 2],
+    pub scale : f32,
+    pub colors : [Vec4; NUM_COLOR_SLOTS],
+    /// (first, last) page the node is drawn on.
+    #[serde(default = "all_pages")]
+    pub page_range : [i32; 2],
+    /// Index into Scene::layers
+    #[serde(default)]
+    pub layer : u32,
+    #[serde(default)]
+    pub label : Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EdgeDefinition {
+    /// Indices into Scene::nodes
+    pub start : usize,
+    pub end : usize,
+    /// Indices into Scene::arrows
+    pub start_tip : Option<usize>,
+    pub end_tip : Option<usize>,
+    /// In radians
+    pub bend_angle : f32,
+    pub thickness : f32,
+    #[serde(default = "black")]
+    pub color : Vec4,
+    pub dash_pattern : Vec<u8>,
+    pub boundary : GlyphBoundary,
+    #[serde(default = "all_pages")]
+    pub page_range : [i32; 2],
+    #[serde(default)]
+    pub layer : u32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LayerDefinition {
+    pub name : String,
+    pub z_order : i32,
+    pub visible : bool,
+    pub opacity : f32,
+}
+
+fn black() -> Vec4 {
+    Vec4::new(0.0, 0.0, 0.0, 1.0)
+}
+
+fn all_pages() -> [i32; 2] {
+    ALL_PAGES
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct CoordinateSystemDefinition {
+    pub origin : [f32; 2],
+    pub scale : [f32; 2],
+    pub glyph_scale : f32,
+    /// (left, right, bottom, top)
+    pub margins : [i32; 4],
+    pub natural_scale_ratio : f32,
+    pub max_scale : f32,
+    /// Limits of panning, None if unbounded (json has no infinity).
+    pub min_xy_boundary : [Option<f32>; 2],
+    pub max_xy_boundary : [Option<f32>; 2],
+    /// Chart to lattice coordinates (a, c, b, d, tx, ty): (x, y) -> (a x + b y + tx, c x + d y + ty).
+    #[serde(default = "identity")]
+    pub lattice : [f32; 6],
+}
+
+fn identity() -> [f32; 6] {
+    [1.0, 0.0, 0.0, 1.0, 0.0, 0.0]
+}
+
+impl Scene {
+    pub fn to_json(&self) -> Result<String, ChartError> {
+        serde_json::to_string(self).map_err(|e| ChartError::InvalidInput(format!("Failed to serialize scene: {}", e)))
+    }
+
+    pub fn from_json(json : &str) -> Result<Scene, ChartError> {
+        let value : serde_json::Value = serde_json::from_str(json)
+            .map_err(|e| ChartError::InvalidInput(format!("Invalid scene json: {}", e)))?;
+        let version = value.get("version").and_then(|v| v.as_u64())
+            .ok_or_else(|| ChartError::invalid_input("Scene has no version number."))?;
+        if version == 0 || version > SCENE_VERSION as u64 {
+            return Err(ChartError::InvalidInput(format!(
+                "Unsupported scene version {}: this version of the chart reads scene versions up to {}.", version, SCENE_VERSION
+            )));
+        }
+        // Migrations from older versions go here.
+        serde_json::from_value(value).map_err(|e| ChartError::InvalidInput(format!("Invalid scene: {}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    // Every field is different from its default.
+    fn scene() -> Scene {
+        let node = NodeDefinition {
+            glyph : 0,
+            position : [1.0, 2.0],
+            scale : 3.0,
+            colors : [Vec4::new(1.0, 0.0, 0.0, 1.0); NUM_COLOR_SLOTS],
+            page_range : [1, 2],
+            layer : 1,
+            label : Some("x".to_string()),
+        };
+        Scene {
+            version : SCENE_VERSION,
+            glyphs : vec![GlyphDefinition {
+                components : vec![GlyphComponentDefinition {
+                    path : "M 0 0 L 10 0 L 10 10 Z".to_string(),
+                    options : GlyphComponentOptions::new(),
+                    stroke_color : ComponentColor::Slot(0),
+                    fill_color : ComponentColor::Slot(1),
+                }],
+                bounding_box : [0.0, 0.0, 10.0, 10.0],
+                convex_hull_component : 0,
+                raster_convex_hull : false,
+            }],
+            arrows : vec![],
+            nodes : vec![node.clone(), node],
+            edges : vec![EdgeDefinition {
+                start : 0,
+                end : 1,
+                start_tip : None,
+                end_tip : None,
+                bend_angle : 0.5,
+                thickness : 2.0,
+                color : Vec4::new(0.0, 1.0, 0.0, 1.0),
+                dash_pattern : vec![1, 2],
+                boundary : GlyphBoundary::ConvexHull,
+                page_range : [2, 3],
+                layer : 1,
+            }],
+            layers : vec![
+                LayerDefinition { name : "bottom".to_string(), z_order : 0, visible : true, opacity : 1.0 },
+                LayerDefinition { name : "top".to_string(), z_order : 1, visible : false, opacity : 0.5 },
+            ],
+            coordinate_system : CoordinateSystemDefinition {
+                origin : [10.0, 20.0],
+                scale : [30.0, 30.0],
+                glyph_scale : 1.0,
+                margins : [1, 2, 3, 4],
+                natural_scale_ratio : 1.0,
+                max_scale : 100.0,
+                min_xy_boundary : [None, Some(-1.0)],
+                max_xy_boundary : [Some(5.0), None],
+                lattice : [1.0, 0.0, 0.5, 1.0, 0.0, 0.0],
+            },
+            page : 2,
+        }
+    }
+
+    // The fields each version added, where they are (nothing for the scene itself) and what older scenes get instead.
+    fn added_fields() -> Vec<(u32, &'static str, &'static str, Value)> {
+        vec![
+            (2, "edges", "color", serde_json::to_value(black()).unwrap()),
+            (3, "nodes", "page_range", json!(ALL_PAGES)),
+            (3, "edges", "page_range", json!(ALL_PAGES)),
+            (3, "", "page", json!(0)),
+            (4, "", "layers", json!([])),
+            (4, "nodes", "layer", json!(0)),
+            (4, "edges", "layer", json!(0)),
+            (5, "coordinate_system", "lattice", json!(identity())),
+            (6, "nodes", "label", Value::Null),
+        ]
+    }
+
+    fn objects<'a>(scene : &'a mut Value, place : &str) -> Vec<&'a mut serde_json::Map<String, Value>> {
+        let value = if place.is_empty() { scene } else { &mut scene[place] };
+        match value {
+            Value::Array(items) => items.iter_mut().map(|item| item.as_object_mut().unwrap()).collect(),
+            Value::Object(object) => vec![object],
+            _ => panic!("{} is not an object or array", place),
+        }
+    }
+
+    #[test]
+    fn every_version_added_fields() {
+        let versions : Vec<u32> = added_fields().iter().map(|field| field.0).collect();
+        for version in 2 ..= SCENE_VERSION {
+            assert!(versions.contains(&version), "No fields listed for version {}.", version);
+        }
+    }
+
+    #[test]
+    fn older_versions() {
+        let current = serde_json::to_value(scene()).unwrap();
+        for version in 1 .. SCENE_VERSION {
+            let mut old = current.clone();
+            let mut expected = current.clone();
+            old["version"] = json!(version);
+            expected["version"] = json!(version);
+            for (_, place, field, default) in added_fields().into_iter().filter(|field| field.0 > version) {
+                for object in objects(&mut old, place) {
+                    object.remove(field);
+                }
+                for object in objects(&mut expected, place) {
+                    object.insert(field.to_string(), default.clone());
+                }
+            }
+            let loaded = Scene::from_json(&old.to_string()).unwrap();
+            assert_eq!(serde_json::to_value(loaded).unwrap(), expected, "version {}", version);
+        }
+    }
+
+    #[test]
+    fn current_version_round_trip() {
+        let json = scene().to_json().unwrap();
+        assert_eq!(Scene::from_json(&json).unwrap().to_json().unwrap(), json);
+    }
+
+    #[test]
+    fn unsupported_versions() {
+        let mut value = serde_json::to_value(scene()).unwrap();
+        for version in [json!(0), json!(SCENE_VERSION + 1), json!("1"), Value::Null] {
+            value["version"] = version;
+            assert!(matches!(Scene::from_json(&value.to_string()), Err(ChartError::InvalidInput(_))));
+        }
+        value.as_object_mut().unwrap().remove("version");
+        assert!(matches!(Scene::from_json(&value.to_string()), Err(ChartError::InvalidInput(_))));
+    }
+}
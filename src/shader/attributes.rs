@@ -1,13 +1,15 @@
 use std::convert::{TryInto, TryFrom};
 use web_sys::{WebGl2RenderingContext, WebGlVertexArrayObject, WebGlBuffer, WebGlProgram};
-use wasm_bindgen::JsValue;
+use crate::error::ChartError;
 
 use crate::webgl_wrapper::WebGlWrapper;
+use crate::shader::gl_resources::TextureFormat;
 
+#[allow(dead_code)]
 #[derive(Copy, Clone, Debug)]
 pub enum Type {
     F32,
-    U32,
+    I32, U32,
     I16, U16,
     U8,
 }
@@ -57,6 +59,11 @@ impl Format {
             Format(Type::F32, NumChannels::Three) => WebGl2RenderingContext::RGB32F,
             Format(Type::F32, NumChannels::Four) => WebGl2RenderingContext::RGBA32F,
 
+            Format(Type::I32, NumChannels::One) => WebGl2RenderingContext::R32I,
+            Format(Type::I32, NumChannels::Two) => WebGl2RenderingContext::RG32I,
+            Format(Type::I32, NumChannels::Three) => WebGl2RenderingContext::RGB32I,
+            Format(Type::I32, NumChannels::Four) => WebGl2RenderingContext::RGBA32I,
+
             Format(Type::U32, NumChannels::One) => WebGl2RenderingContext::R32UI,
             Format(Type::U32, NumChannels::Two) => WebGl2RenderingContext::RG32UI,
             Format(Type::U32, NumChannels::Three) => WebGl2RenderingContext::RGB32UI,
@@ -92,6 +99,16 @@ impl Format {
     pub fn base_format(self) -> u32 {
         self.1.base_format()
     }
+
+    /// Data textures are read texel by texel, without filtering.
+    pub fn texture_format(self) -> TextureFormat {
+        TextureFormat {
+            internal_format : self.internal_format(),
+            base_format : self.base_format(),
+            webgl_type : self.webgl_type(),
+            filter : WebGl2RenderingContext::NEAREST,
+        }
+    }
 }
 
 
@@ -100,6 +117,7 @@ impl Type {
     fn size(self) -> i32 {
         match self {
             Type::F32 => std::mem::size_of::<f32>() as i32,
+            Type::I32 => std::mem::size_of::<i32>() as i32,
             Type::U32 => std::mem::size_of::<u32>() as i32,
             Type::I16 => std::mem::size_of::<i16>() as i32,
             Type::U16 => std::mem::size_of::<u16>() as i32,
@@ -110,6 +128,7 @@ impl Type {
     fn webgl_type(self) -> u32 {
         match self {
             Type::F32 => WebGl2RenderingContext::FLOAT,
+            Type::I32 => WebGl2RenderingContext::INT,
             Type::U32 => WebGl2RenderingContext::UNSIGNED_INT,
             Type::I16 => WebGl2RenderingContext::SHORT,
             Type::U16 => WebGl2RenderingContext::UNSIGNED_SHORT,
@@ -150,11 +169,11 @@ impl Attributes {
         ).sum()
     }
     
-    fn stride(&self) -> i32 {
+    pub(in crate::shader) fn stride(&self) -> i32 {
         self.offset(self.attributes.len())
     }
 
-    pub fn set_up_vertex_array(&self, webgl : &WebGlWrapper, program : &WebGlProgram, attribute_state : Option<&WebGlVertexArrayObject>, attributes_buffer : Option<&WebGlBuffer>) -> Result<(), JsValue> {
+    pub fn set_up_vertex_array(&self, webgl : &WebGlWrapper, program : &WebGlProgram, attribute_state : Option<&WebGlVertexArrayObject>, attributes_buffer : Option<&WebGlBuffer>) -> Result<(), ChartError> {
         webgl.bind_vertex_array(attribute_state);
         // IMPORTANT: Must bind_buffer here!!!!
         // vertex_attrib_pointer uses the current bound buffer implicitly.
@@ -163,12 +182,12 @@ impl Attributes {
         let stride = self.stride();
         for (idx, &Attribute {name, size, ty}) in self.attributes.iter().enumerate() {
             let size = size as i32;
-            let loc = webgl.get_attrib_location(program, name).try_into().map_err(|_| name.to_string())?;
+            let loc = webgl.get_attrib_location(program, name).try_into().map_err(|_| ChartError::ProgramLink(format!("No active attribute named {}.", name)))?;
             let offset = self.offset(idx);
             webgl.enable_vertex_attrib_array(loc);
             match ty {
                 Type::F32 => {webgl.vertex_attrib_pointer_with_i32(loc, size, ty.webgl_type(), false, stride, offset)},
-                Type::U32 | Type::I16 | Type::U16 | Type::U8 
+                Type::I32 | Type::U32 | Type::I16 | Type::U16 | Type::U8 
                     => {webgl.vertex_attrib_i_pointer_with_i32(loc, size, ty.webgl_type(), stride, offset)}
             };
             webgl.vertex_attrib_divisor(loc, 1);
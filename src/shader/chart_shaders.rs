@@ -1,102 +1,293 @@
 use std::collections::{BTreeMap, btree_map};
 
-use wasm_bindgen::JsValue;
+use crate::error::{ChartError, checked_index};
 
-use lyon::geom::math::{Vector};
+use lyon::geom::math::{Point, Vector};
 
 #[allow(unused_imports)]
 use crate::log;
 use crate::webgl_wrapper::WebGlWrapper;
 
-use crate::vector::JsPoint;
+use crate::vector::{JsPoint, Vec4};
+use crate::animation::Transition;
 use crate::coordinate_system::CoordinateSystem;
-use crate::glyph::{Glyph, GlyphUuid, GlyphInstance};
+use crate::glyph::{Glyph, GlyphUuid, GlyphInstance, NUM_COLOR_SLOTS};
+use crate::tessellation_cache::TessellationCache;
 
 use crate::shader::attributes::{Format, Type, NumChannels};
-use crate::shader::{GlyphShader, HitCanvasShader, EdgeShader, EdgeOptions, DataTexture};
+use crate::shader::{GlyphShader, HitCanvasShader, EdgeShader, FocusRingShader, EdgeOptions, DataTexture};
+use crate::shader::gl_resources::GlResources;
 
 
 
+/// A group of nodes and edges that is drawn together. Layers are drawn in increasing z_order, ties in the order they
+/// were added, and within a layer the glyphs are drawn before the edges.
+#[derive(Clone, Debug)]
+pub struct Layer {
+    pub name : String,
+    pub z_order : i32,
+    pub visible : bool,
+    pub opacity : f32,
+}
+
+impl Layer {
+    pub fn new(name : String, z_order : i32) -> Self {
+        Self { name, z_order, visible : true, opacity : 1.0 }
+    }
+}
+
 // struct NodeId(usize);
 // struct EdgeId(usize);
-pub struct ChartShaders {
+pub struct ChartShaders<G : GlResources = WebGlWrapper> {
     glyph_map : BTreeMap<GlyphUuid, usize>,
+    tessellation_cache : TessellationCache,
 
-    glyph_convex_hulls : DataTexture<Vector>,
+    // For each glyph, ANGLE_RESOLUTION points of the convex hull followed by ANGLE_RESOLUTION points of the star outline.
+    glyph_boundaries : DataTexture<Vector, G>,
     
-    pub glyph_shader : GlyphShader,
-    pub edge_shader : EdgeShader,
-    pub hit_canvas_shader : HitCanvasShader,
+    pub glyph_shader : GlyphShader<G>,
+    pub edge_shader : EdgeShader<G>,
+    pub hit_canvas_shader : HitCanvasShader<G>,
+    pub focus_ring_shader : FocusRingShader<G>,
+    // Instance index of the node with keyboard focus.
+    focused : Option<usize>,
 
+    // Instances whose page range doesn't contain this are skipped by the shaders.
+    page : i32,
+    // Index 0 is the default layer that everything starts out in.
+    layers : Vec<Layer>,
 }
 
-impl ChartShaders {
-    pub fn new(webgl : WebGlWrapper) -> Result<Self, JsValue> {
-        let glyph_convex_hulls = DataTexture::new(webgl.clone(), Format(Type::F32, NumChannels::Four));
+impl<G : GlResources> ChartShaders<G> {
+    pub fn new(webgl : G, tessellation_cache : TessellationCache) -> Result<Self, ChartError> {
+        let glyph_boundaries = DataTexture::new(webgl.clone(), Format(Type::F32, NumChannels::Four));
         let glyph_shader = GlyphShader::new(webgl.clone())?;
         let hit_canvas_shader = HitCanvasShader::new(webgl.clone())?;
         let edge_shader = EdgeShader::new(webgl.clone())?;
+        let focus_ring_shader = FocusRingShader::new(webgl.clone())?;
         Ok(Self { 
             glyph_map : BTreeMap::new(),
-            glyph_convex_hulls,
+            tessellation_cache,
+            glyph_boundaries,
             glyph_shader,
             hit_canvas_shader,
             edge_shader,
+            focus_ring_shader,
+            focused : None,
+            page : 0,
+            layers : vec![Layer::new("default".to_string(), 0)],
         })
     }
 
+    /// Rebuild everything on the new context and upload all the data again.
+    pub fn restore_context(&mut self, webgl : G) -> Result<(), ChartError> {
+        self.glyph_boundaries.restore_context(webgl.clone());
+        self.glyph_shader.restore_context(webgl.clone())?;
+        self.hit_canvas_shader.restore_context(webgl.clone())?;
+        self.edge_shader.restore_context(webgl.clone())?;
+        self.focus_ring_shader.restore_context(webgl)?;
+        self.prepare()
+    }
+
+    /// Upload the data that changed, drawing does this for the shaders it uses.
+    pub fn prepare(&mut self) -> Result<(), ChartError> {
+        self.glyph_boundaries.prepare()?;
+        self.glyph_shader.prepare()?;
+        self.hit_canvas_shader.prepare();
+        self.edge_shader.prepare()?;
+        self.focus_ring_shader.prepare();
+        Ok(())
+    }
+
+    pub fn tessellation_cache(&self) -> &TessellationCache {
+        &self.tessellation_cache
+    }
+
+    // The hull and star outline of each glyph, ANGLE_RESOLUTION points each.
+    #[cfg(test)]
+    pub(crate) fn glyph_boundaries(&self) -> &[Vector] {
+        self.glyph_boundaries.entries()
+    }
+
     pub fn clear_glyphs(&mut self) {
         self.glyph_map.clear();
+        self.glyph_boundaries.clear();
         self.hit_canvas_shader.clear_glyphs();
         self.glyph_shader.clear_glyphs();
+        self.set_focus(None);
+    }
+
+    /// Draw the focus ring around the glyph instance with the given index.
+    pub fn set_focus(&mut self, instance_index : Option<usize>) {
+        self.focused = instance_index;
+        self.update_focus_ring();
+    }
+
+    // The focus ring shader has a copy of the focused instance, which has to follow every change to it.
+    fn update_focus_ring(&mut self) {
+        let instance = self.focused.map(|index| self.hit_canvas_shader.instance(index));
+        self.focus_ring_shader.set_instance(instance);
     }
 
     pub fn clear_edges(&mut self) {
         self.edge_shader.clear();
     }
 
-    fn glyph_index(&mut self, glyph : &Glyph) -> Result<usize, JsValue>{
+    fn glyph_index(&mut self, glyph : &Glyph) -> Result<usize, ChartError>{
         let next_index = self.glyph_map.len();
         let entry = self.glyph_map.entry(glyph.uuid);
         Ok(match entry {
             btree_map::Entry::Occupied(oe) => *oe.get(),
             btree_map::Entry::Vacant(ve) => {
-                self.glyph_shader.add_glyph_data(glyph)?;
-                self.glyph_convex_hulls.append(glyph.boundary().iter().copied());
+                self.glyph_shader.add_glyph_data(glyph, &self.tessellation_cache)?;
+                self.glyph_boundaries.append(glyph.boundary().iter().copied());
+                self.glyph_boundaries.append(glyph.star_outline().iter().copied());
                 *ve.insert(next_index)
             }
         })
     }
 
-    pub fn add_glyph_instance(&mut self, glyph_instance : GlyphInstance) -> Result<(), JsValue> {
+    pub fn add_glyph_instance(&mut self, glyph_instance : GlyphInstance) -> Result<(), ChartError> {
         let glyph_index = self.glyph_index(&glyph_instance.glyph)?;
         self.glyph_shader.add_glyph_instance(glyph_instance.clone(), glyph_index);
         self.hit_canvas_shader.add_glyph(glyph_instance, glyph_index)?;
         Ok(())
     }
 
-    pub fn add_edge(&mut self, start : GlyphInstance, end : GlyphInstance, options : &EdgeOptions) -> Result<(), JsValue> {
+    pub fn add_edge(&mut self, start : GlyphInstance, end : GlyphInstance, options : &EdgeOptions) -> Result<(), ChartError> {
         let start_glyph_index = self.glyph_index(&start.glyph)?;
         let end_glyph_index = self.glyph_index(&end.glyph)?;
-        self.edge_shader.add_edge(start, end, start_glyph_index, end_glyph_index, options)?;
+        self.edge_shader.add_edge(start, end, start_glyph_index, end_glyph_index, options, &self.tessellation_cache)?;
         Ok(())
     }
 
-    pub fn object_underneath_pixel(&self, coordinate_system : CoordinateSystem, p : JsPoint) -> Result<Option<u32>, JsValue> {
+    /// Move the nodes and edges that are in transition to where they are at time, in milliseconds since the epoch.
+    pub fn set_time(&mut self, time : f64) {
+        self.glyph_shader.update_transitions(time);
+        self.edge_shader.update_transitions(time);
+        if self.hit_canvas_shader.update_transitions(time) {
+            self.update_focus_ring();
+        }
+    }
+
+    pub fn set_page(&mut self, page : i32) {
+        self.page = page;
+    }
+
+    pub fn set_glyph_page_range(&mut self, instance_index : usize, page_range : [i32; 2]) {
+        self.glyph_shader.set_page_range(instance_index, page_range);
+        self.hit_canvas_shader.set_page_range(instance_index, page_range);
+        self.update_focus_ring();
+    }
+
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    pub fn layer_mut(&mut self, layer : u32) -> Result<&mut Layer, ChartError> {
+        self.check_layer(layer)?;
+        Ok(&mut self.layers[layer as usize])
+    }
+
+    pub fn check_layer(&self, layer : u32) -> Result<(), ChartError> {
+        if (layer as usize) < self.layers.len() {
+            Ok(())
+        } else {
+            Err(ChartError::InvalidInput(format!("No layer with index {}: there are {} layers.", layer, self.layers.len())))
+        }
+    }
+
+    pub fn add_layer(&mut self, layer : Layer) -> Result<u32, ChartError> {
+        let index = checked_index(self.layers.len(), "layers")?;
+        self.layers.push(layer);
+        Ok(index)
+    }
+
+    /// Replaces all layers, the first one becomes the default layer.
+    pub fn set_layers(&mut self, layers : Vec<Layer>) -> Result<(), ChartError> {
+        if layers.is_empty() {
+            return Err(ChartError::invalid_input("There has to be at least one layer."));
+        }
+        checked_index::<u32>(layers.len() - 1, "layers")?;
+        self.layers = layers;
+        Ok(())
+    }
+
+    pub fn set_glyph_layer(&mut self, instance_index : usize, layer : u32) {
+        self.glyph_shader.set_layer(instance_index, layer);
+        self.hit_canvas_shader.set_layer(instance_index, layer);
+        self.update_focus_ring();
+    }
+
+    pub fn set_edge_layer(&mut self, instance_index : usize, layer : u32) {
+        self.edge_shader.set_layer(instance_index, layer);
+    }
+
+    /// Move the node with the given index and recolor it, together with the ends of the given edges.
+    /// Edges are (index, whether the node is at the start, whether the node is at the end).
+    pub fn transition_glyph_instance(&mut self, instance_index : usize, center : Point, colors : &[Vec4; NUM_COLOR_SLOTS], edges : &[(usize, bool, bool)], transition : Transition) {
+        self.glyph_shader.transition_instance(instance_index, center, colors, transition);
+        self.hit_canvas_shader.transition_instance(instance_index, center, transition);
+        self.update_focus_ring();
+        for &(edge_index, at_start, at_end) in edges {
+            if at_start {
+                self.edge_shader.transition_start(edge_index, center, transition);
+            }
+            if at_end {
+                self.edge_shader.transition_end(edge_index, center, transition);
+            }
+        }
+    }
+
+    pub fn transition_edge_color(&mut self, instance_index : usize, color : Vec4, transition : Transition) {
+        self.edge_shader.transition_color(instance_index, color, transition);
+    }
+
+    pub fn set_edge_page_range(&mut self, instance_index : usize, page_range : [i32; 2]) {
+        self.edge_shader.set_page_range(instance_index, page_range);
+    }
+
+}
+
+impl ChartShaders {
+    pub fn object_underneath_pixel(&self, coordinate_system : CoordinateSystem, p : JsPoint) -> Result<Option<u32>, ChartError> {
         self.hit_canvas_shader.object_underneath_pixel(coordinate_system, p.into())
     }
 
-    pub fn draw(&mut self, coordinate_system : CoordinateSystem) -> Result<(), JsValue> {
-        self.glyph_shader.draw(coordinate_system)?;
-        self.edge_shader.draw(coordinate_system, &mut self.glyph_convex_hulls)?;
-        self.hit_canvas_shader.draw(coordinate_system, &mut self.glyph_convex_hulls)?;
+    pub fn draw(&mut self, coordinate_system : CoordinateSystem) -> Result<(), ChartError> {
+        let visible_layers = self.draw_layers(coordinate_system)?;
+        if let Some(index) = self.focused {
+            if self.layers[self.hit_canvas_shader.instance(index).layer() as usize].visible {
+                self.focus_ring_shader.draw(coordinate_system, &mut self.glyph_boundaries, self.page)?;
+            }
+        }
+        self.hit_canvas_shader.draw(coordinate_system, &mut self.glyph_boundaries, self.page, &visible_layers)?;
         Ok(())
     }
+
+    /// Draw the glyphs and edges without the focus ring and without updating the hit canvas, for a second view of the
+    /// same chart.
+    pub fn draw_overview(&mut self, coordinate_system : CoordinateSystem) -> Result<(), ChartError> {
+        self.draw_layers(coordinate_system)?;
+        Ok(())
+    }
+
+    // Returns the visible layers in the order they were drawn.
+    fn draw_layers(&mut self, coordinate_system : CoordinateSystem) -> Result<Vec<u32>, ChartError> {
+        let mut visible_layers : Vec<u32> = (0 .. self.layers.len() as u32).filter(|&i| self.layers[i as usize].visible).collect();
+        visible_layers.sort_by_key(|&i| self.layers[i as usize].z_order);
+        for &layer in &visible_layers {
+            let opacity = self.layers[layer as usize].opacity;
+            self.glyph_shader.draw(coordinate_system, self.page, layer, opacity)?;
+            self.edge_shader.draw(coordinate_system, &mut self.glyph_boundaries, self.page, layer, opacity)?;
+        }
+        Ok(visible_layers)
+    }
 }
 
 
 impl ChartShaders {
-    // fn glyph_data(&mut self, glyph : &Glyph) -> Result<ShaderGlyphHeader, JsValue> {
+    // fn glyph_data(&mut self, glyph : &Glyph) -> Result<ShaderGlyphHeader, ChartError> {
     //     let entry = self.glyph_map.entry(glyph.uuid);
     //     // If btree_map::Entry had a method "or_try_insert(f : K -> Result<V, E>) -> Result<&V, E>" we could use that instead.
     //     match entry {
@@ -164,3 +355,174 @@ impl ChartShaders {
 //     // aGlyphData
 //     glyph : GlyphShaderGlyphHeader
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ops::Range;
+    use lyon::geom::math::point;
+    use crate::arrow::Arrow;
+    use crate::glyph::GlyphBuilder;
+    use crate::shader::GridShader;
+    use crate::shader::mock_context::{MockContext, Contents};
+    use crate::convex_hull::ANGLE_RESOLUTION;
+
+    const BLACK : Vec4 = Vec4 { x : 0.0, y : 0.0, z : 0.0, w : 1.0 };
+    const RED : Vec4 = Vec4 { x : 1.0, y : 0.0, z : 0.0, w : 1.0 };
+
+    struct Chart {
+        glyphs : Vec<Glyph>,
+        arrows : Vec<Arrow>,
+    }
+
+    impl Chart {
+        fn new(num_glyphs : usize) -> Self {
+            let glyphs = (0 .. num_glyphs).map(|i| {
+                let mut builder = GlyphBuilder::from_svg_path(&format!("M 0 0 L {} 0 L 5 {} Z", 5 + i, 10 + i), 1.0, true).unwrap();
+                // Some fixed colors for the palette.
+                if i % 3 == 0 {
+                    builder.boxed(2.0);
+                    builder.set_fill_color(0, &RED).unwrap();
+                }
+                builder.build().unwrap()
+            }).collect();
+            let arrows = vec![Arrow::test_arrow(), Arrow::normal_arrow_with_density(1.0, 1.0, false, false, false, false)];
+            Self { glyphs, arrows }
+        }
+
+        fn instance(&self, glyph : usize, copy : usize) -> GlyphInstance {
+            GlyphInstance::new(self.glyphs[glyph].clone(), point(glyph as f32, copy as f32), 1.0 + copy as f32, BLACK, RED)
+        }
+
+        // Two instances of each of the glyphs, with an edge from each instance to the one before.
+        fn add(&self, shaders : &mut ChartShaders<MockContext>, glyphs : Range<usize>) {
+            for glyph in glyphs {
+                for copy in 0 .. 2 {
+                    shaders.add_glyph_instance(self.instance(glyph, copy)).unwrap();
+                    let previous = if copy == 0 { (glyph.max(1) - 1, 1) } else { (glyph, 0) };
+                    let mut options = EdgeOptions::new();
+                    options.set_start_tip(self.arrows[glyph % 2].clone());
+                    options.set_end_tip(self.arrows[copy].clone());
+                    options.set_dash_pattern(vec![(glyph % 4) as u8 + 1, 2]);
+                    shaders.add_edge(self.instance(previous.0, previous.1), self.instance(glyph, copy), &options).unwrap();
+                }
+            }
+        }
+
+        // The moves start at time 10 and the color change at 20, both take duration milliseconds.
+        fn change(&self, shaders : &mut ChartShaders<MockContext>, duration : f64) {
+            let layer = shaders.add_layer(Layer::new("top".to_string(), 1)).unwrap();
+            shaders.set_focus(Some(3));
+            shaders.transition_glyph_instance(3, point(-1.0, -2.0), &[RED; NUM_COLOR_SLOTS], &[(3, true, false), (4, false, true)], Transition::new(10.0, duration));
+            shaders.set_glyph_page_range(1, [0, 2]);
+            shaders.set_glyph_layer(2, layer);
+            shaders.transition_edge_color(2, RED, Transition::new(20.0, duration));
+            shaders.set_edge_page_range(5, [1, 1]);
+            shaders.set_edge_layer(6, layer);
+        }
+    }
+
+    fn assert_same_contents(actual : &Contents, expected : &Contents) {
+        // The contents are too large to print.
+        assert!(actual.buffers == expected.buffers, "Buffers differ.");
+        assert!(actual.textures == expected.textures, "Textures differ.");
+        assert!(actual.programs == expected.programs, "Programs differ.");
+        assert!(actual.vertex_arrays == expected.vertex_arrays, "Vertex arrays differ.");
+    }
+
+    #[test]
+    fn restore_context_uploads_everything_again() {
+        let chart = Chart::new(20);
+        let context = MockContext::new();
+        let mut shaders = ChartShaders::new(context.clone(), TessellationCache::new()).unwrap();
+        let mut grid_shader = GridShader::new(context.clone()).unwrap();
+        // Uploaded in two parts, the first one ends in the middle of a glyph boundary row.
+        chart.add(&mut shaders, 0 .. 10);
+        shaders.prepare().unwrap();
+        chart.add(&mut shaders, 10 .. 20);
+        chart.change(&mut shaders, 100.0);
+        shaders.prepare().unwrap();
+
+        let before = context.contents();
+        // Glyph boundaries, vertices and palette, arrow headers and paths and dash patterns.
+        assert_eq!(before.textures.len(), 6);
+        assert!(before.textures.iter().all(|texture| texture.rows.len() == texture.height));
+        // Glyph, hit canvas, edge and focus ring instances.
+        assert_eq!(before.buffers.len(), 4);
+        assert!(before.buffers.iter().all(|buffer| !buffer.is_empty()));
+        assert_eq!(before.vertex_arrays.len(), 4);
+        assert_eq!(before.programs.len(), 5);
+
+        context.lose();
+        assert_eq!(shaders.restore_context(context.clone()).unwrap_err(), ChartError::ContextLost);
+        let restored = MockContext::new();
+        shaders.restore_context(restored.clone()).unwrap();
+        grid_shader.restore_context(restored.clone()).unwrap();
+        assert_same_contents(&restored.contents(), &before);
+    }
+
+    #[test]
+    fn changes_after_restore_context() {
+        let chart = Chart::new(20);
+        let cache = TessellationCache::new();
+        let context = MockContext::new();
+        let mut shaders = ChartShaders::new(context.clone(), cache.clone()).unwrap();
+        chart.add(&mut shaders, 0 .. 10);
+        shaders.prepare().unwrap();
+        context.lose();
+        let restored = MockContext::new();
+        shaders.restore_context(restored.clone()).unwrap();
+        chart.add(&mut shaders, 10 .. 20);
+        chart.change(&mut shaders, 100.0);
+        shaders.prepare().unwrap();
+
+        let fresh = MockContext::new();
+        let mut fresh_shaders = ChartShaders::new(fresh.clone(), cache).unwrap();
+        chart.add(&mut fresh_shaders, 0 .. 20);
+        chart.change(&mut fresh_shaders, 100.0);
+        fresh_shaders.prepare().unwrap();
+        assert_same_contents(&restored.contents(), &fresh.contents());
+    }
+
+    #[test]
+    fn clear_glyphs_clears_boundaries() {
+        let chart = Chart::new(3);
+        let mut shaders = ChartShaders::new(MockContext::new(), TessellationCache::new()).unwrap();
+        shaders.add_glyph_instance(chart.instance(1, 0)).unwrap();
+        shaders.prepare().unwrap();
+        shaders.clear_glyphs();
+        shaders.add_glyph_instance(chart.instance(2, 0)).unwrap();
+        shaders.prepare().unwrap();
+        let glyph = &chart.glyphs[2];
+        assert_eq!(shaders.glyph_boundaries.len(), 2 * ANGLE_RESOLUTION);
+        assert_eq!(&shaders.glyph_boundaries.entries()[.. ANGLE_RESOLUTION], &glyph.boundary()[..]);
+        assert_eq!(&shaders.glyph_boundaries.entries()[ANGLE_RESOLUTION ..], &glyph.star_outline()[..]);
+    }
+
+    #[test]
+    fn transition_progress() {
+        let chart = Chart::new(10);
+        let context = MockContext::new();
+        let mut shaders = ChartShaders::new(context.clone(), TessellationCache::new()).unwrap();
+        chart.add(&mut shaders, 0 .. 10);
+        chart.change(&mut shaders, 100.0);
+        let mut contents_at = |time| {
+            shaders.set_time(time);
+            shaders.prepare().unwrap();
+            context.contents().buffers
+        };
+        let start = contents_at(10.0);
+        let middle = contents_at(60.0);
+        let end = contents_at(120.0);
+        assert!(start != middle && middle != end);
+        assert!(contents_at(1000.0) == end);
+
+        // Drawn the same as if there had been no transitions.
+        let finished = MockContext::new();
+        let mut finished_shaders = ChartShaders::new(finished.clone(), TessellationCache::new()).unwrap();
+        chart.add(&mut finished_shaders, 0 .. 10);
+        chart.change(&mut finished_shaders, 0.0);
+        finished_shaders.prepare().unwrap();
+        assert!(finished.contents().buffers == end);
+    }
+}
@@ -12,6 +12,20 @@ float getValueByIndexFrom4ChannelTexture(sampler2D tex, int index){
     return texelFetch(tex, ivec2(col, row), 0)[channel];
 }
 
+vec2 getVec2ByIndexFrom4ChannelTexture(sampler2D tex, int index){
+    int texWidth = textureSize(tex, 0).x;
+    int channel = index % 2;
+    int texOffset = index / 2;
+    int col = texOffset % texWidth;
+    int row = texOffset / texWidth;
+    vec4 pixel = texelFetch(tex, ivec2(col, row), 0);
+    if(channel == 0) {
+        return pixel.xy;
+    } else {
+        return pixel.zw;
+    }
+}
+
 vec4 getValueByIndexFromTexture(sampler2D tex, int index) {
     int texWidth = textureSize(tex, 0).x;
     int col = index % texWidth;
@@ -23,10 +37,14 @@ vec4 getValueByIndexFromTexture(sampler2D tex, int index) {
 
 // layout (std140) uniform Transform {
     uniform mat3x2 uTransformationMatrix;
+    uniform mat3x2 uLattice;
     uniform vec2 uOrigin;
     uniform vec2 uScale;
     uniform float uGlyphScale;
 // };
+uniform int uPage;
+uniform int uLayer;
+uniform float uOpacity;
 
 uniform sampler2D uGlyphBoundaryTexture;
 uniform sampler2D uArrowHeaderTexture;
@@ -38,7 +56,15 @@ in vec4 aPositions; // (start_position, end_position)
 in vec4 aGlyphScales_angle_thickness; // (start_glyph_scale, end_glyph_scale, angle, thickness)
 in ivec4 aStart; // (startGlyph, vec3 startArrow = (NumVertices, HeaderIndex, VerticesIndex) ) 
 in ivec4 aEnd; // (endGlyph, vec3 endArrow = (NumVertices, HeaderIndex, VerticesIndex) )
-in ivec4 aDashPattern; // (dash_length, dash_index, dash_offset, _ )
+in ivec4 aDashPattern; // (dash_length, dash_index, dash_offset, boundary_mode )
+in ivec2 aPageRange; // (first_page, last_page)
+in int aLayer;
+in vec4 aPreviousColor;
+in vec4 aPreviousPositions; // (previous_start_position, previous_end_position)
+in vec3 aProgress; // of the moves of the start and end node and of the color change, eased in and out
+
+// The positions of the ends, set at the start of main.
+vec4 positions;
 
 out vec4 fColor;
 out vec2 vPosition;
@@ -53,7 +79,7 @@ flat out float fInitialAngle;
 
 
 vec2 transformPos(vec2 pos){
-    return uOrigin + (vec2(1.0, -1.0) * uScale) * pos;
+    return uOrigin + (vec2(1.0, -1.0) * uScale) * (uLattice * vec3(pos, 1.0));
 }
 
 vec4 reverseTangent(vec4 pos_tan){
@@ -68,10 +94,13 @@ mat2 rotationMatrix(vec2 direction){
     return mat2(direction, normalVector(direction));
 }
 
+// Each glyph has ANGLE_RES convex hull points followed by ANGLE_RES star outline points.
+// The boundary mode (0 for convex hull, 1 for star outline) picks which to use.
 float glyphBoundaryPoint(int glyph, float angle){
     int glyph_index = (int(angle / (2.0 * M_PI) * float(ANGLE_RES)) + ANGLE_RES) % ANGLE_RES;
-    int total_index = ANGLE_RES * glyph + glyph_index;
-    return uGlyphScale * getValueByIndexFrom4ChannelTexture(uGlyphBoundaryTexture, total_index);
+    int boundary_mode = aDashPattern.w;
+    int total_index = ANGLE_RES * (2 * glyph + boundary_mode) + glyph_index;
+    return uGlyphScale * length(getVec2ByIndexFrom4ChannelTexture(uGlyphBoundaryTexture, total_index));
 }
 
 
@@ -182,8 +211,8 @@ vec4 glyphOffsetCurved(int glyph, float scale, float extra, vec4 pos_tan, float
 
 
 vec2 vertexPositionLinear(){
-    vec2 startPos = transformPos(aPositions.xy);
-    vec2 endPos = transformPos(aPositions.zw);
+    vec2 startPos = transformPos(positions.xy);
+    vec2 endPos = transformPos(positions.zw);
     vec2 tangent = normalize(endPos - startPos);
     float angle = atan(tangent.y, tangent.x);
 
@@ -254,8 +283,8 @@ vec2 positionCurvedArrrow(ivec3 arrow, int glyph, float glyphScale, vec4 posTan,
 }
 
 vec2 vertexPositionCurved(){
-    vec2 startPos = transformPos(aPositions.xy);
-    vec2 endPos = transformPos(aPositions.zw);
+    vec2 startPos = transformPos(positions.xy);
+    vec2 endPos = transformPos(positions.zw);
     vec2 displacement = endPos.xy - startPos.xy;
     float displacement_length = length(displacement);
     float angle = aGlyphScales_angle_thickness.z;
@@ -365,8 +394,22 @@ vec2 vertexPositionCurved(){
     return vec2(0.0, 0.0);
 }
 
+// Not on the current page, or not in the layer being drawn.
+bool hidden(){
+    return uPage < aPageRange.x || uPage > aPageRange.y || aLayer != uLayer;
+}
+
 void main() {
-    fColor = aColor;
+    if(hidden()){
+        // Every vertex of the instance is outside the clip volume, so nothing gets rasterized.
+        gl_Position = vec4(2.0, 2.0, 2.0, 1.0);
+        return;
+    }
+    fColor = mix(aPreviousColor, aColor, aProgress.z) * uOpacity;
+    positions = vec4(
+        mix(aPreviousPositions.xy, aPositions.xy, aProgress.x),
+        mix(aPreviousPositions.zw, aPositions.zw, aProgress.y)
+    );
     fDashPattern = aDashPattern;
     float angle = aGlyphScales_angle_thickness.z;
     vec2 position;
@@ -2,19 +2,22 @@ use std::collections::{BTreeMap, btree_map};
 use uuid::Uuid;
 
 use wasm_bindgen::prelude::*;
-use web_sys::{WebGl2RenderingContext, WebGlVertexArrayObject, WebGlTexture};
+use web_sys::WebGl2RenderingContext;
 
 use lyon::geom::math::{Point, Angle, Vector};
-use lyon::tessellation::{VertexBuffers};
 
 #[allow(unused_imports)]
 use crate::log;
 use crate::vector::{Vec4};
 use crate::shader::Program;
+use crate::shader::gl_resources::{GlResources, TextureFormat};
 use crate::webgl_wrapper::WebGlWrapper;
 
-use crate::glyph::{GlyphInstance};
+use crate::error::{ChartError, checked_index};
+use crate::glyph::{GlyphInstance, GlyphBoundary, ALL_PAGES};
 use crate::arrow::Arrow;
+use crate::tessellation_cache::TessellationCache;
+use crate::animation::{Transition, Transitions};
 
 use crate::shader::attributes::{Format, Type, NumChannels, Attribute, Attributes};
 use crate::shader::data_texture::DataTexture;
@@ -25,25 +28,41 @@ use crate::coordinate_system::CoordinateSystem;
 
 
 const DASH_PATTERN_TEXTURE_WIDTH : usize = 512;
+// One byte per entry, interpolated between entries.
+const DASH_PATTERN_TEXTURE_FORMAT : TextureFormat = TextureFormat {
+    internal_format : WebGl2RenderingContext::R8,
+    base_format : WebGl2RenderingContext::RED,
+    webgl_type : WebGl2RenderingContext::UNSIGNED_BYTE,
+    filter : WebGl2RenderingContext::LINEAR,
+};
 
 const ATTRIBUTES : Attributes = Attributes::new(&[
     Attribute::new("aColor", 4, Type::F32), // color
     Attribute::new("aPositions", 4, Type::F32), // (start_position, end_position)
     Attribute::new("aGlyphScales_angle_thickness", 4, Type::F32), // (start_glyph_scale, end_glyph_scale, angle, thickness)
 
-    Attribute::new("aStart", 4, Type::I16), // (startGlyph, vec3 startArrow = (NumVertices, HeaderIndex, VerticesIndex) )
-    Attribute::new("aEnd", 4, Type::I16), // (endGlyph, vec3 endArrow = (NumVertices, HeaderIndex, VerticesIndex) )
-    Attribute::new("aDashPattern", 4, Type::I16), // (dash_length, dash_index, dash_offset, dash_padding )
+    Attribute::new("aStart", 4, Type::I32), // (startGlyph, vec3 startArrow = (NumVertices, HeaderIndex, VerticesIndex) )
+    Attribute::new("aEnd", 4, Type::I32), // (endGlyph, vec3 endArrow = (NumVertices, HeaderIndex, VerticesIndex) )
+    Attribute::new("aDashPattern", 4, Type::I32), // (dash_length, dash_index, dash_offset, boundary_mode )
+    Attribute::new("aPageRange", 2, Type::I32), // (first_page, last_page)
+    Attribute::new("aLayer", 1, Type::I32),
+    Attribute::new("aPreviousColor", 4, Type::F32),
+    Attribute::new("aPreviousPositions", 4, Type::F32), // (previous_start_position, previous_end_position)
+    Attribute::new("aProgress", 3, Type::F32), // of the moves of the start and end and of the color change
 ]);
 
 #[wasm_bindgen]
 #[derive(Clone, Debug)]
 pub struct EdgeOptions {
-    start_tip : Option<Arrow>, 
-    end_tip : Option<Arrow>,
-    angle : Angle,
-    thickness : f32,
-    dash_pattern : Vec<u8>,
+    pub(crate) start_tip : Option<Arrow>, 
+    pub(crate) end_tip : Option<Arrow>,
+    pub(crate) angle : Angle,
+    pub(crate) thickness : f32,
+    pub(crate) color : Vec4,
+    pub(crate) dash_pattern : Vec<u8>,
+    pub(crate) boundary : GlyphBoundary,
+    pub(crate) page_range : [i32; 2],
+    pub(crate) layer : u32,
 }
 
 #[wasm_bindgen]
@@ -54,7 +73,11 @@ impl EdgeOptions {
             end_tip : None,
             angle : Angle::zero(),
             thickness : 1.0,
-            dash_pattern : vec![]
+            color : Vec4::new(0.0, 0.0, 0.0, 1.0),
+            dash_pattern : vec![],
+            boundary : GlyphBoundary::ConvexHull,
+            page_range : ALL_PAGES,
+            layer : 0,
         }
     }
 
@@ -92,9 +115,31 @@ impl EdgeOptions {
         self.thickness = thickness;
     }
 
+    pub fn set_color(&mut self, color : &Vec4) {
+        self.color = *color;
+    }
+
     pub fn set_dash_pattern(&mut self, dash_pattern : Vec<u8>) {
         self.dash_pattern = dash_pattern;
     }
+
+    pub fn set_boundary(&mut self, boundary : GlyphBoundary) {
+        self.boundary = boundary;
+    }
+
+    /// Only draw the edge on pages first_page through last_page, see Canvas::set_page.
+    pub fn set_page_range(&mut self, first_page : i32, last_page : i32) {
+        self.page_range = [first_page, last_page];
+    }
+
+    pub fn all_pages(&mut self) {
+        self.page_range = ALL_PAGES;
+    }
+
+    /// Index of the layer to draw the edge in, as returned by Canvas::add_layer.
+    pub fn set_layer(&mut self, layer : u32) {
+        self.layer = layer;
+    }
 }
 
 
@@ -110,15 +155,24 @@ struct EdgeInstance {
     angle : f32,
     thickness : f32,
     
-    start_glyph : u16,
+    start_glyph : i32,
     start_arrow : ArrowIndices,
-    end_glyph : u16,
+    end_glyph : i32,
     end_arrow : ArrowIndices,
 
-    dash_length : u16, 
-    dash_index : u16, 
-    dash_offset : u16, 
-    dash_padding : u16,
+    dash_length : i32, 
+    dash_index : i32, 
+    dash_offset : i32, 
+    boundary_mode : i32,
+
+    page_range : [i32; 2],
+    layer : i32,
+
+    previous_color : Vec4,
+    previous_start_position : Point,
+    previous_end_position : Point,
+    // Of start_transitions, end_transitions and color_transitions of the shader.
+    progress : [f32; 3],
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -134,61 +188,58 @@ struct ArrowHeader {
 #[derive(Clone, Copy, Debug, Default)]
 #[repr(C)]
 struct ArrowIndices {
-    num_vertices : u16,
-    header_index : u16,
-    vertices_index : u16
+    num_vertices : i32,
+    header_index : i32,
+    vertices_index : i32
 }
 
-pub struct EdgeShader {
-    webgl : WebGlWrapper,
-    program : Program,
+pub struct EdgeShader<G : GlResources = WebGlWrapper> {
+    webgl : G,
+    program : Program<G>,
     
-    edge_instances : VertexBuffer<EdgeInstance>,
-    attribute_state : Option<WebGlVertexArrayObject>,
+    edge_instances : VertexBuffer<EdgeInstance, G>,
+    start_transitions : Transitions,
+    end_transitions : Transitions,
+    color_transitions : Transitions,
+    attribute_state : Option<G::VertexArray>,
     
     tip_map : BTreeMap<Uuid, ArrowIndices>,
     max_arrow_tip_num_vertices : usize,
-    arrow_header_data : DataTexture<ArrowHeader>,
-    arrow_path_data : DataTexture<Point>,
+    arrow_header_data : DataTexture<ArrowHeader, G>,
+    arrow_path_data : DataTexture<Point, G>,
 
     dash_data : Vec<u8>,
-    dash_texture : Option<WebGlTexture>,
+    dash_texture : Option<G::Texture>,
     dash_texture_num_rows : usize,
-    dash_map : BTreeMap<Vec<u8>, (u16, u16)>,
+    dash_map : BTreeMap<Vec<u8>, (i32, i32)>,
 
     ready : bool,
 }
 
-impl EdgeShader {
-    pub fn new(webgl : WebGlWrapper) -> Result<Self, JsValue> {
+impl<G : GlResources> EdgeShader<G> {
+    pub fn new(webgl : G) -> Result<Self, ChartError> {
         let program = Program::new(
             webgl.clone(), 
             include_str!("edge.vert"),
             include_str!("edge.frag")
         )?;
-        let attribute_state = webgl.create_vertex_array();
         let edge_instances = VertexBuffer::new(webgl.clone());
-        ATTRIBUTES.set_up_vertex_array(&webgl, &program.program, attribute_state.as_ref(), edge_instances.buffer.as_ref())?;
 
         let arrow_header_data = DataTexture::new(webgl.clone(), Format(Type::F32, NumChannels::Four));
         let arrow_path_data = DataTexture::new(webgl.clone(), Format(Type::F32, NumChannels::Two));
-        
-        program.use_program();
-        program.set_uniform_int("uGlyphBoundaryTexture", 0);
-        program.set_uniform_int("uArrowHeaderTexture", 1);
-        program.set_uniform_int("uArrowPathTexture", 2);
-        program.set_uniform_int("uDashPatterns", 3);
 
-        let dash_texture = webgl.create_texture();
         let mut dash_map = BTreeMap::new();
         dash_map.insert(vec![], (0, 0));
 
-        Ok(Self {
+        let mut result = Self {
             webgl,
             program,
 
             edge_instances,
-            attribute_state,
+            start_transitions : Transitions::new(),
+            end_transitions : Transitions::new(),
+            color_transitions : Transitions::new(),
+            attribute_state : None,
 
             tip_map : BTreeMap::new(),
             arrow_header_data,
@@ -196,16 +247,38 @@ impl EdgeShader {
             max_arrow_tip_num_vertices : 0,
             
             dash_data : Vec::new(),
-            dash_texture,
+            dash_texture : None,
             dash_texture_num_rows : 0,
             dash_map,
             ready : false,
-        })
+        };
+        result.set_up_program()?;
+        Ok(result)
+    }
+
+    fn set_up_program(&mut self) -> Result<(), ChartError> {
+        self.attribute_state = self.webgl.create_vertex_array(&self.program.program, &ATTRIBUTES, self.edge_instances.buffer.as_ref())?;
+        self.webgl.set_samplers(&self.program.program, &["uGlyphBoundaryTexture", "uArrowHeaderTexture", "uArrowPathTexture", "uDashPatterns"]);
+        Ok(())
+    }
+
+    pub fn restore_context(&mut self, webgl : G) -> Result<(), ChartError> {
+        self.program.restore_context(webgl.clone())?;
+        self.edge_instances.restore_context(webgl.clone());
+        self.arrow_header_data.restore_context(webgl.clone());
+        self.arrow_path_data.restore_context(webgl.clone());
+        self.webgl = webgl;
+        self.set_up_program()?;
+        // The dash patterns are uploaded again by the next prepare.
+        self.dash_texture = None;
+        self.dash_texture_num_rows = 0;
+        self.ready = false;
+        Ok(())
     }
 
-    fn dash_data(&mut self, dash_pattern : Vec<u8>) -> (u16, u16) {
+    fn dash_data(&mut self, dash_pattern : Vec<u8>) -> Result<(i32, i32), ChartError> {
         let entry = self.dash_map.entry(dash_pattern);
-        match entry {
+        Ok(match entry {
             btree_map::Entry::Occupied(oe) => *oe.get(),
             btree_map::Entry::Vacant(ve) => {
                 let orig_dash_data_len = self.dash_data.len();
@@ -228,10 +301,11 @@ impl EdgeShader {
                 }
                 self.dash_data.extend(&[255]);
                 self.dash_data.resize_with(orig_dash_data_len +  DASH_PATTERN_TEXTURE_WIDTH, ||0);
-                let pattern_len : u16 = dash_pattern.iter().map(|&b| b as u16).sum();
-                *ve.insert((dash_pattern_row as u16, pattern_len))
+                let pattern_len : i32 = dash_pattern.iter().map(|&b| b as i32).sum();
+                let dash_pattern_row = checked_index(dash_pattern_row, "dash patterns")?;
+                *ve.insert((dash_pattern_row, pattern_len))
             }
-        }
+        })
     }
 
     fn ensure_dash_texture_size(&mut self){
@@ -241,56 +315,34 @@ impl EdgeShader {
         }
         self.dash_texture_num_rows = num_rows;
         self.webgl.delete_texture(self.dash_texture.as_ref());
-        self.dash_texture = self.webgl.create_texture();
-        self.webgl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, self.dash_texture.as_ref());
-        self.webgl.tex_storage_2d(
-            WebGl2RenderingContext::TEXTURE_2D,
-            1, // mip levels
-            WebGl2RenderingContext::R8,
-            DASH_PATTERN_TEXTURE_WIDTH as i32, self.dash_texture_num_rows as i32
-        );
-        self.webgl.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, WebGl2RenderingContext::TEXTURE_MAG_FILTER, WebGl2RenderingContext::LINEAR as i32);
-        self.webgl.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, WebGl2RenderingContext::TEXTURE_MIN_FILTER, WebGl2RenderingContext::LINEAR as i32);
-        self.webgl.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, WebGl2RenderingContext::TEXTURE_WRAP_S, WebGl2RenderingContext::CLAMP_TO_EDGE as i32);
-        self.webgl.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, WebGl2RenderingContext::TEXTURE_WRAP_T, WebGl2RenderingContext::CLAMP_TO_EDGE as i32);
+        self.dash_texture = self.webgl.create_texture(DASH_PATTERN_TEXTURE_FORMAT, DASH_PATTERN_TEXTURE_WIDTH, num_rows);
     }
 
-    fn upload_dash_texture_data(&mut self) -> Result<(), JsValue>{
+    fn upload_dash_texture_data(&mut self) -> Result<(), ChartError>{
         self.ensure_dash_texture_size();
         let num_rows = self.dash_data.len() / DASH_PATTERN_TEXTURE_WIDTH;
-        self.webgl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, self.dash_texture.as_ref());
-        unsafe {
-            let array_view = js_sys::Uint8Array::view(&self.dash_data);
-            self.webgl.tex_sub_image_2d_with_i32_and_i32_and_u32_and_type_and_opt_array_buffer_view(
-                WebGl2RenderingContext::TEXTURE_2D, 
-                0, // mip level
-                0, 0, // xoffset, yoffset: i32,
-                DASH_PATTERN_TEXTURE_WIDTH as i32, num_rows as i32, // width, height
-                WebGl2RenderingContext::RED, // format: u32,
-                WebGl2RenderingContext::UNSIGNED_BYTE, // type_: u32,
-                Some(&array_view) // pixels: Option<&Object>
-            )?; 
-        }
-        Ok(())
+        self.webgl.upload_texture_rows(self.dash_texture.as_ref(), DASH_PATTERN_TEXTURE_FORMAT, DASH_PATTERN_TEXTURE_WIDTH, 0, num_rows, &self.dash_data)
     }
 
     pub fn clear(&mut self){
         self.max_arrow_tip_num_vertices = 0;
         self.tip_map.clear();
         self.edge_instances.clear();
+        self.start_transitions.clear();
+        self.end_transitions.clear();
+        self.color_transitions.clear();
         self.arrow_header_data.clear();
         self.arrow_path_data.clear();
         self.ready = false;
     }
 
-    fn arrow_tip_data(&mut self, arrow : &Arrow) -> Result<ArrowIndices, JsValue> {
+    fn arrow_tip_data(&mut self, arrow : &Arrow, tessellation_cache : &TessellationCache) -> Result<ArrowIndices, ChartError> {
         let next_header_index = self.tip_map.len();
         let entry = self.tip_map.entry(arrow.uuid);
         match entry {
             btree_map::Entry::Occupied(oe) => Ok(*oe.get()),
             btree_map::Entry::Vacant(ve) => {
-                let mut buffers: VertexBuffers<Point, u16> = VertexBuffers::new();
-                arrow.tesselate_into_buffers(&mut buffers)?;
+                let buffers = tessellation_cache.arrow(arrow)?;
 
                 let vertices_index = self.arrow_path_data.len();
                 let num_vertices = buffers.indices.len();
@@ -305,9 +357,9 @@ impl EdgeShader {
                 self.max_arrow_tip_num_vertices = self.max_arrow_tip_num_vertices.max(num_vertices);
 
                 let arrow_indices = ArrowIndices {
-                    num_vertices : num_vertices as u16,
-                    header_index : next_header_index as u16,
-                    vertices_index : vertices_index as u16,
+                    num_vertices : checked_index(num_vertices, "arrow tip vertices")?,
+                    header_index : checked_index(next_header_index, "arrow tips")?,
+                    vertices_index : checked_index(vertices_index, "arrow tip vertices")?,
                 };
                 Ok(*ve.insert(arrow_indices))
             }
@@ -321,20 +373,21 @@ impl EdgeShader {
         start_glyph_id : usize,
         end_glyph_id : usize,
         options : &EdgeOptions,
+        tessellation_cache : &TessellationCache,
         // start_tip : Option<&Arrow>, end_tip : Option<&Arrow>,
         // angle : Angle,
         // thickness : f32,
         // dash_pattern : &[u8],
-    ) -> Result<(), JsValue> {
-        let start_arrow = options.start_tip.as_ref().map(|tip| self.arrow_tip_data(tip)).unwrap_or_else(|| Ok(Default::default()))?;
-        let end_arrow = options.end_tip.as_ref().map(|tip| self.arrow_tip_data(tip)).unwrap_or_else(|| Ok(Default::default()))?;
-        let start_glyph_idx = start_glyph_id as u16;
-        let end_glyph_idx = end_glyph_id as u16;
-        let (dash_index, dash_length) = self.dash_data(options.dash_pattern.to_vec());
+    ) -> Result<(), ChartError> {
+        let start_arrow = options.start_tip.as_ref().map(|tip| self.arrow_tip_data(tip, tessellation_cache)).unwrap_or_else(|| Ok(Default::default()))?;
+        let end_arrow = options.end_tip.as_ref().map(|tip| self.arrow_tip_data(tip, tessellation_cache)).unwrap_or_else(|| Ok(Default::default()))?;
+        let start_glyph_idx = checked_index(start_glyph_id, "glyphs")?;
+        let end_glyph_idx = checked_index(end_glyph_id, "glyphs")?;
+        let (dash_index, dash_length) = self.dash_data(options.dash_pattern.to_vec())?;
 
         self.ready = false;
         self.edge_instances.push(EdgeInstance {
-            color : Vec4::new(0.0, 0.0, 0.0, 1.0),
+            color : options.color,
             start_position : start.center,
             end_position : end.center,
             start_glyph : start_glyph_idx,
@@ -350,15 +403,81 @@ impl EdgeShader {
             dash_length,
             dash_index,
             dash_offset : 0,
-            dash_padding : 0,
+            boundary_mode : options.boundary as i32,
+            page_range : options.page_range,
+            layer : options.layer as i32,
+
+            previous_color : options.color,
+            previous_start_position : start.center,
+            previous_end_position : end.center,
+            progress : [1.0; 3],
         });
+        self.start_transitions.push();
+        self.end_transitions.push();
+        self.color_transitions.push();
         Ok(())
     }
 
-    fn prepare(&mut self) -> Result<(), JsValue> {
+    pub fn set_page_range(&mut self, instance_index : usize, page_range : [i32; 2]) {
+        self.edge_instances[instance_index].page_range = page_range;
+        self.ready = false;
+    }
+
+    /// Follow the start node of the edge as it moves, see GlyphShader::transition_instance.
+    pub fn transition_start(&mut self, instance_index : usize, position : Point, transition : Transition) {
+        let t = self.start_transitions.start(instance_index, transition);
+        let instance = &mut self.edge_instances[instance_index];
+        instance.previous_start_position = instance.previous_start_position.lerp(instance.start_position, t);
+        instance.start_position = position;
+        instance.progress[0] = transition.progress(transition.start_time);
+        self.ready = false;
+    }
+
+    pub fn transition_end(&mut self, instance_index : usize, position : Point, transition : Transition) {
+        let t = self.end_transitions.start(instance_index, transition);
+        let instance = &mut self.edge_instances[instance_index];
+        instance.previous_end_position = instance.previous_end_position.lerp(instance.end_position, t);
+        instance.end_position = position;
+        instance.progress[1] = transition.progress(transition.start_time);
+        self.ready = false;
+    }
+
+    pub fn transition_color(&mut self, instance_index : usize, color : Vec4, transition : Transition) {
+        let t = self.color_transitions.start(instance_index, transition);
+        let instance = &mut self.edge_instances[instance_index];
+        instance.previous_color = instance.previous_color.lerp(instance.color, t);
+        instance.color = color;
+        instance.progress[2] = transition.progress(transition.start_time);
+        self.ready = false;
+    }
+
+    /// Draw the edges whose ends are moving or whose colors are changing where they are at time.
+    pub fn update_transitions(&mut self, time : f64) {
+        let instances = &mut self.edge_instances;
+        let mut changed = false;
+        let mut transitions = [&mut self.start_transitions, &mut self.end_transitions, &mut self.color_transitions];
+        for (i, transitions) in transitions.iter_mut().enumerate() {
+            transitions.update(time, |index, progress| {
+                instances[index].progress[i] = progress;
+                changed = true;
+            });
+        }
+        self.ready &= !changed;
+    }
+
+    pub fn set_layer(&mut self, instance_index : usize, layer : u32) {
+        self.edge_instances[instance_index].layer = layer as i32;
+        self.ready = false;
+    }
+
+    /// Upload the edges, arrow tips and dash patterns that changed.
+    pub fn prepare(&mut self) -> Result<(), ChartError> {
+        self.arrow_header_data.prepare()?;
+        self.arrow_path_data.prepare()?;
         if self.ready  {
             return Ok(());
         }
+        self.ready = true;
         self.edge_instances.prepare();
 
         if !self.dash_data.is_empty() {
@@ -366,12 +485,16 @@ impl EdgeShader {
         }
         Ok(())
     }
+}
 
+impl EdgeShader {
 
-    pub fn draw(&mut self, coordinate_system : CoordinateSystem, glyph_boundary_data : &mut DataTexture<Vector>) -> Result<(), JsValue> {
+    /// Draw the edges in the given layer.
+    pub fn draw(&mut self, coordinate_system : CoordinateSystem, glyph_boundary_data : &mut DataTexture<Vector>, page : i32, layer : u32, opacity : f32) -> Result<(), ChartError> {
         if self.edge_instances.is_empty() {
             return Ok(());
         }
+        self.prepare()?;
         self.program.use_program();
         glyph_boundary_data.bind(WebGl2RenderingContext::TEXTURE0)?;
         self.arrow_header_data.bind(WebGl2RenderingContext::TEXTURE1)?;
@@ -380,13 +503,16 @@ impl EdgeShader {
         self.webgl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, self.dash_texture.as_ref());
 
         self.webgl.bind_vertex_array(self.attribute_state.as_ref());
-        self.prepare()?;
 
         
         self.program.set_uniform_transform("uTransformationMatrix", coordinate_system.transform);
+        self.program.set_uniform_transform("uLattice", coordinate_system.lattice);
         self.program.set_uniform_point("uOrigin", coordinate_system.origin);
         self.program.set_uniform_vector("uScale", coordinate_system.scale);
         self.program.set_uniform_float("uGlyphScale", coordinate_system.glyph_scale);
+        self.program.set_uniform_int("uPage", page);
+        self.program.set_uniform_int("uLayer", layer as i32);
+        self.program.set_uniform_float("uOpacity", opacity);
         
         self.webgl.draw_arrays_instanced(
             WebGl2RenderingContext::TRIANGLES,
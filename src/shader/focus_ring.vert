@@ -0,0 +1,56 @@
+#version 300 es
+#define ANGLE_RES 180 // should be same as ANGLE_RESOLUTION
+// layout (std140) uniform Transform {
+    uniform mat3x2 uTransformationMatrix;
+    uniform mat3x2 uLattice;
+    uniform vec2 uOrigin;
+    uniform vec2 uScale;
+    uniform float uGlyphScale;
+// };
+uniform int uPage;
+uniform float uGap; // between the convex hull and the ring, in pixels
+uniform float uThickness;
+uniform sampler2D uGlyphBoundaryTexture;
+
+in vec2 aPosition;
+in float aScale;
+in uvec2 aGlyphIndex;  // (index, layer)
+in ivec2 aPageRange; // (first_page, last_page)
+in vec2 aPreviousPosition;
+in float aProgress; // of the move from the previous position, eased in and out
+
+vec2 getVec2ByIndexFrom4ChannelTexture(sampler2D tex, int index){
+    int texWidth = textureSize(tex, 0).x;
+    int channel = index % 2;
+    int texOffset = index / 2;
+    int col = texOffset % texWidth;
+    int row = texOffset / texWidth;
+    vec4 pixel = texelFetch(tex, ivec2(col, row), 0);
+    if(channel == 0) {
+        return pixel.xy;
+    } else {
+        return pixel.zw;
+    }
+}
+
+vec2 glyphBoundaryPointVertex(uint glyph, int vertexID){
+    // Each glyph has ANGLE_RES convex hull points followed by ANGLE_RES star outline points.
+    int total_index = 2 * ANGLE_RES * int(glyph) + vertexID;
+    return uGlyphScale * aScale * getVec2ByIndexFrom4ChannelTexture(uGlyphBoundaryTexture, total_index);
+}
+
+void main() {
+    if(uPage < aPageRange.x || uPage > aPageRange.y){
+        // Every vertex is outside the clip volume, so nothing gets rasterized.
+        gl_Position = vec4(2.0, 2.0, 2.0, 1.0);
+        return;
+    }
+    // A triangle strip going around the convex hull, alternating between the inner and outer edge of the ring.
+    vec2 boundaryPoint = glyphBoundaryPointVertex(aGlyphIndex.x, (gl_VertexID / 2) % ANGLE_RES);
+    float offset = uGap + ((gl_VertexID % 2 == 0) ? 0.0 : uThickness);
+    float radius = length(boundaryPoint);
+    vec2 vertexPosition = radius > 0.0 ? boundaryPoint * (1.0 + offset / radius) : vec2(0.0);
+    vec2 position = mix(aPreviousPosition, aPosition, aProgress);
+    vec2 transformedPosition = uOrigin +  (vec2(1.0, -1.0) * uScale) * (uLattice * vec3(position, 1.0));
+    gl_Position = vec4(uTransformationMatrix * vec3(transformedPosition + vertexPosition, 1.0), 0.0, 1.0);
+}
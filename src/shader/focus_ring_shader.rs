@@ -0,0 +1,120 @@
+use web_sys::WebGl2RenderingContext;
+
+use lyon::geom::math::Vector;
+
+#[allow(unused_imports)]
+use crate::log;
+
+use crate::webgl_wrapper::WebGlWrapper;
+use crate::shader::Program;
+use crate::shader::gl_resources::GlResources;
+use crate::shader::data_texture::DataTexture;
+use crate::shader::vertex_buffer::VertexBuffer;
+use crate::shader::hit_canvas_shader::{ATTRIBUTES, ShaderGlyphInstance};
+
+use crate::error::ChartError;
+use crate::vector::Vec4;
+use crate::convex_hull::ANGLE_RESOLUTION;
+use crate::coordinate_system::CoordinateSystem;
+
+
+/// Draws a ring around the convex hull of the focused node.
+pub struct FocusRingShader<G : GlResources = WebGlWrapper> {
+    webgl : G,
+    program : Program<G>,
+    attribute_state : Option<G::VertexArray>,
+    // The focused instance copied from the hit canvas, or nothing.
+    instance : VertexBuffer<ShaderGlyphInstance, G>,
+    color : Vec4,
+    gap : f32,
+    thickness : f32,
+}
+
+impl<G : GlResources> FocusRingShader<G> {
+    pub fn new(webgl : G) -> Result<Self, ChartError> {
+        let program = Program::new(
+            webgl.clone(),
+            include_str!("focus_ring.vert"),
+            r#"#version 300 es
+                precision highp float;
+                uniform vec4 uColor;
+                out vec4 outColor;
+                void main() {
+                    outColor = uColor;
+                }
+            "#
+        )?;
+        let instance = VertexBuffer::new(webgl.clone());
+        let mut result = Self {
+            webgl,
+            program,
+            attribute_state : None,
+            instance,
+            color : Vec4::new(0.1, 0.4, 0.9, 1.0),
+            gap : 2.0,
+            thickness : 2.0,
+        };
+        result.set_up_program()?;
+        Ok(result)
+    }
+
+    fn set_up_program(&mut self) -> Result<(), ChartError> {
+        self.attribute_state = self.webgl.create_vertex_array(&self.program.program, &ATTRIBUTES, self.instance.buffer.as_ref())?;
+        self.webgl.set_samplers(&self.program.program, &["uGlyphBoundaryTexture"]);
+        Ok(())
+    }
+
+    pub fn restore_context(&mut self, webgl : G) -> Result<(), ChartError> {
+        self.program.restore_context(webgl.clone())?;
+        self.instance.restore_context(webgl.clone());
+        self.webgl = webgl;
+        self.set_up_program()
+    }
+
+    pub(in crate::shader) fn set_instance(&mut self, instance : Option<ShaderGlyphInstance>) {
+        self.instance.clear();
+        if let Some(instance) = instance {
+            self.instance.push(instance);
+        }
+    }
+
+    /// Gap between the convex hull and the ring and thickness of the ring, in pixels.
+    pub fn set_style(&mut self, color : Vec4, gap : f32, thickness : f32) {
+        self.color = color;
+        self.gap = gap;
+        self.thickness = thickness;
+    }
+
+    pub fn prepare(&mut self) {
+        self.instance.prepare();
+    }
+}
+
+impl FocusRingShader {
+
+    pub fn draw(&mut self, coordinate_system : CoordinateSystem, glyph_boundary_data : &mut DataTexture<Vector>, page : i32) -> Result<(), ChartError> {
+        if self.instance.is_empty() {
+            return Ok(());
+        }
+        self.program.use_program();
+        self.webgl.bind_vertex_array(self.attribute_state.as_ref());
+        self.prepare();
+        glyph_boundary_data.bind(WebGl2RenderingContext::TEXTURE0)?;
+
+        self.program.set_uniform_transform("uTransformationMatrix", coordinate_system.transform);
+        self.program.set_uniform_transform("uLattice", coordinate_system.lattice);
+        self.program.set_uniform_point("uOrigin", coordinate_system.origin);
+        self.program.set_uniform_vector("uScale", coordinate_system.scale);
+        self.program.set_uniform_float("uGlyphScale", coordinate_system.glyph_scale);
+        self.program.set_uniform_int("uPage", page);
+        self.program.set_uniform_float("uGap", self.gap);
+        self.program.set_uniform_float("uThickness", self.thickness);
+        self.program.set_uniform_vec4("uColor", self.color);
+
+        // Two vertices per convex hull point, and the first two again to close the ring.
+        let num_vertices = 2 * (ANGLE_RESOLUTION as i32 + 1);
+        self.webgl.draw_arrays_instanced(WebGl2RenderingContext::TRIANGLE_STRIP, 0, num_vertices, 1);
+        self.webgl.bind_vertex_array(None);
+        Ok(())
+    }
+}
@@ -0,0 +1,126 @@
+use web_sys::{WebGl2RenderingContext, WebGlBuffer, WebGlTexture, WebGlProgram, WebGlVertexArrayObject};
+
+#[allow(unused_imports)]
+use crate::log;
+use crate::error::ChartError;
+use crate::webgl_wrapper::WebGlWrapper;
+use crate::shader::program::build_program;
+use crate::shader::attributes::Attributes;
+
+
+/// How a texture is stored and sampled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TextureFormat {
+    pub internal_format : u32,
+    pub base_format : u32,
+    pub webgl_type : u32,
+    pub filter : u32,
+}
+
+/// The calls that create the buffers, textures, programs and vertex arrays of the shaders and fill them with data.
+/// After the context is lost all of these are made again on the new context, drawing uses the WebGlWrapper directly.
+pub trait GlResources : Clone {
+    type Buffer;
+    type Texture;
+    type Program;
+    type VertexArray;
+
+    fn create_buffer(&self) -> Option<Self::Buffer>;
+    /// Reserve size bytes for the buffer, discarding its contents.
+    fn allocate_buffer(&self, buffer : Option<&Self::Buffer>, size : usize);
+    fn upload_buffer(&self, buffer : Option<&Self::Buffer>, offset : usize, data : &[u8]);
+
+    /// A width by height texture with undefined contents.
+    fn create_texture(&self, format : TextureFormat, width : usize, height : usize) -> Option<Self::Texture>;
+    fn delete_texture(&self, texture : Option<&Self::Texture>);
+    /// Replace num_rows rows starting at first_row with data. For float formats data has to be 4 byte aligned.
+    fn upload_texture_rows(&self, texture : Option<&Self::Texture>, format : TextureFormat, width : usize, first_row : usize, num_rows : usize, data : &[u8]) -> Result<(), ChartError>;
+
+    fn build_program(&self, vertex_shader : &str, fragment_shader : &str) -> Result<Self::Program, ChartError>;
+    /// Point the sampler uniforms at texture units 0, 1, ... in order.
+    fn set_samplers(&self, program : &Self::Program, samplers : &[&str]);
+    /// A vertex array that reads the per instance attributes from the buffer.
+    fn create_vertex_array(&self, program : &Self::Program, attributes : &Attributes, buffer : Option<&Self::Buffer>) -> Result<Option<Self::VertexArray>, ChartError>;
+}
+
+impl GlResources for WebGlWrapper {
+    type Buffer = WebGlBuffer;
+    type Texture = WebGlTexture;
+    type Program = WebGlProgram;
+    type VertexArray = WebGlVertexArrayObject;
+
+    fn create_buffer(&self) -> Option<WebGlBuffer> {
+        self.inner.create_buffer()
+    }
+
+    fn allocate_buffer(&self, buffer : Option<&WebGlBuffer>, size : usize) {
+        self.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, buffer);
+        self.buffer_data_with_i32(WebGl2RenderingContext::ARRAY_BUFFER, size as i32, WebGl2RenderingContext::STATIC_DRAW);
+    }
+
+    fn upload_buffer(&self, buffer : Option<&WebGlBuffer>, offset : usize, data : &[u8]) {
+        self.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, buffer);
+        self.buffer_sub_data_with_i32_and_u8_array(WebGl2RenderingContext::ARRAY_BUFFER, offset as i32, data);
+    }
+
+    fn create_texture(&self, format : TextureFormat, width : usize, height : usize) -> Option<WebGlTexture> {
+        let texture = self.inner.create_texture();
+        self.bind_texture(WebGl2RenderingContext::TEXTURE_2D, texture.as_ref());
+        self.tex_storage_2d(
+            WebGl2RenderingContext::TEXTURE_2D,
+            1, // mip levels
+            format.internal_format,
+            width as i32, height as i32
+        );
+        self.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, WebGl2RenderingContext::TEXTURE_MAG_FILTER, format.filter as i32);
+        self.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, WebGl2RenderingContext::TEXTURE_MIN_FILTER, format.filter as i32);
+        self.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, WebGl2RenderingContext::TEXTURE_WRAP_S, WebGl2RenderingContext::CLAMP_TO_EDGE as i32);
+        self.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, WebGl2RenderingContext::TEXTURE_WRAP_T, WebGl2RenderingContext::CLAMP_TO_EDGE as i32);
+        texture
+    }
+
+    fn delete_texture(&self, texture : Option<&WebGlTexture>) {
+        self.inner.delete_texture(texture);
+    }
+
+    fn upload_texture_rows(&self, texture : Option<&WebGlTexture>, format : TextureFormat, width : usize, first_row : usize, num_rows : usize, data : &[u8]) -> Result<(), ChartError> {
+        self.bind_texture(WebGl2RenderingContext::TEXTURE_2D, texture);
+        // The views borrow the wasm memory, nothing may allocate until the upload is done.
+        let view : js_sys::Object = unsafe {
+            if format.webgl_type == WebGl2RenderingContext::FLOAT {
+                debug_assert_eq!(data.as_ptr() as usize % 4, 0);
+                js_sys::Float32Array::view_mut_raw(data.as_ptr() as *mut f32, data.len() / 4).into()
+            } else {
+                js_sys::Uint8Array::view_mut_raw(data.as_ptr() as *mut u8, data.len()).into()
+            }
+        };
+        self.tex_sub_image_2d_with_i32_and_i32_and_u32_and_type_and_opt_array_buffer_view(
+            WebGl2RenderingContext::TEXTURE_2D,
+            0, // mip level
+            0, first_row as i32, // xoffset, yoffset
+            width as i32, num_rows as i32, // width, height
+            format.base_format,
+            format.webgl_type,
+            Some(&view)
+        )?;
+        Ok(())
+    }
+
+    fn build_program(&self, vertex_shader : &str, fragment_shader : &str) -> Result<WebGlProgram, ChartError> {
+        build_program(self, vertex_shader, fragment_shader)
+    }
+
+    fn set_samplers(&self, program : &WebGlProgram, samplers : &[&str]) {
+        self.use_program(Some(program));
+        for (unit, &name) in samplers.iter().enumerate() {
+            let loc = self.get_uniform_location(program, name);
+            self.uniform1iv_with_i32_array(loc.as_ref(), &[unit as i32]);
+        }
+    }
+
+    fn create_vertex_array(&self, program : &WebGlProgram, attributes : &Attributes, buffer : Option<&WebGlBuffer>) -> Result<Option<WebGlVertexArrayObject>, ChartError> {
+        let vertex_array = self.inner.create_vertex_array();
+        attributes.set_up_vertex_array(self, program, vertex_array.as_ref(), buffer)?;
+        Ok(vertex_array)
+    }
+}
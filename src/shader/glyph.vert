@@ -1,44 +1,40 @@
 #version 300 es
 // layout (std140) uniform Transform {
     uniform mat3x2 uTransformationMatrix;
+    uniform mat3x2 uLattice;
     uniform vec2 uOrigin;
     uniform vec2 uScale;    
     uniform float uGlyphScale;
 // };
-uniform sampler2D uGlyphDataTexture;
+uniform int uPage;
+uniform int uLayer;
+uniform float uOpacity;
+uniform sampler2D uGlyphDataTexture; // (position, colorRef, _)
+uniform sampler2D uGlyphPaletteTexture;
 
 in vec2 aPosition;
 in float aScale;
-in uvec4 aColors;
-in uvec4 aGlyphData; // (index, num_fill_vertices, num_stroke_vertices, _)
+in uvec4 aColors01; // color slots 0 and 1
+in uvec4 aColors23; // color slots 2 and 3
+in uvec4 aGlyphData; // (index, num_fill_vertices, num_stroke_vertices, layer)
+in ivec2 aPageRange; // (first_page, last_page)
+in vec2 aPreviousPosition;
+in uvec4 aPreviousColors01;
+in uvec4 aPreviousColors23;
+in float aProgress; // of the change from the previous position and colors, eased in and out
 
 flat out vec4 fColor;
 
 vec4 uintColorToVec4(uvec2 color){
     float field1 = float(color[0] & 255u)/255.0;
-    color[0] >>= 4;
+    color[0] >>= 8;
     float field2 = float(color[0] & 255u)/255.0;
-    // color >>= 4;
     float field3 = float(color[1] & 255u)/255.0;
-    color[1] >>= 4;
+    color[1] >>= 8;
     float field4 = float(color[1] & 255u)/255.0;
     return vec4(field1, field2, field3, field4);
 }
 
-void setColor(uint vertexID, uvec2 numVertices){
-    if(vertexID < numVertices[0]) {
-        fColor = uintColorToVec4(aColors.xy);
-        return;
-    }
-    vertexID -= numVertices[0];
-    if(vertexID < numVertices[1]) {
-        fColor = uintColorToVec4(aColors.zw);
-        return;
-    }
-    vertexID -= numVertices[1];
-}
-
-
 vec4 getValueByIndexFromTexture(sampler2D tex, uint index) {
     uint texWidth = uint(textureSize(tex, 0).x);
     int col = int(index % texWidth);
@@ -46,6 +42,24 @@ vec4 getValueByIndexFromTexture(sampler2D tex, uint index) {
     return texelFetch(tex, ivec2(col, row), 0);
 }
 
+vec4 slotColor(int slot){
+    switch(slot){
+        case 0: return mix(uintColorToVec4(aPreviousColors01.xy), uintColorToVec4(aColors01.xy), aProgress);
+        case 1: return mix(uintColorToVec4(aPreviousColors01.zw), uintColorToVec4(aColors01.zw), aProgress);
+        case 2: return mix(uintColorToVec4(aPreviousColors23.xy), uintColorToVec4(aColors23.xy), aProgress);
+        default: return mix(uintColorToVec4(aPreviousColors23.zw), uintColorToVec4(aColors23.zw), aProgress);
+    }
+}
+
+// colorRef < 0 refers to instance color slot -colorRef - 1, otherwise it is an index into the palette.
+void setColor(float colorRef){
+    if(colorRef < 0.0) {
+        fColor = slotColor(int(-colorRef) - 1);
+    } else {
+        fColor = getValueByIndexFromTexture(uGlyphPaletteTexture, uint(colorRef));
+    }
+}
+
 vec2 getVertexPosition() {
     uvec4 glyphData =  aGlyphData * 3u;
     uint glyphIndex = glyphData[0];
@@ -53,14 +67,27 @@ vec2 getVertexPosition() {
     uint numStrokeVertices = glyphData[2];
     uint vertexID = uint(gl_VertexID);
     if(vertexID < numFillVertices + numStrokeVertices){
-        setColor(vertexID, uvec2(numFillVertices, numStrokeVertices));
-        return getValueByIndexFromTexture(uGlyphDataTexture, glyphIndex + vertexID).xy * aScale;
+        vec4 vertex = getValueByIndexFromTexture(uGlyphDataTexture, glyphIndex + vertexID);
+        setColor(vertex.z);
+        return vertex.xy * aScale;
     }
     return vec2(0.0, 0.0); // degenerate vertex
 }
 
+// Not on the current page, or not in the layer being drawn.
+bool hidden(){
+    return uPage < aPageRange.x || uPage > aPageRange.y || int(aGlyphData.w) != uLayer;
+}
+
 void main() {
+    if(hidden()){
+        // Every vertex of the instance is outside the clip volume, so nothing gets rasterized.
+        gl_Position = vec4(2.0, 2.0, 2.0, 1.0);
+        return;
+    }
     vec2 vertexPosition = getVertexPosition();
-    vec2 transformedPosition = uOrigin +  (vec2(1.0, -1.0) * uScale) * aPosition;
+    fColor *= uOpacity;
+    vec2 position = mix(aPreviousPosition, aPosition, aProgress);
+    vec2 transformedPosition = uOrigin +  (vec2(1.0, -1.0) * uScale) * (uLattice * vec3(position, 1.0));
     gl_Position = vec4(uTransformationMatrix * vec3(transformedPosition + uGlyphScale * vertexPosition, 1.0), 0.0, 1.0);
 }
\ No newline at end of file
@@ -1,11 +1,4 @@
-use std::convert::TryInto;
-
-
-use wasm_bindgen::JsValue;
-use web_sys::{
-    WebGl2RenderingContext, 
-    WebGlVertexArrayObject,
-};
+use web_sys::WebGl2RenderingContext;
 
 use lyon::geom::math::{Point};
 
@@ -15,13 +8,17 @@ use lyon::tessellation::{VertexBuffers};
 use crate::log;
 use crate::webgl_wrapper::WebGlWrapper;
 use crate::shader::Program;
+use crate::shader::gl_resources::GlResources;
 use crate::vector::Vec4;
+use crate::error::{ChartError, checked_index};
 
-use crate::glyph::{GlyphInstance, Glyph};
+use crate::glyph::{GlyphInstance, Glyph, GlyphVertex, ComponentColor, NUM_COLOR_SLOTS};
+use crate::tessellation_cache::{TessellationCache, GLYPH_TESSELLATION_SCALE};
 
 use crate::shader::attributes::{Format, Type, NumChannels,  Attribute, Attributes};
 use crate::shader::data_texture::DataTexture;
 use crate::shader::vertex_buffer::VertexBuffer;
+use crate::animation::{Transition, Transitions};
 
 use crate::coordinate_system::CoordinateSystem;
 
@@ -29,18 +26,25 @@ use crate::coordinate_system::CoordinateSystem;
 const ATTRIBUTES : Attributes = Attributes::new(&[
     Attribute::new("aPosition", 2, Type::F32),
     Attribute::new("aScale", 1, Type::F32),
-    Attribute::new("aColors",4, Type::U16),
-    Attribute::new("aGlyphData", 4, Type::U16), // ShaderGlyphHeader: (index, num_fill_vertices, num_stroke_vertices, padding)
+    Attribute::new("aColors01", 4, Type::U16), // color slots 0 and 1, each packed as (rg, ba)
+    Attribute::new("aColors23", 4, Type::U16), // color slots 2 and 3
+    Attribute::new("aGlyphData", 4, Type::U32), // ShaderGlyphHeader: (index, num_fill_vertices, num_stroke_vertices, layer)
+    Attribute::new("aPageRange", 2, Type::I32), // (first_page, last_page)
+    Attribute::new("aPreviousPosition", 2, Type::F32),
+    Attribute::new("aPreviousColors01", 4, Type::U16),
+    Attribute::new("aPreviousColors23", 4, Type::U16),
+    Attribute::new("aProgress", 1, Type::F32), // of the change from the previous position and colors
 ]);
 
 
 #[derive(Clone, Copy, Debug)]
 #[repr(C)]
 struct ShaderGlyphHeader {
-    index : u16,
-    num_fill_triangles : u16,
-    num_stroke_triangles : u16,
-    padding : u16,
+    index : u32,
+    num_fill_triangles : u32,
+    num_stroke_triangles : u32,
+    // Per instance, the glyph_map entries have 0 here.
+    layer : u32,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -48,14 +52,35 @@ struct ShaderGlyphHeader {
 struct ShaderGlyphInstance {
     position : Point,
     scale : f32,
-    fill_color : [u16;2],
-    stroke_color : [u16;2],
+    colors : [[u16;2]; NUM_COLOR_SLOTS],
     
     // aGlyphData
-    glyph : ShaderGlyphHeader
+    glyph : ShaderGlyphHeader,
+    page_range : [i32; 2],
+
+    previous_position : Point,
+    previous_colors : [[u16;2]; NUM_COLOR_SLOTS],
+    progress : f32,
+}
+
+// Entry of uGlyphDataTexture.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+struct ShaderGlyphVertex {
+    position : Point,
+    // Negative values -(slot + 1) refer to an instance color slot, 
+    // nonnegative values are indices into uGlyphPaletteTexture.
+    color : f32,
+    padding : f32,
 }
 
 
+fn u8_array_to_vec4(c : [u16;2]) -> Vec4 {
+    let [x, y] = c[0].to_le_bytes();
+    let [z, w] = c[1].to_le_bytes();
+    Vec4::new(x as f32 / 255.0, y as f32 / 255.0, z as f32 / 255.0, w as f32 / 255.0)
+}
+
 fn vec4_to_u8_array(v : Vec4) -> [u16;2] {
     [u16::from_le_bytes([
         (v.x * 255.0) as u8, 
@@ -67,27 +92,37 @@ fn vec4_to_u8_array(v : Vec4) -> [u16;2] {
     ])]
 }
 
+fn pack_colors(colors : &[Vec4; NUM_COLOR_SLOTS]) -> [[u16;2]; NUM_COLOR_SLOTS] {
+    let mut packed = [[0; 2]; NUM_COLOR_SLOTS];
+    for (packed, &color) in packed.iter_mut().zip(colors.iter()) {
+        *packed = vec4_to_u8_array(color);
+    }
+    packed
+}
 
 
-pub struct GlyphShader {
-    webgl : WebGlWrapper,
-    pub(in crate::shader) program : Program,
+pub struct GlyphShader<G : GlResources = WebGlWrapper> {
+    webgl : G,
+    pub(in crate::shader) program : Program<G>,
     glyph_map : Vec<ShaderGlyphHeader>,
-    glyph_instances : VertexBuffer<ShaderGlyphInstance>,
-    attribute_state : Option<WebGlVertexArrayObject>,
+    glyph_instances : VertexBuffer<ShaderGlyphInstance, G>,
+    transitions : Transitions,
+    attribute_state : Option<G::VertexArray>,
 
     // Vertices has its length padded to a multiple of DATA_ROW_SIZE so that it will fit correctly into the data_texture
     // so we need to separately store the number of actually used entries separately.
     max_glyph_num_triangles : usize,
-    vertices_data : DataTexture<Point>,
+    vertices_data : DataTexture<ShaderGlyphVertex, G>,
+    // Fixed component colors
+    palette_data : DataTexture<Vec4, G>,
 
     ready : bool,
 }
 
 
 
-impl GlyphShader {
-    pub fn new(webgl : WebGlWrapper) -> Result<Self, JsValue> {
+impl<G : GlResources> GlyphShader<G> {
+    pub fn new(webgl : G) -> Result<Self, ChartError> {
         let program = Program::new(
             webgl.clone(), 
             include_str!("glyph.vert"),
@@ -103,82 +138,157 @@ impl GlyphShader {
         )?;
 
         let glyph_instances = VertexBuffer::new(webgl.clone());
-        let attribute_state = webgl.create_vertex_array();
-
-        ATTRIBUTES.set_up_vertex_array(&webgl, &program.program, attribute_state.as_ref(), glyph_instances.buffer.as_ref())?;
+        let vertices_data = DataTexture::new(webgl.clone(), Format(Type::F32, NumChannels::Four));
+        let palette_data = DataTexture::new(webgl.clone(), Format(Type::F32, NumChannels::Four));
 
-        let vertices_data = DataTexture::new(webgl.clone(), Format(Type::F32, NumChannels::Two));
-        program.use_program();
-        program.set_uniform_int("uGlyphDataTexture", 0);
-
-        Ok(Self {
+        let mut result = Self {
             webgl,
             program,
             glyph_map : Vec::new(),
 
-            attribute_state,
+            attribute_state : None,
             glyph_instances, 
+            transitions : Transitions::new(),
             max_glyph_num_triangles : 0,
             
             vertices_data,
+            palette_data,
             ready : false
-        })
+        };
+        result.set_up_program()?;
+        Ok(result)
+    }
+
+    fn set_up_program(&mut self) -> Result<(), ChartError> {
+        self.attribute_state = self.webgl.create_vertex_array(&self.program.program, &ATTRIBUTES, self.glyph_instances.buffer.as_ref())?;
+        self.webgl.set_samplers(&self.program.program, &["uGlyphDataTexture", "uGlyphPaletteTexture"]);
+        Ok(())
+    }
+
+    pub fn restore_context(&mut self, webgl : G) -> Result<(), ChartError> {
+        self.program.restore_context(webgl.clone())?;
+        self.glyph_instances.restore_context(webgl.clone());
+        self.vertices_data.restore_context(webgl.clone());
+        self.palette_data.restore_context(webgl.clone());
+        self.webgl = webgl;
+        self.set_up_program()?;
+        self.ready = false;
+        Ok(())
     }
 
     pub fn clear_glyphs(&mut self){
         self.max_glyph_num_triangles = 0;
         self.vertices_data.clear();
+        self.palette_data.clear();
         self.glyph_map.clear();
         self.glyph_instances.clear();
+        self.transitions.clear();
         self.ready = false;
     }
 
-    pub(in crate::shader) fn add_glyph_data(&mut self, glyph : &Glyph) -> Result<(), JsValue> {
+    pub(in crate::shader) fn add_glyph_data(&mut self, glyph : &Glyph, tessellation_cache : &TessellationCache) -> Result<(), ChartError> {
 
-        let index = self.vertices_data.len() / 3;
-        let index : Result<u16, _> = index.try_into();
-        let index = index.map_err(|_| "Too many total glyph vertices : max number of triangles in all glyphs is 65535.")?;
+        let index = checked_index(self.vertices_data.len() / 3, "glyph triangles")?;
 
-        let mut buffers: VertexBuffers<Point, u16> = VertexBuffers::new();
-        let scale = 100.0;
-        
-        glyph.tessellate_fill(&mut buffers, scale)?;
-        let num_fill_triangles = buffers.indices.len()  / 3;
-        self.vertices_data.append(buffers.indices.iter().map(|&i| buffers.vertices[i as usize]));
-        
-        buffers.vertices.clear();
-        buffers.indices.clear();
+        let tessellation = tessellation_cache.glyph(glyph)?;
+        let num_fill_triangles = tessellation.fill.indices.len()  / 3;
+        self.append_vertices(&tessellation.fill);
 
-        glyph.tessellate_stroke(&mut buffers, scale)?;
-        let num_stroke_triangles = buffers.indices.len() / 3;
-        self.vertices_data.append(buffers.indices.iter().map(|&i| buffers.vertices[i as usize]));
+        let num_stroke_triangles = tessellation.stroke.indices.len() / 3;
+        self.append_vertices(&tessellation.stroke);
         
         self.max_glyph_num_triangles = self.max_glyph_num_triangles.max(num_fill_triangles + num_stroke_triangles);
 
-        let num_fill_triangles = num_fill_triangles.try_into().unwrap();
-        let num_stroke_triangles  = num_stroke_triangles.try_into().unwrap();
+        let num_fill_triangles = checked_index(num_fill_triangles, "glyph triangles")?;
+        let num_stroke_triangles  = checked_index(num_stroke_triangles, "glyph triangles")?;
         self.glyph_map.push(ShaderGlyphHeader {
             index, 
             num_fill_triangles, 
             num_stroke_triangles,
-            padding : 0
+            layer : 0
         });
         Ok(())
     }
 
+    fn append_vertices(&mut self, buffers : &VertexBuffers<GlyphVertex, u16>) {
+        let mut palette = Vec::new();
+        let palette_start = self.palette_data.len();
+        let vertices : Vec<_> = buffers.indices.iter().map(|&i| {
+            let GlyphVertex { position, color } = buffers.vertices[i as usize];
+            let color = match color {
+                ComponentColor::Slot(slot) => - (slot as f32 + 1.0),
+                ComponentColor::Fixed(color) => {
+                    // Components are tessellated one at a time, so equal colors are usually adjacent.
+                    if palette.last() != Some(&color) {
+                        palette.push(color);
+                    }
+                    (palette_start + palette.len() - 1) as f32
+                }
+            };
+            ShaderGlyphVertex { position, color, padding : 0.0 }
+        }).collect();
+        self.vertices_data.append(vertices.into_iter());
+        self.palette_data.append(palette.into_iter());
+    }
+
     pub fn add_glyph_instance(&mut self, glyph_instance : GlyphInstance, glyph_index : usize) {
-        let glyph = self.glyph_map[glyph_index];
+        let glyph = ShaderGlyphHeader { layer : glyph_instance.layer, ..self.glyph_map[glyph_index] };
+        let colors = pack_colors(&glyph_instance.colors);
         self.glyph_instances.push(ShaderGlyphInstance {
             position : glyph_instance.center,
-            scale : glyph_instance.scale / 100.0,
-            fill_color : vec4_to_u8_array(glyph_instance.fill_color),
-            stroke_color : vec4_to_u8_array(glyph_instance.stroke_color),
-            glyph 
+            scale : glyph_instance.scale / GLYPH_TESSELLATION_SCALE,
+            colors,
+            glyph,
+            page_range : glyph_instance.page_range,
+            previous_position : glyph_instance.center,
+            previous_colors : colors,
+            progress : 1.0,
+        });
+        self.transitions.push();
+        self.ready = false;
+    }
+
+    /// Move the instance to center and change its colors over the given time span, starting from wherever it is drawn at
+    /// transition.start_time.
+    pub fn transition_instance(&mut self, instance_index : usize, center : Point, colors : &[Vec4; NUM_COLOR_SLOTS], transition : Transition) {
+        let t = self.transitions.start(instance_index, transition);
+        let instance = &mut self.glyph_instances[instance_index];
+        instance.previous_position = instance.previous_position.lerp(instance.position, t);
+        for (previous, &current) in instance.previous_colors.iter_mut().zip(instance.colors.iter()) {
+            *previous = vec4_to_u8_array(u8_array_to_vec4(*previous).lerp(u8_array_to_vec4(current), t));
+        }
+        instance.position = center;
+        instance.colors = pack_colors(colors);
+        instance.progress = transition.progress(transition.start_time);
+        self.ready = false;
+    }
+
+    /// Draw the instances that are moving or changing colors where they are at time.
+    pub fn update_transitions(&mut self, time : f64) {
+        let instances = &mut self.glyph_instances;
+        let mut changed = false;
+        self.transitions.update(time, |index, progress| {
+            instances[index].progress = progress;
+            changed = true;
         });
+        self.ready &= !changed;
+    }
+
+    /// Only the changed instance is uploaded again.
+    pub fn set_page_range(&mut self, instance_index : usize, page_range : [i32; 2]) {
+        self.glyph_instances[instance_index].page_range = page_range;
         self.ready = false;
     }
 
-    fn prepare(&mut self) -> Result<(), JsValue> {
+    pub fn set_layer(&mut self, instance_index : usize, layer : u32) {
+        self.glyph_instances[instance_index].glyph.layer = layer;
+        self.ready = false;
+    }
+
+    /// Upload the instances and glyph data that changed.
+    pub fn prepare(&mut self) -> Result<(), ChartError> {
+        self.vertices_data.prepare()?;
+        self.palette_data.prepare()?;
         if self.ready {
             return Ok(());
         }
@@ -186,19 +296,27 @@ impl GlyphShader {
         self.glyph_instances.prepare();
         Ok(())
     }
+}
 
-    pub fn draw(&mut self, coordinate_system : CoordinateSystem) -> Result<(), JsValue> {
+impl GlyphShader {
+    /// Draw the instances in the given layer.
+    pub fn draw(&mut self, coordinate_system : CoordinateSystem, page : i32, layer : u32, opacity : f32) -> Result<(), ChartError> {
         if self.glyph_instances.is_empty() {
             return Ok(());
         }
+        self.prepare()?;
         self.program.use_program();
         self.webgl.bind_vertex_array(self.attribute_state.as_ref());
-        self.prepare()?;
         self.vertices_data.bind(WebGl2RenderingContext::TEXTURE0)?;
+        self.palette_data.bind(WebGl2RenderingContext::TEXTURE1)?;
         self.program.set_uniform_transform("uTransformationMatrix", coordinate_system.transform);
+        self.program.set_uniform_transform("uLattice", coordinate_system.lattice);
         self.program.set_uniform_point("uOrigin", coordinate_system.origin);
         self.program.set_uniform_vector("uScale", coordinate_system.scale);
         self.program.set_uniform_float("uGlyphScale", coordinate_system.glyph_scale);
+        self.program.set_uniform_int("uPage", page);
+        self.program.set_uniform_int("uLayer", layer as i32);
+        self.program.set_uniform_float("uOpacity", opacity);
 
         let num_instances = self.glyph_instances.len() as i32;
         let num_vertices = (self.max_glyph_num_triangles * 3) as i32;
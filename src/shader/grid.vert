@@ -1,58 +1,48 @@
 #version 300 es
 uniform mat3x2 uTransformationMatrix;
+uniform mat3x2 uLattice;
 uniform vec2 uOrigin;
 uniform vec2 uScale;
 uniform ivec4 uChartRange; // (xmin, xmax, ymin, ymax)
-uniform vec4 uScreenRange; // (xmin, xmax, ymin, ymax)
 uniform ivec2 uGridStep; // (xGridStep, yGridStep)
-uniform vec2 uGridOffset; // (xGridStep, yGridStep)
+uniform vec2 uGridOffset; // (xGridOffset, yGridOffset)
 
 uniform float uThickness;
 uniform vec4 uColor;
 
-vec2 getCoord(int lineID, int axisMin, int axisStep, float axisOffset, float axisOrigin, float axisScale, vec2 screenRange){
-    int vertexID = (gl_VertexID % 3) + gl_VertexID / 3;
-    int chartCoord = axisMin + axisStep * lineID;
-    float normalCoord = (float(chartCoord) + axisOffset) * axisScale + axisOrigin;
-    float varyingCoord;
-
-    if(vertexID/2 == 0){
-        varyingCoord = screenRange.x;
-    } else {
-        varyingCoord = screenRange.y;
-    }
-    if(vertexID % 2 == 1){
-        normalCoord += uThickness;
-    } else {
-        normalCoord -= uThickness;
-    }
-
-    return vec2(varyingCoord, normalCoord);
+vec2 transformPos(vec2 pos){
+    return uOrigin + (vec2(1.0, -1.0) * uScale) * (uLattice * vec3(pos, 1.0));
 }
 
-
 void main() {
-    float chartXGridOffset = uGridOffset.x;
-    float chartYGridOffset = uGridOffset.y;
-    int chartXGridStep = uGridStep.x;
-    int chartYGridStep = uGridStep.y;
     int chartXMin = uChartRange.x;
     int chartXMax = uChartRange.y;
     int chartYMin = uChartRange.z;
     int chartYMax = uChartRange.w;
-    int numVerticalGridLines = (chartXMax - chartXMin) / chartXGridStep + 1;
-    vec2 screenXRange = uScreenRange.xy;
-    vec2 screenYRange = uScreenRange.zw;
-    
-    vec2 position;
+    int numVerticalGridLines = (chartXMax - chartXMin) / uGridStep.x + 1;
+
+    // Each line is drawn across the whole chart range, the lattice may skew it so it need not be axis aligned.
+    vec2 start;
+    vec2 end;
     if(gl_InstanceID < numVerticalGridLines){
-        // Horizontal lines
-        int lineID = gl_InstanceID;
-        position = getCoord(lineID, chartYMin, chartYGridStep, chartYGridOffset, uOrigin.y, -uScale.y, screenXRange).xy;
+        // Lines of constant x
+        float x = float(chartXMin + uGridStep.x * gl_InstanceID) + uGridOffset.x;
+        start = vec2(x, float(chartYMin));
+        end = vec2(x, float(chartYMax));
     } else {
-        // Vertical lines
-        int lineID = gl_InstanceID - numVerticalGridLines;
-        position = getCoord(lineID, chartXMin, chartXGridStep, chartXGridOffset, uOrigin.x, uScale.x, screenYRange).yx;
+        // Lines of constant y
+        float y = float(chartYMin + uGridStep.y * (gl_InstanceID - numVerticalGridLines)) + uGridOffset.y;
+        start = vec2(float(chartXMin), y);
+        end = vec2(float(chartXMax), y);
     }
+    start = transformPos(start);
+    end = transformPos(end);
+    vec2 direction = normalize(end - start);
+    vec2 normal = vec2(-direction.y, direction.x);
+
+    // Two triangles: vertices (0, 1, 2) and (1, 2, 3)
+    int vertexID = (gl_VertexID % 3) + gl_VertexID / 3;
+    vec2 position = (vertexID / 2 == 0) ? start : end;
+    position += (vertexID % 2 == 1) ? uThickness * normal : -uThickness * normal;
     gl_Position = vec4(uTransformationMatrix * vec3(position, 1.0), 0.0, 1.0);
-}
\ No newline at end of file
+}
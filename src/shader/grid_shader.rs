@@ -2,17 +2,18 @@
 use crate::log;
 
 use crate::vector::{Vec4};
-use lyon::geom::math::{Point, vector};
+use lyon::geom::math::{Point};
 use crate::webgl_wrapper::WebGlWrapper;
 use crate::shader::Program;
+use crate::shader::gl_resources::GlResources;
 
-use wasm_bindgen::JsValue;
+use crate::error::ChartError;
 use web_sys::WebGl2RenderingContext;
 use crate::coordinate_system::CoordinateSystem;
 
 
-pub struct GridShader {
-    pub program : Program,
+pub struct GridShader<G : GlResources = WebGlWrapper> {
+    pub program : Program<G>,
     x_grid_step : i32,
     y_grid_step : i32,
     color : Vec4,
@@ -22,8 +23,8 @@ pub struct GridShader {
 }
 
 
-impl GridShader {
-    pub fn new(webgl : WebGlWrapper) -> Result<Self, JsValue> {
+impl<G : GlResources> GridShader<G> {
+    pub fn new(webgl : G) -> Result<Self, ChartError> {
         let program = Program::new(
             webgl,
             // vertexShader : 
@@ -48,13 +49,19 @@ impl GridShader {
             ready : false,
         })
     }
+    pub fn restore_context(&mut self, webgl : G) -> Result<(), ChartError> {
+        self.program.restore_context(webgl)?;
+        self.ready = false;
+        Ok(())
+    }
+
     // uniform mat3x2 uTransformationMatrix;
+    // uniform mat3x2 uLattice;
     // uniform vec2 uOrigin;
     // uniform vec2 uScale;
     // uniform ivec4 uChartRange; // (xmin, xmax, ymin, ymax)
-    // uniform vec4 uScreenRange; // (xmin, xmax, ymin, ymax)
     // uniform ivec2 uGridStep; // (xGridStep, yGridStep)
-    // uniform vec2 uGridOffset; // (xGridStep, yGridStep)
+    // uniform vec2 uGridOffset; // (xGridOffset, yGridOffset)
 
     #[allow(dead_code)]
     pub fn grid_step(&mut self, x_grid_step : i32, y_grid_step : i32){
@@ -81,7 +88,9 @@ impl GridShader {
         self.thickness = thickness;
         self.ready = false;
     }
+}
 
+impl GridShader {
     fn prepare(&mut self){
         if self.ready {
             return;
@@ -95,32 +104,37 @@ impl GridShader {
     }
 
 
-    pub fn draw(&mut self, coordinate_system : CoordinateSystem) -> Result<(), JsValue> {
+    pub fn draw(&mut self, coordinate_system : CoordinateSystem) -> Result<(), ChartError> {
         self.program.use_program();
         self.prepare();
         self.program.set_uniform_transform("uTransformationMatrix", coordinate_system.transform);
+        self.program.set_uniform_transform("uLattice", coordinate_system.lattice);
         self.program.set_uniform_point("uOrigin", coordinate_system.origin);
         self.program.set_uniform_vector("uScale", coordinate_system.scale);
 
-        let [mut chart_x_min, mut chart_y_min] : [i32; 2] = (coordinate_system.current_min_xy().floor() - vector(1.0, 1.0)).cast().to_array();
-        let [chart_x_max, chart_y_max] : [i32; 2] = (coordinate_system.current_max_xy().ceil() + vector(1.0, 1.0)).cast().to_array();
-
-        chart_x_min = (chart_x_min/self.x_grid_step) * self.x_grid_step;
-        chart_y_min = (chart_y_min/self.y_grid_step) * self.x_grid_step;
-
-        let (screen_x_min, screen_x_max) = coordinate_system.screen_x_range();
-        let (screen_y_min, screen_y_max) = coordinate_system.screen_y_range();
+        // The visible region in chart coordinates, the lattice may map it to a skewed box on screen.
+        let visible = coordinate_system.visible_chart_box();
+        let (min, max) = (visible.min.floor(), visible.max.ceil());
+        // Without this check, it seems to freeze the computer when you zoom out very far.
+        if !(max - min).to_array().iter().all(|&size| size.is_finite() && size < 100_000.0) {
+            return Err(ChartError::invalid_input("Scale too small!"));
+        }
+        let [chart_x_min, chart_y_min] : [i32; 2] = min.cast().to_array();
+        let [chart_x_max, chart_y_max] : [i32; 2] = max.cast().to_array();
 
-        let num_vertical_grid_lines = (chart_x_max - chart_x_min + self.x_grid_step - 1) / self.x_grid_step + 1;
-        let num_horizontal_grid_lines = (chart_y_max - chart_y_min + self.y_grid_step - 1) / self.y_grid_step + 1;
-        let loc = self.program.webgl.get_uniform_location(&self.program.program, "uChartRange");  
-        self.program.webgl.uniform4iv_with_i32_array(loc.as_ref(), &[chart_x_min, chart_x_max, chart_y_min, chart_y_max]);
-        self.program.set_uniform_vec4("uScreenRange", Vec4::new(screen_x_min, screen_x_max, screen_y_min, screen_y_max));
+        // Align the first line to the grid step, the shader draws (max - min) / step + 1 lines per direction.
+        let chart_x_min = (chart_x_min - 1).div_euclid(self.x_grid_step) * self.x_grid_step;
+        let chart_y_min = (chart_y_min - 1).div_euclid(self.y_grid_step) * self.y_grid_step;
+        let chart_x_max = chart_x_min + ((chart_x_max + 1 - chart_x_min) / self.x_grid_step + 1) * self.x_grid_step;
+        let chart_y_max = chart_y_min + ((chart_y_max + 1 - chart_y_min) / self.y_grid_step + 1) * self.y_grid_step;
 
-        // Without this check, it seems to freeze the computer when you zoom out very far.
+        let num_vertical_grid_lines = (chart_x_max - chart_x_min) / self.x_grid_step + 1;
+        let num_horizontal_grid_lines = (chart_y_max - chart_y_min) / self.y_grid_step + 1;
         if num_vertical_grid_lines + num_horizontal_grid_lines > 10_000 {
-            return Err("Scale too small!".into());
+            return Err(ChartError::invalid_input("Scale too small!"));
         }
+        let loc = self.program.webgl.get_uniform_location(&self.program.program, "uChartRange");  
+        self.program.webgl.uniform4iv_with_i32_array(loc.as_ref(), &[chart_x_min, chart_x_max, chart_y_min, chart_y_max]);
 
         self.program.webgl.draw_arrays_instanced(
             WebGl2RenderingContext::TRIANGLES,
@@ -130,4 +144,4 @@ impl GridShader {
         );
         Ok(())
     }
-}
\ No newline at end of file
+}
\ No newline at end of file
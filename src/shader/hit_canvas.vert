@@ -2,15 +2,21 @@
 #define ANGLE_RES 180 // should be same as ANGLE_RESOLUTION
 // layout (std140) uniform Transform {
     uniform mat3x2 uTransformationMatrix;
+    uniform mat3x2 uLattice;
     uniform vec2 uOrigin;
     uniform vec2 uScale;
     uniform float uGlyphScale;
 // };
+uniform int uPage;
+uniform int uLayer;
 uniform sampler2D uGlyphBoundaryTexture;
 
 in vec2 aPosition;
 in float aScale;
-in uvec2 aGlyphIndex;  // (index, padding)
+in uvec2 aGlyphIndex;  // (index, layer)
+in ivec2 aPageRange; // (first_page, last_page)
+in vec2 aPreviousPosition;
+in float aProgress; // of the move from the previous position, eased in and out
 
 flat out vec4 fColor;
 out vec2 vPosition;
@@ -30,7 +36,8 @@ vec2 getVec2ByIndexFrom4ChannelTexture(sampler2D tex, int index){
 }
 
 vec2 glyphBoundaryPointVertex(uint glyph, int vertexID){
-    int total_index = ANGLE_RES * int(glyph) + vertexID;
+    // Each glyph has ANGLE_RES convex hull points followed by ANGLE_RES star outline points.
+    int total_index = 2 * ANGLE_RES * int(glyph) + vertexID;
     return uGlyphScale * aScale * getVec2ByIndexFrom4ChannelTexture(uGlyphBoundaryTexture, total_index);
 }
 
@@ -43,10 +50,21 @@ vec4 getColor(){
     return vec4(float(r)/255., float(g)/255., float(b)/255., float(a)/255.);
 }
 
+// Not on the current page, or not in the layer being drawn.
+bool hidden(){
+    return uPage < aPageRange.x || uPage > aPageRange.y || int(aGlyphIndex.y) != uLayer;
+}
+
 void main() {
+    if(hidden()){
+        // Every vertex of the instance is outside the clip volume, so nothing gets rasterized.
+        gl_Position = vec4(2.0, 2.0, 2.0, 1.0);
+        return;
+    }
     vec2 vertexPosition = glyphBoundaryPointVertex(aGlyphIndex.x, gl_VertexID);
     vPosition = vertexPosition;
-    vec2 transformedPosition = uOrigin +  (vec2(1.0, -1.0) * uScale) * aPosition;
+    vec2 position = mix(aPreviousPosition, aPosition, aProgress);
+    vec2 transformedPosition = uOrigin +  (vec2(1.0, -1.0) * uScale) * (uLattice * vec3(position, 1.0));
     fColor = getColor();
     gl_Position = vec4(uTransformationMatrix * vec3(transformedPosition + vertexPosition, 1.0), 0.0, 1.0);
     length(vPosition);
@@ -1,8 +1,7 @@
 #![allow(dead_code)]
 
 
-use web_sys::{WebGl2RenderingContext, WebGlVertexArrayObject, WebGlTexture, WebGlFramebuffer, WebGlRenderbuffer};
-use wasm_bindgen::JsValue;
+use web_sys::{WebGl2RenderingContext, WebGlTexture, WebGlFramebuffer, WebGlRenderbuffer};
 
 use lyon::geom::math::{Point, Vector};
 
@@ -12,52 +11,71 @@ use crate::log;
 
 use crate::webgl_wrapper::WebGlWrapper;
 use crate::shader::Program;
+use crate::shader::gl_resources::GlResources;
 use crate::shader::attributes::{Type, Attribute, Attributes};
 
 use crate::shader::data_texture::DataTexture;
 use crate::shader::vertex_buffer::VertexBuffer;
 
+use crate::error::{ChartError, checked_index};
 use crate::glyph::{GlyphInstance};
+use crate::animation::{Transition, Transitions};
 
 use crate::convex_hull::ANGLE_RESOLUTION;
 use crate::coordinate_system::{CoordinateSystem, BufferDimensions};
 
 
-const ATTRIBUTES : Attributes = Attributes::new(&[
+// Also used by the focus ring shader, which draws around a copy of one instance.
+pub(in crate::shader) const ATTRIBUTES : Attributes = Attributes::new(&[
     Attribute::new("aPosition", 2, Type::F32),
     Attribute::new("aScale", 1, Type::F32),
-    Attribute::new("aGlyphIndex", 2, Type::U16), // (index, padding)
+    Attribute::new("aGlyphIndex", 2, Type::U32), // (index, layer)
+    Attribute::new("aPageRange", 2, Type::I32), // (first_page, last_page)
+    Attribute::new("aPreviousPosition", 2, Type::F32),
+    Attribute::new("aProgress", 1, Type::F32), // of the move from the previous position
 ]);
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
 struct ShaderGlyphHeader {
-    index : u16,
-    padding : u16,
+    index : u32,
+    layer : u32,
 }
 
-#[derive(Debug)]
-struct ShaderGlyphInstance {
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub(in crate::shader) struct ShaderGlyphInstance {
     position : Point,
     scale : f32,
     glyph : ShaderGlyphHeader,
+    page_range : [i32; 2],
+    previous_position : Point,
+    progress : f32,
 }
 
-pub struct HitCanvasShader {
-    webgl : WebGlWrapper,
-    program : Program,
+pub struct HitCanvasShader<G : GlResources = WebGlWrapper> {
+    webgl : G,
+    program : Program<G>,
     hit_canvas_buffer_dimensions : BufferDimensions,
     hit_canvas_framebuffer : Option<WebGlFramebuffer>,
     hit_canvas_texture : Option<WebGlTexture>,
     hit_canvas_depth_buffer : Option<WebGlRenderbuffer>,
 
-    attribute_state : Option<WebGlVertexArrayObject>,
+    attribute_state : Option<G::VertexArray>,
 
-    glyph_instances : VertexBuffer<ShaderGlyphInstance>,
+    glyph_instances : VertexBuffer<ShaderGlyphInstance, G>,
+    transitions : Transitions,
     ready : bool,
 }
 
-impl HitCanvasShader {
-    pub fn new(webgl : WebGlWrapper) -> Result<Self, JsValue> {
+impl ShaderGlyphInstance {
+    pub(in crate::shader) fn layer(&self) -> u32 {
+        self.glyph.layer
+    }
+}
+
+impl<G : GlResources> HitCanvasShader<G> {
+    pub fn new(webgl : G) -> Result<Self, ChartError> {
         let program = Program::new(
             webgl.clone(), 
             include_str!("hit_canvas.vert"),
@@ -73,15 +91,9 @@ impl HitCanvasShader {
             "#
         )?;
 
-        let attribute_state = webgl.create_vertex_array();
         let glyph_instances = VertexBuffer::new(webgl.clone());
 
-        ATTRIBUTES.set_up_vertex_array(&webgl, &program.program, attribute_state.as_ref(), glyph_instances.buffer.as_ref())?;
-
-        program.use_program();
-        program.set_uniform_int("uGlyphDataTexture", 0);
-
-        Ok(Self {
+        let mut result = Self {
             webgl,
             program,
             hit_canvas_buffer_dimensions : BufferDimensions::new(1, 1, 0.0),
@@ -90,12 +102,100 @@ impl HitCanvasShader {
             hit_canvas_depth_buffer : None,
 
             glyph_instances, 
+            transitions : Transitions::new(),
             
-            attribute_state,
+            attribute_state : None,
             ready : false
-        })
+        };
+        result.set_up_program()?;
+        Ok(result)
+    }
+
+    fn set_up_program(&mut self) -> Result<(), ChartError> {
+        self.attribute_state = self.webgl.create_vertex_array(&self.program.program, &ATTRIBUTES, self.glyph_instances.buffer.as_ref())?;
+        self.webgl.set_samplers(&self.program.program, &["uGlyphDataTexture"]);
+        Ok(())
+    }
+
+    pub fn restore_context(&mut self, webgl : G) -> Result<(), ChartError> {
+        self.program.restore_context(webgl.clone())?;
+        self.glyph_instances.restore_context(webgl.clone());
+        self.webgl = webgl;
+        self.set_up_program()?;
+        // The old framebuffer belongs to the lost context, initialize_hit_canvas makes a new one on the next draw.
+        self.hit_canvas_framebuffer = None;
+        self.hit_canvas_texture = None;
+        self.hit_canvas_depth_buffer = None;
+        self.hit_canvas_buffer_dimensions = BufferDimensions::new(1, 1, 0.0);
+        self.ready = false;
+        Ok(())
+    }
+
+    pub fn add_glyph(&mut self, glyph_instance : GlyphInstance, glyph_index : usize) -> Result<(), ChartError> {
+        self.glyph_instances.push(ShaderGlyphInstance {
+            position : glyph_instance.center,
+            scale : glyph_instance.scale,
+            glyph : ShaderGlyphHeader { 
+                index : checked_index(glyph_index, "glyphs")?,
+                layer : glyph_instance.layer,
+            },
+            page_range : glyph_instance.page_range,
+            previous_position : glyph_instance.center,
+            progress : 1.0,
+        });
+        self.transitions.push();
+        self.ready = false;
+        Ok(())
+    }
+
+    pub(in crate::shader) fn instance(&self, instance_index : usize) -> ShaderGlyphInstance {
+        self.glyph_instances[instance_index]
+    }
+
+    pub fn set_page_range(&mut self, instance_index : usize, page_range : [i32; 2]) {
+        self.glyph_instances[instance_index].page_range = page_range;
+        self.ready = false;
+    }
+
+    /// Same as GlyphShader::transition_instance, so that hit testing matches what is drawn.
+    pub fn transition_instance(&mut self, instance_index : usize, center : Point, transition : Transition) {
+        let t = self.transitions.start(instance_index, transition);
+        let instance = &mut self.glyph_instances[instance_index];
+        instance.previous_position = instance.previous_position.lerp(instance.position, t);
+        instance.position = center;
+        instance.progress = transition.progress(transition.start_time);
+        self.ready = false;
+    }
+
+    /// Returns true if any instance moved.
+    pub fn update_transitions(&mut self, time : f64) -> bool {
+        let instances = &mut self.glyph_instances;
+        let mut changed = false;
+        self.transitions.update(time, |index, progress| {
+            instances[index].progress = progress;
+            changed = true;
+        });
+        self.ready &= !changed;
+        changed
+    }
+
+    pub fn set_layer(&mut self, instance_index : usize, layer : u32) {
+        self.glyph_instances[instance_index].glyph.layer = layer;
+        self.ready = false;
+    }
+
+    pub fn clear_glyphs(&mut self){
+        self.glyph_instances.clear();
+        self.transitions.clear();
+        self.ready = false;
     }
 
+    pub fn prepare(&mut self) {
+        self.glyph_instances.prepare();
+    }
+}
+
+impl HitCanvasShader {
     fn initialize_hit_canvas(&mut self, dimensions : BufferDimensions){
         if self.hit_canvas_buffer_dimensions == dimensions {
             return;
@@ -104,7 +204,7 @@ impl HitCanvasShader {
         self.webgl.delete_texture(self.hit_canvas_texture.as_ref());
         self.webgl.delete_renderbuffer(self.hit_canvas_depth_buffer.as_ref());
         self.hit_canvas_framebuffer = self.webgl.create_framebuffer();
-        self.hit_canvas_texture = self.webgl.create_texture();
+        self.hit_canvas_texture = self.webgl.inner.create_texture();
         self.hit_canvas_depth_buffer = self.webgl.create_renderbuffer();
 
         self.webgl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, self.hit_canvas_texture.as_ref());
@@ -135,25 +235,8 @@ impl HitCanvasShader {
 
     }
 
-    pub fn add_glyph(&mut self, glyph_instance : GlyphInstance, glyph_index : usize) -> Result<(), JsValue> {
-        self.glyph_instances.push(ShaderGlyphInstance {
-            position : glyph_instance.center,
-            scale : glyph_instance.scale,
-            glyph : ShaderGlyphHeader { 
-                index : glyph_index as u16,
-                padding : 0
-            },
-        });
-        self.ready = false;
-        Ok(())
-    }
-
-    pub fn clear_glyphs(&mut self){
-        self.glyph_instances.clear();
-        self.ready = false;
-    }
-
-    pub fn draw(&mut self, coordinate_system : CoordinateSystem, glyph_boundary_data : &mut DataTexture<Vector>) -> Result<(), JsValue> {
+    /// Layers are drawn in the given order, each one on top of the ones before regardless of depth.
+    pub fn draw(&mut self, coordinate_system : CoordinateSystem, glyph_boundary_data : &mut DataTexture<Vector>, page : i32, layers : &[u32]) -> Result<(), ChartError> {
         if self.glyph_instances.is_empty() {
             return Ok(());
         }
@@ -169,24 +252,29 @@ impl HitCanvasShader {
         // This has a side-effect of adjusting texture bindings, so it has to occur before glyph_boundary_data.bind().
         self.initialize_hit_canvas(coordinate_system.buffer_dimensions);
 
-        self.glyph_instances.prepare();
+        self.prepare();
         glyph_boundary_data.bind(WebGl2RenderingContext::TEXTURE0)?;
 
         self.program.set_uniform_transform("uTransformationMatrix", coordinate_system.transform);
+        self.program.set_uniform_transform("uLattice", coordinate_system.lattice);
         self.program.set_uniform_point("uOrigin", coordinate_system.origin);
         self.program.set_uniform_vector("uScale", coordinate_system.scale);
         self.program.set_uniform_float("uGlyphScale", coordinate_system.glyph_scale);
+        self.program.set_uniform_int("uPage", page);
 
 
-        
         let num_instances = self.glyph_instances.len() as i32;
         let num_vertices = ANGLE_RESOLUTION as i32;
-        self.webgl.draw_arrays_instanced(
-            WebGl2RenderingContext::TRIANGLE_FAN,
-            0,
-            num_vertices,
-            num_instances
-        );
+        for &layer in layers {
+            self.webgl.clear(WebGl2RenderingContext::DEPTH_BUFFER_BIT);
+            self.program.set_uniform_int("uLayer", layer as i32);
+            self.webgl.draw_arrays_instanced(
+                WebGl2RenderingContext::TRIANGLE_FAN,
+                0,
+                num_vertices,
+                num_instances
+            );
+        }
 
         self.webgl.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, None);
         self.webgl.bind_vertex_array(None);
@@ -196,7 +284,7 @@ impl HitCanvasShader {
         Ok(())
     }
 
-    pub fn object_underneath_pixel(&self, coordinate_system : CoordinateSystem, point : Point) -> Result<Option<u32>, JsValue> {
+    pub fn object_underneath_pixel(&self, coordinate_system : CoordinateSystem, point : Point) -> Result<Option<u32>, ChartError> {
         let mut data = [0; 4];
         self.webgl.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, self.hit_canvas_framebuffer.as_ref());
         let density = coordinate_system.buffer_dimensions.density();
@@ -0,0 +1,209 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::error::ChartError;
+use crate::shader::attributes::Attributes;
+use crate::shader::gl_resources::{GlResources, TextureFormat};
+
+static NEXT_CONTEXT : AtomicUsize = AtomicUsize::new(0);
+
+/// An object made by a MockContext. Using it with a different context panics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Handle {
+    context : usize,
+    id : usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Buffer {
+    data : Vec<u8>,
+    // Bytes past the highest upload were never written.
+    written : usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Texture {
+    format : TextureFormat,
+    width : usize,
+    pub height : usize,
+    // Rows that were never uploaded are missing.
+    pub rows : BTreeMap<usize, Vec<u8>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Program {
+    vertex_shader : String,
+    fragment_shader : String,
+    samplers : Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct VertexArray {
+    program : Program,
+    stride : i32,
+    buffer : Option<Vec<u8>>,
+}
+
+#[derive(Default)]
+struct State {
+    lost : bool,
+    next_id : usize,
+    buffers : BTreeMap<usize, Buffer>,
+    textures : BTreeMap<usize, Texture>,
+    programs : BTreeMap<usize, Program>,
+    // The program and buffer, which are looked up when the contents are compared.
+    vertex_arrays : BTreeMap<usize, (Handle, i32, Option<Handle>)>,
+}
+
+/// Everything that is alive in a context, without the handles so that two contexts can be compared.
+#[derive(Debug, PartialEq)]
+pub struct Contents {
+    pub buffers : Vec<Vec<u8>>,
+    pub textures : Vec<Texture>,
+    pub programs : Vec<Program>,
+    pub vertex_arrays : Vec<VertexArray>,
+}
+
+/// Keeps the objects made through GlResources in memory, for checking what ends up on the GPU.
+#[derive(Clone)]
+pub struct MockContext {
+    context : usize,
+    state : Rc<RefCell<State>>,
+}
+
+impl MockContext {
+    pub fn new() -> Self {
+        Self { context : NEXT_CONTEXT.fetch_add(1, Ordering::Relaxed), state : Default::default() }
+    }
+
+    /// Like a lost WebGL context, nothing can be made anymore and calls do nothing.
+    pub fn lose(&self) {
+        self.state.borrow_mut().lost = true;
+    }
+
+    pub fn contents(&self) -> Contents {
+        let state = self.state.borrow();
+        let mut buffers : Vec<_> = state.buffers.values().map(|buffer| buffer.data[.. buffer.written].to_vec()).collect();
+        let mut textures : Vec<_> = state.textures.values().cloned().collect();
+        let mut programs : Vec<_> = state.programs.values().cloned().collect();
+        let mut vertex_arrays : Vec<_> = state.vertex_arrays.values().map(|&(program, stride, buffer)| VertexArray {
+            program : state.programs[&self.id(&program)].clone(),
+            stride,
+            buffer : buffer.map(|buffer| { let buffer = &state.buffers[&self.id(&buffer)]; buffer.data[.. buffer.written].to_vec() }),
+        }).collect();
+        buffers.sort();
+        textures.sort();
+        programs.sort();
+        vertex_arrays.sort();
+        Contents { buffers, textures, programs, vertex_arrays }
+    }
+
+    fn id(&self, handle : &Handle) -> usize {
+        assert_eq!(handle.context, self.context, "Object of another context used.");
+        handle.id
+    }
+
+    // None if the context is lost.
+    fn create(&self) -> Option<Handle> {
+        let mut state = self.state.borrow_mut();
+        if state.lost {
+            return None;
+        }
+        state.next_id += 1;
+        Some(Handle { context : self.context, id : state.next_id })
+    }
+
+    fn is_lost(&self) -> bool {
+        self.state.borrow().lost
+    }
+}
+
+impl GlResources for MockContext {
+    type Buffer = Handle;
+    type Texture = Handle;
+    type Program = Handle;
+    type VertexArray = Handle;
+
+    fn create_buffer(&self) -> Option<Handle> {
+        let handle = self.create()?;
+        self.state.borrow_mut().buffers.insert(handle.id, Buffer { data : Vec::new(), written : 0 });
+        Some(handle)
+    }
+
+    fn allocate_buffer(&self, buffer : Option<&Handle>, size : usize) {
+        if self.is_lost() {
+            return;
+        }
+        let id = self.id(buffer.expect("No buffer bound."));
+        self.state.borrow_mut().buffers.insert(id, Buffer { data : vec![0; size], written : 0 });
+    }
+
+    fn upload_buffer(&self, buffer : Option<&Handle>, offset : usize, data : &[u8]) {
+        if self.is_lost() {
+            return;
+        }
+        let id = self.id(buffer.expect("No buffer bound."));
+        let mut state = self.state.borrow_mut();
+        let buffer = state.buffers.get_mut(&id).unwrap();
+        buffer.data[offset .. offset + data.len()].copy_from_slice(data);
+        buffer.written = buffer.written.max(offset + data.len());
+    }
+
+    fn create_texture(&self, format : TextureFormat, width : usize, height : usize) -> Option<Handle> {
+        let handle = self.create()?;
+        self.state.borrow_mut().textures.insert(handle.id, Texture { format, width, height, rows : BTreeMap::new() });
+        Some(handle)
+    }
+
+    fn delete_texture(&self, texture : Option<&Handle>) {
+        if let Some(texture) = texture {
+            let id = self.id(texture);
+            self.state.borrow_mut().textures.remove(&id);
+        }
+    }
+
+    fn upload_texture_rows(&self, texture : Option<&Handle>, format : TextureFormat, width : usize, first_row : usize, num_rows : usize, data : &[u8]) -> Result<(), ChartError> {
+        if self.is_lost() {
+            return Ok(());
+        }
+        let id = self.id(texture.expect("No texture bound."));
+        let mut state = self.state.borrow_mut();
+        let texture = state.textures.get_mut(&id).unwrap();
+        assert_eq!((texture.format, texture.width), (format, width));
+        assert!(first_row + num_rows <= texture.height && data.len().is_multiple_of(num_rows));
+        for (i, row) in data.chunks_exact(data.len() / num_rows).enumerate() {
+            texture.rows.insert(first_row + i, row.to_vec());
+        }
+        Ok(())
+    }
+
+    fn build_program(&self, vertex_shader : &str, fragment_shader : &str) -> Result<Handle, ChartError> {
+        let handle = self.create().ok_or(ChartError::ContextLost)?;
+        self.state.borrow_mut().programs.insert(handle.id, Program {
+            vertex_shader : vertex_shader.to_string(),
+            fragment_shader : fragment_shader.to_string(),
+            samplers : Vec::new(),
+        });
+        Ok(handle)
+    }
+
+    fn set_samplers(&self, program : &Handle, samplers : &[&str]) {
+        if self.is_lost() {
+            return;
+        }
+        let id = self.id(program);
+        self.state.borrow_mut().programs.get_mut(&id).unwrap().samplers = samplers.iter().map(|name| name.to_string()).collect();
+    }
+
+    fn create_vertex_array(&self, program : &Handle, attributes : &Attributes, buffer : Option<&Handle>) -> Result<Option<Handle>, ChartError> {
+        self.id(program);
+        buffer.map(|buffer| self.id(buffer));
+        let handle = self.create();
+        if let Some(handle) = handle {
+            self.state.borrow_mut().vertex_arrays.insert(handle.id, (*program, attributes.stride(), buffer.copied()));
+        }
+        Ok(handle)
+    }
+}
@@ -4,6 +4,9 @@ mod attributes;
 mod data_texture;
 mod vertex_buffer;
 mod program;
+mod gl_resources;
+#[cfg(test)]
+pub(crate) mod mock_context;
 
 
 mod grid_shader;
@@ -11,6 +14,7 @@ mod glyph_shader;
 mod hit_canvas_shader;
 mod edge_shader;
 mod chart_shaders;
+mod focus_ring_shader;
 
 
 
@@ -20,9 +24,10 @@ pub(in crate::shader) use data_texture::DataTexture;
 pub(in crate::shader) use glyph_shader::GlyphShader;
 pub(in crate::shader) use hit_canvas_shader::HitCanvasShader;
 pub(in crate::shader) use edge_shader::EdgeShader;
+pub(in crate::shader) use focus_ring_shader::FocusRingShader;
 
 
 
 pub use grid_shader::GridShader;
 pub use edge_shader::EdgeOptions;
-pub use chart_shaders::ChartShaders;
\ No newline at end of file
+pub use chart_shaders::{ChartShaders, Layer};
\ No newline at end of file
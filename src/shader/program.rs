@@ -1,36 +1,40 @@
 use crate::vector::{Vec3, Vec4};
 use lyon::geom::math::{Point, Vector, Transform};
 
-use wasm_bindgen::JsValue;
+use crate::error::ChartError;
 use crate::webgl_wrapper::WebGlWrapper;
+use crate::shader::gl_resources::GlResources;
 use web_sys::{ 
     WebGl2RenderingContext, WebGlProgram, WebGlShader, 
 };
 
-pub struct Program {
-    pub webgl : WebGlWrapper,
-    pub program : WebGlProgram
+pub struct Program<G : GlResources = WebGlWrapper> {
+    pub webgl : G,
+    pub program : G::Program,
+    // Kept so that the program can be rebuilt after the context is lost.
+    vertex_shader : String,
+    fragment_shader : String,
 }
 
-impl Program {
-    pub fn new(webgl : WebGlWrapper, vertex_shader : &str, fragment_shader : &str) -> Result<Self, JsValue> {
-        let vert_shader = compile_shader(
-            &webgl,
-            WebGl2RenderingContext::VERTEX_SHADER,
-            vertex_shader
-        )?;
-        let frag_shader = compile_shader(
-            &webgl,
-            WebGl2RenderingContext::FRAGMENT_SHADER,
-            fragment_shader
-        )?;
-        let program = link_program(&webgl, &vert_shader, &frag_shader)?;
+impl<G : GlResources> Program<G> {
+    pub fn new(webgl : G, vertex_shader : &str, fragment_shader : &str) -> Result<Self, ChartError> {
+        let program = webgl.build_program(vertex_shader, fragment_shader)?;
         Ok(Program {
             webgl, 
-            program
+            program,
+            vertex_shader : vertex_shader.to_string(),
+            fragment_shader : fragment_shader.to_string(),
         })
     }
 
+    pub fn restore_context(&mut self, webgl : G) -> Result<(), ChartError> {
+        self.program = webgl.build_program(&self.vertex_shader, &self.fragment_shader)?;
+        self.webgl = webgl;
+        Ok(())
+    }
+}
+
+impl Program {
     pub fn use_program(&self){
         self.webgl.use_program(Some(&self.program));
     }
@@ -75,15 +79,29 @@ impl Program {
 }
 
 
+pub(in crate::shader) fn build_program(webgl : &WebGlWrapper, vertex_shader : &str, fragment_shader : &str) -> Result<WebGlProgram, ChartError> {
+    let vert_shader = compile_shader(
+        webgl,
+        WebGl2RenderingContext::VERTEX_SHADER,
+        vertex_shader
+    )?;
+    let frag_shader = compile_shader(
+        webgl,
+        WebGl2RenderingContext::FRAGMENT_SHADER,
+        fragment_shader
+    )?;
+    link_program(webgl, &vert_shader, &frag_shader)
+}
+
 fn compile_shader(
     webgl: &WebGlWrapper,
     shader_type: u32,
     source: &str,
-) -> Result<WebGlShader, String> {
+) -> Result<WebGlShader, ChartError> {
     let webgl = &webgl.inner;
     let shader = webgl
         .create_shader(shader_type)
-        .ok_or_else(|| String::from("Unable to create shader object"))?;
+        .ok_or_else(|| context_lost_or(ChartError::ShaderCompile(String::from("Unable to create shader object")), webgl))?;
     webgl.shader_source(&shader, source);
     webgl.compile_shader(&shader);
 
@@ -94,9 +112,10 @@ fn compile_shader(
     {
         Ok(shader)
     } else {
-        Err(webgl
+        let log = webgl
             .get_shader_info_log(&shader)
-            .unwrap_or_else(|| String::from("Unknown error creating shader")))
+            .unwrap_or_else(|| String::from("Unknown error creating shader"));
+        Err(context_lost_or(ChartError::ShaderCompile(log), webgl))
     }
 }
 
@@ -104,11 +123,11 @@ fn link_program(
     webgl: &WebGlWrapper,
     vert_shader: &WebGlShader,
     frag_shader: &WebGlShader,
-) -> Result<WebGlProgram, String> {
+) -> Result<WebGlProgram, ChartError> {
     let webgl = &webgl.inner;
     let program = webgl
         .create_program()
-        .ok_or_else(|| String::from("Unable to create shader object"))?;
+        .ok_or_else(|| context_lost_or(ChartError::ProgramLink(String::from("Unable to create shader object")), webgl))?;
 
     webgl.attach_shader(&program, vert_shader);
     webgl.attach_shader(&program, frag_shader);
@@ -121,8 +140,14 @@ fn link_program(
     {
         Ok(program)
     } else {
-        Err(webgl
+        let log = webgl
             .get_program_info_log(&program)
-            .unwrap_or_else(|| String::from("Unknown error creating program object")))
+            .unwrap_or_else(|| String::from("Unknown error creating program object"));
+        Err(context_lost_or(ChartError::ProgramLink(log), webgl))
     }
 }
+
+// Compiling and linking fail when the context is lost, in which case the info log is useless.
+fn context_lost_or(error : ChartError, webgl : &WebGl2RenderingContext) -> ChartError {
+    if webgl.is_context_lost() { ChartError::ContextLost } else { error }
+}
@@ -2,28 +2,28 @@
 
 use crate::shader::range::MemoryRange;
 use crate::webgl_wrapper::WebGlWrapper;
+use crate::shader::gl_resources::GlResources;
 
 
 use std::ops::{Index, IndexMut};
 use std::slice::SliceIndex;
-use web_sys::{WebGlBuffer, WebGl2RenderingContext};
 
 
-pub struct VertexBuffer<T> {
-    webgl : WebGlWrapper,
-    pub buffer : Option<WebGlBuffer>,
+pub struct VertexBuffer<T, G : GlResources = WebGlWrapper> {
+    webgl : G,
+    pub buffer : Option<G::Buffer>,
     buffer_capacity : usize,
     data : Vec<T>,
     dirty_range : MemoryRange,
     ready : bool,
 }
 
-impl<T> VertexBuffer<T> {
-    pub fn new(webgl : WebGlWrapper) -> Self {
+impl<T, G : GlResources> VertexBuffer<T, G> {
+    pub fn new(webgl : G) -> Self {
         Self::with_capacity(webgl, 0)
     }
 
-    pub fn with_capacity(webgl : WebGlWrapper, capacity: usize) -> Self {
+    pub fn with_capacity(webgl : G, capacity: usize) -> Self {
         let buffer = webgl.create_buffer();
         Self {
             webgl,
@@ -35,6 +35,14 @@ impl<T> VertexBuffer<T> {
         }
     }
 
+    /// Create a new buffer on the new context, the next prepare uploads all the data again.
+    pub fn restore_context(&mut self, webgl : G) {
+        self.buffer = webgl.create_buffer();
+        self.webgl = webgl;
+        self.buffer_capacity = 0;
+        self.dirty_range = MemoryRange::new(0, self.data.len());
+    }
+
     pub fn len(&self) -> usize {
         self.data.len()
     }
@@ -61,8 +69,9 @@ impl<T> VertexBuffer<T> {
             return;
         }
         // reserve size for buffer.
-        let buffer_size = (self.data.capacity() * std::mem::size_of::<T>()) as i32;
-        self.webgl.buffer_data_with_i32(WebGl2RenderingContext::ARRAY_BUFFER, buffer_size, WebGl2RenderingContext::STATIC_DRAW);
+        let buffer_size = self.data.capacity() * std::mem::size_of::<T>();
+        self.webgl.allocate_buffer(self.buffer.as_ref(), buffer_size);
+        self.buffer_capacity = self.data.capacity();
         self.dirty_range = MemoryRange::new(0, self.data.len());
     }
 
@@ -72,30 +81,29 @@ impl<T> VertexBuffer<T> {
         }
         let dirty_min = self.dirty_range.min;
         let dirty_max = self.dirty_range.max.min(self.data.len());
-        let offset = std::mem::size_of_val(&self.data[0..dirty_min]) as i32;
+        let offset = std::mem::size_of_val(&self.data[0..dirty_min]);
         let slice = &self.data[dirty_min .. dirty_max];
         let slice_size = std::mem::size_of_val(slice);
-        let u8_ptr = self.data.as_ptr() as *mut u8;
+        let u8_ptr = slice.as_ptr() as *mut u8;
         let u8_slice = unsafe {
             std::slice::from_raw_parts(u8_ptr, slice_size)
         };
-        self.webgl.buffer_sub_data_with_i32_and_u8_array(WebGl2RenderingContext::ARRAY_BUFFER, offset, u8_slice);
+        self.webgl.upload_buffer(self.buffer.as_ref(), offset, u8_slice);
         self.dirty_range = MemoryRange::empty();
     }
 
 
     pub fn prepare(&mut self) {
-        if self.data.len() == 0 {
+        if self.data.is_empty() {
             return;
         }
-        self.webgl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, self.buffer.as_ref());
         self.ensure_buffer_size();
         self.update_buffer_data();
     }
 
 }
 
-impl<T, I: SliceIndex<[T]>> Index<I> for VertexBuffer<T> {
+impl<T, G : GlResources, I: SliceIndex<[T]>> Index<I> for VertexBuffer<T, G> {
     type Output = I::Output;
     #[inline]
     fn index(&self, index: I) -> &Self::Output {
@@ -103,7 +111,7 @@ impl<T, I: SliceIndex<[T]>> Index<I> for VertexBuffer<T> {
     }
 }
 
-impl<T, I: SliceIndex<[T]> + Copy + Into<MemoryRange> > IndexMut<I> for VertexBuffer<T> {
+impl<T, G : GlResources, I: SliceIndex<[T]> + Copy + Into<MemoryRange> > IndexMut<I> for VertexBuffer<T, G> {
     #[inline]
     fn index_mut(&mut self, index: I) -> &mut Self::Output {
         self.dirty_range.include_range(index.into());
@@ -0,0 +1,473 @@
+// Import of spectral sequence charts, as produced by Ext computations. The json looks like:
+// {
+//     "x_range" : [0, 40],      (optional, defaults to the origin and the classes plus a margin of one)
+//     "y_range" : [0, 20],      (optional)
+//     "classes" : [{ "x" : 0, "y" : 1, "name" : "h_0", "multiplicity" : 1, "last_page" : 3 }, ...],
+//     "differentials" : [{ "source" : 3, "target" : 7, "page" : 2 }, ...],
+//     "structure_lines" : [{ "source" : 0, "target" : 1, "kind" : "h0" }, ...]
+// }
+// Sources and targets are indices into the classes list. Names are optional and multiplicities default to 1.
+// Structure lines go in a layer "structure lines" below the classes and differentials in a layer "differentials" above
+// them, unless their options already put them in a layer other than the default one.
+// A class is drawn up to its last_page (forever if there is none), a differential only on its page and a structure line
+// while both its ends are.
+use std::collections::{BTreeMap, btree_map};
+use std::convert::TryFrom;
+
+use serde::Deserialize;
+use wasm_bindgen::prelude::*;
+
+#[allow(unused_imports)]
+use crate::log;
+use crate::error::ChartError;
+use crate::canvas::{Canvas, SceneContents, Edge};
+use crate::glyph::{Glyph, GlyphBuilder, GlyphComponentOptions, GlyphInstance, ALL_PAGES};
+use crate::arrow::Arrow;
+use crate::shader::{EdgeOptions, Layer};
+use crate::vector::Vec4;
+use lyon::geom::math::point;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SpectralSequenceData {
+    #[serde(default)]
+    pub x_range : Option<[f32; 2]>,
+    #[serde(default)]
+    pub y_range : Option<[f32; 2]>,
+    pub classes : Vec<ClassData>,
+    #[serde(default)]
+    pub differentials : Vec<DifferentialData>,
+    #[serde(default)]
+    pub structure_lines : Vec<StructureLineData>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ClassData {
+    pub x : i32,
+    pub y : i32,
+    #[serde(default)]
+    pub name : String,
+    #[serde(default = "one")]
+    pub multiplicity : u32,
+    #[serde(default)]
+    pub last_page : Option<u32>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct DifferentialData {
+    pub source : usize,
+    pub target : usize,
+    pub page : u32,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct StructureLineData {
+    pub source : usize,
+    pub target : usize,
+    pub kind : String,
+}
+
+fn one() -> u32 {
+    1
+}
+
+fn page_index(page : u32) -> Result<i32, ChartError> {
+    i32::try_from(page).map_err(|_| ChartError::InvalidInput(format!("Page {} is out of range.", page)))
+}
+
+// From the origin to the furthest class, plus a margin of one.
+fn padded_range<I : Iterator<Item = i32>>(values : I) -> [f32; 2] {
+    let (min, max) = values.fold((0, 0), |(min, max), v| (min.min(v), max.max(v)));
+    [min as f32 - 1.0, max as f32 + 1.0]
+}
+
+impl SpectralSequenceData {
+    pub fn from_json(json : &str) -> Result<Self, ChartError> {
+        let data : Self = serde_json::from_str(json)
+            .map_err(|e| ChartError::InvalidInput(format!("Invalid spectral sequence json: {}", e)))?;
+        data.validate()?;
+        Ok(data)
+    }
+
+    fn validate(&self) -> Result<(), ChartError> {
+        let num_classes = self.classes.len();
+        let check_class = |index : usize| {
+            if index < num_classes {
+                Ok(())
+            } else {
+                Err(ChartError::InvalidInput(format!("No class with index {}: there are {} classes.", index, num_classes)))
+            }
+        };
+        for class in &self.classes {
+            if class.multiplicity == 0 {
+                return Err(ChartError::InvalidInput(format!("Class {:?} at ({}, {}) has multiplicity 0.", class.name, class.x, class.y)));
+            }
+            class.last_page.map(page_index).transpose()?;
+        }
+        for differential in &self.differentials {
+            check_class(differential.source)?;
+            check_class(differential.target)?;
+            page_index(differential.page)?;
+        }
+        for line in &self.structure_lines {
+            check_class(line.source)?;
+            check_class(line.target)?;
+        }
+        Ok(())
+    }
+
+    /// Classes in the same bidegree are spread out horizontally, spacing apart and centered on the lattice point.
+    pub fn class_positions(&self, spacing : f32) -> Vec<(f32, f32)> {
+        let mut bidegree_counts : BTreeMap<(i32, i32), usize> = BTreeMap::new();
+        for class in &self.classes {
+            *bidegree_counts.entry((class.x, class.y)).or_insert(0) += 1;
+        }
+        let mut bidegree_indices : BTreeMap<(i32, i32), usize> = BTreeMap::new();
+        self.classes.iter().map(|class| {
+            let count = bidegree_counts[&(class.x, class.y)];
+            let index = bidegree_indices.entry((class.x, class.y)).or_insert(0);
+            let offset = (*index as f32 - (count - 1) as f32 / 2.0) * spacing;
+            *index += 1;
+            (class.x as f32 + offset, class.y as f32)
+        }).collect()
+    }
+
+    /// (first, last) page that each class is drawn on.
+    pub fn class_page_ranges(&self) -> Vec<[i32; 2]> {
+        self.classes.iter().map(|class|
+            [ALL_PAGES[0], class.last_page.map_or(ALL_PAGES[1], |page| page as i32)]
+        ).collect()
+    }
+
+    fn ranges(&self) -> ([f32; 2], [f32; 2]) {
+        (
+            self.x_range.unwrap_or_else(|| padded_range(self.classes.iter().map(|class| class.x))),
+            self.y_range.unwrap_or_else(|| padded_range(self.classes.iter().map(|class| class.y))),
+        )
+    }
+
+    /// The nodes and edges of the chart, with the layers for structure lines and differentials added to layers if they
+    /// aren't there yet.
+    fn contents(&self, style : &SpectralSequenceStyle, mut layers : Vec<Layer>) -> Result<SceneContents, ChartError> {
+        let mut multiplicity_glyphs = BTreeMap::new();
+        for class in self.classes.iter().filter(|class| class.multiplicity > 1) {
+            if let btree_map::Entry::Vacant(entry) = multiplicity_glyphs.entry(class.multiplicity) {
+                entry.insert(SpectralSequenceStyle::multiplicity_glyph(class.multiplicity)?);
+            }
+        }
+
+        let mut find_or_add_layer = |name : &str, z_order| {
+            let index = layers.iter().position(|layer| layer.name == name).unwrap_or_else(|| {
+                layers.push(Layer::new(name.to_string(), z_order));
+                layers.len() - 1
+            });
+            index as u32
+        };
+        let structure_line_layer = find_or_add_layer("structure lines", -1);
+        let differential_layer = find_or_add_layer("differentials", 1);
+        let page_ranges = self.class_page_ranges();
+        let nodes = self.classes.iter().zip(self.class_positions(style.class_spacing)).zip(&page_ranges).map(|((class, (x, y)), &page_range)| {
+            let glyph = multiplicity_glyphs.get(&class.multiplicity).unwrap_or(&style.class_glyph);
+            let mut node = GlyphInstance::new(glyph.clone(), point(x, y), style.class_scale, style.class_color, style.class_color);
+            node.page_range = page_range;
+            if !class.name.is_empty() {
+                node.label = Some(class.name.clone());
+            }
+            node
+        }).collect();
+        let structure_lines = self.structure_lines.iter().map(|line| {
+            let mut options = style.structure_line_options(&line.kind).clone();
+            options.set_page_range(ALL_PAGES[0], page_ranges[line.source][1].min(page_ranges[line.target][1]));
+            if options.layer == 0 {
+                options.set_layer(structure_line_layer);
+            }
+            Edge { start : line.source, end : line.target, options }
+        });
+        let differentials = self.differentials.iter().map(|differential| {
+            let mut options = style.differential_options(differential.page).clone();
+            let page = differential.page as i32;
+            options.set_page_range(page, page);
+            if options.layer == 0 {
+                options.set_layer(differential_layer);
+            }
+            Edge { start : differential.source, end : differential.target, options }
+        });
+        let edges = structure_lines.chain(differentials).collect();
+        Ok(SceneContents { layers, nodes, edges })
+    }
+}
+
+/// How the classes, differentials and structure lines of an imported spectral sequence are drawn.
+#[wasm_bindgen]
+pub struct SpectralSequenceStyle {
+    class_glyph : Glyph,
+    class_scale : f32,
+    class_color : Vec4,
+    class_spacing : f32,
+    differential : EdgeOptions,
+    page_differentials : BTreeMap<u32, EdgeOptions>,
+    structure_line : EdgeOptions,
+    structure_line_kinds : BTreeMap<String, EdgeOptions>,
+}
+
+#[wasm_bindgen]
+impl SpectralSequenceStyle {
+    pub fn new() -> Result<SpectralSequenceStyle, ChartError> {
+        let mut dot = GlyphComponentOptions::new();
+        dot.set_stroke_width(0.0);
+        dot.set_fill(true);
+        let class_glyph = GlyphBuilder::from_svg_path_with_options("M -3 0 A 3 3 0 1 0 3 0 A 3 3 0 1 0 -3 0 Z", &dot)?.build()?;
+        let mut differential = EdgeOptions::new();
+        differential.set_end_tip(Arrow::normal_arrow(1.0, false, false, false, false));
+        Ok(Self {
+            class_glyph,
+            class_scale : 1.0,
+            class_color : Vec4::new(0.0, 0.0, 0.0, 1.0),
+            class_spacing : 0.3,
+            differential,
+            page_differentials : BTreeMap::new(),
+            structure_line : EdgeOptions::new(),
+            structure_line_kinds : BTreeMap::new(),
+        })
+    }
+
+    /// The glyph of classes with multiplicity 1. Classes with higher multiplicity are drawn as the multiplicity in a circle.
+    pub fn set_class_glyph(&mut self, glyph : &Glyph) {
+        self.class_glyph = glyph.clone();
+    }
+
+    pub fn set_class_scale(&mut self, scale : f32) {
+        self.class_scale = scale;
+    }
+
+    pub fn set_class_color(&mut self, color : &Vec4) {
+        self.class_color = *color;
+    }
+
+    /// Horizontal distance in chart coordinates between classes in the same bidegree.
+    pub fn set_class_spacing(&mut self, spacing : f32) {
+        self.class_spacing = spacing;
+    }
+
+    /// Used for differentials on pages that have no options of their own.
+    pub fn set_differential_options(&mut self, options : &EdgeOptions) {
+        self.differential = options.clone();
+    }
+
+    pub fn set_page_differential_options(&mut self, page : u32, options : &EdgeOptions) {
+        self.page_differentials.insert(page, options.clone());
+    }
+
+    /// Used for structure lines of kinds that have no options of their own.
+    pub fn set_structure_line_options(&mut self, options : &EdgeOptions) {
+        self.structure_line = options.clone();
+    }
+
+    pub fn set_structure_line_kind_options(&mut self, kind : &str, options : &EdgeOptions) {
+        self.structure_line_kinds.insert(kind.to_string(), options.clone());
+    }
+}
+
+impl SpectralSequenceStyle {
+    fn differential_options(&self, page : u32) -> &EdgeOptions {
+        self.page_differentials.get(&page).unwrap_or(&self.differential)
+    }
+
+    fn structure_line_options(&self, kind : &str) -> &EdgeOptions {
+        self.structure_line_kinds.get(kind).unwrap_or(&self.structure_line)
+    }
+
+    fn multiplicity_glyph(multiplicity : u32) -> Result<Glyph, ChartError> {
+        let mut builder = GlyphBuilder::from_stix(&multiplicity.to_string());
+        builder.circled(5.0);
+        builder.build()
+    }
+}
+
+#[wasm_bindgen]
+impl Canvas {
+    /// Replace the contents of the canvas with a spectral sequence chart, see the top of spectral_sequence.rs for the format.
+    /// Returns the node id of each class, in the order they appear in the json. If the json is invalid, the canvas is left
+    /// as it was.
+    pub fn import_spectral_sequence(&mut self, json : &str, style : &SpectralSequenceStyle) -> Result<Vec<u32>, ChartError> {
+        let data = SpectralSequenceData::from_json(json)?;
+        let contents = data.contents(style, self.layers().to_vec())?;
+        self.set_contents(contents)?;
+
+        let ([xmin, xmax], [ymin, ymax]) = data.ranges();
+        self.set_max_xrange(xmin, xmax);
+        self.set_max_yrange(ymin, ymax);
+        self.set_current_xrange(xmin, xmax);
+        self.set_current_yrange(ymin, ymax);
+        Ok((0 .. data.classes.len() as u32).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tessellation_cache::TessellationCache;
+    use crate::shader::ChartShaders;
+    use crate::shader::mock_context::MockContext;
+    use crate::convex_hull::ANGLE_RESOLUTION;
+
+    fn invalid(json : &str) -> bool {
+        matches!(SpectralSequenceData::from_json(json), Err(ChartError::InvalidInput(_)))
+    }
+
+    #[test]
+    fn from_json() {
+        let data = SpectralSequenceData::from_json(r#"{
+            "classes" : [
+                { "x" : 0, "y" : 0, "name" : "1" },
+                { "x" : 1, "y" : 1, "multiplicity" : 2, "last_page" : 3 },
+                { "x" : -2, "y" : 5 }
+            ],
+            "differentials" : [{ "source" : 1, "target" : 2, "page" : 3 }],
+            "structure_lines" : [{ "source" : 0, "target" : 1, "kind" : "h0" }]
+        }"#).unwrap();
+        let classes : Vec<_> = data.classes.iter().map(|class| (class.x, class.y, class.name.as_str(), class.multiplicity, class.last_page)).collect();
+        assert_eq!(classes, [(0, 0, "1", 1, None), (1, 1, "", 2, Some(3)), (-2, 5, "", 1, None)]);
+        assert_eq!((data.differentials[0].source, data.differentials[0].target, data.differentials[0].page), (1, 2, 3));
+        assert_eq!((data.structure_lines[0].source, data.structure_lines[0].target, data.structure_lines[0].kind.as_str()), (0, 1, "h0"));
+        assert_eq!(data.class_page_ranges(), [ALL_PAGES, [ALL_PAGES[0], 3], ALL_PAGES]);
+        assert_eq!(data.ranges(), ([-3.0, 2.0], [-1.0, 6.0]));
+
+        let data = SpectralSequenceData::from_json(r#"{ "x_range" : [0, 40], "y_range" : [0, 20], "classes" : [] }"#).unwrap();
+        assert!(data.differentials.is_empty() && data.structure_lines.is_empty());
+        assert_eq!(data.ranges(), ([0.0, 40.0], [0.0, 20.0]));
+    }
+
+    #[test]
+    fn invalid_json() {
+        assert!(invalid("{}"));
+        assert!(invalid(r#"{ "classes" : [{ "x" : 0 }] }"#));
+        assert!(invalid(r#"{ "classes" : [{ "x" : 0, "y" : 0, "multiplicity" : -1 }] }"#));
+        assert!(invalid(r#"{ "classes" : [], "differentials" : [{ "source" : 0, "target" : 0 }] }"#));
+    }
+
+    #[test]
+    fn validate() {
+        let classes = r#"[{ "x" : 0, "y" : 0 }, { "x" : 1, "y" : 1 }]"#;
+        let valid = |rest : &str| !invalid(&format!(r#"{{ "classes" : {}, {} }}"#, classes, rest));
+        assert!(valid(r#""differentials" : [{ "source" : 1, "target" : 0, "page" : 2147483647 }]"#));
+        assert!(valid(r#""structure_lines" : [{ "source" : 0, "target" : 1, "kind" : "" }]"#));
+        // Out of range class indices.
+        assert!(!valid(r#""differentials" : [{ "source" : 2, "target" : 0, "page" : 2 }]"#));
+        assert!(!valid(r#""differentials" : [{ "source" : 0, "target" : 2, "page" : 2 }]"#));
+        assert!(!valid(r#""structure_lines" : [{ "source" : 2, "target" : 1, "kind" : "h0" }]"#));
+        assert!(!valid(r#""structure_lines" : [{ "source" : 0, "target" : 18446744073709551615, "kind" : "h0" }]"#));
+        // Pages that don't fit the page ranges of the shaders.
+        assert!(!valid(r#""differentials" : [{ "source" : 1, "target" : 0, "page" : 2147483648 }]"#));
+        assert!(invalid(r#"{ "classes" : [{ "x" : 0, "y" : 0, "last_page" : 4294967295 }] }"#));
+
+        assert!(invalid(r#"{ "classes" : [{ "x" : 0, "y" : 0 }, { "x" : 0, "y" : 1, "multiplicity" : 0 }] }"#));
+        assert!(!invalid(r#"{ "classes" : [{ "x" : 0, "y" : 0, "multiplicity" : 4294967295 }] }"#));
+    }
+
+    #[test]
+    fn class_positions() {
+        let data = SpectralSequenceData::from_json(r#"{ "classes" : [
+            { "x" : 3, "y" : 1 },
+            { "x" : 0, "y" : 0 },
+            { "x" : 3, "y" : 1 },
+            { "x" : 1, "y" : 3 },
+            { "x" : 3, "y" : 1 },
+            { "x" : 1, "y" : 3 }
+        ] }"#).unwrap();
+        // Spread out in the order of the json, centered on the lattice point.
+        assert_eq!(data.class_positions(0.5), [(2.5, 1.0), (0.0, 0.0), (3.0, 1.0), (0.75, 3.0), (3.5, 1.0), (1.25, 3.0)]);
+        assert_eq!(data.class_positions(0.0), [(3.0, 1.0), (0.0, 0.0), (3.0, 1.0), (1.0, 3.0), (3.0, 1.0), (1.0, 3.0)]);
+        assert!(SpectralSequenceData::from_json(r#"{ "classes" : [] }"#).unwrap().class_positions(0.3).is_empty());
+    }
+
+    // SpectralSequenceStyle::new needs the pixel density of the window.
+    fn style(class_glyph : Glyph) -> SpectralSequenceStyle {
+        let mut differential = EdgeOptions::new();
+        differential.set_end_tip(Arrow::test_arrow());
+        SpectralSequenceStyle {
+            class_glyph,
+            class_scale : 1.0,
+            class_color : Vec4::new(0.0, 0.0, 0.0, 1.0),
+            class_spacing : 0.3,
+            differential,
+            page_differentials : BTreeMap::new(),
+            structure_line : EdgeOptions::new(),
+            structure_line_kinds : BTreeMap::new(),
+        }
+    }
+
+    fn square() -> Glyph {
+        GlyphBuilder::from_svg_path("M -3 -3 L 3 -3 L 3 3 L -3 3 Z", 1.0, true).unwrap().build().unwrap()
+    }
+
+    #[test]
+    fn contents() {
+        let data = SpectralSequenceData::from_json(r#"{
+            "classes" : [{ "x" : 0, "y" : 0, "name" : "1", "last_page" : 2 }, { "x" : 0, "y" : 1 }, { "x" : 1, "y" : 0 }],
+            "differentials" : [{ "source" : 2, "target" : 1, "page" : 2 }],
+            "structure_lines" : [{ "source" : 0, "target" : 1, "kind" : "h0" }]
+        }"#).unwrap();
+        let mut style = style(square());
+        let mut h0 = EdgeOptions::new();
+        h0.set_layer(3);
+        style.set_structure_line_kind_options("h0", &h0);
+        let existing = vec![Layer::new("default".to_string(), 0), Layer::new("differentials".to_string(), 5)];
+        let contents = data.contents(&style, existing).unwrap();
+
+        let layers : Vec<_> = contents.layers.iter().map(|layer| (layer.name.as_str(), layer.z_order)).collect();
+        assert_eq!(layers, [("default", 0), ("differentials", 5), ("structure lines", -1)]);
+        let nodes : Vec<_> = contents.nodes.iter().map(|node| (node.center, node.page_range, node.label.as_deref())).collect();
+        assert_eq!(nodes, [
+            (point(0.0, 0.0), [ALL_PAGES[0], 2], Some("1")),
+            (point(0.0, 1.0), ALL_PAGES, None),
+            (point(1.0, 0.0), ALL_PAGES, None),
+        ]);
+        let edges : Vec<_> = contents.edges.iter().map(|edge| (edge.start, edge.end, edge.options.page_range, edge.options.layer)).collect();
+        assert_eq!(edges, [(0, 1, [ALL_PAGES[0], 2], 3), (2, 1, [2, 2], 1)]);
+        // The structure line is in a layer that doesn't exist, so nothing would be imported.
+        assert!(matches!(contents.check(&TessellationCache::new()), Err(ChartError::InvalidInput(_))));
+
+        style.set_structure_line_kind_options("h0", &EdgeOptions::new());
+        let contents = data.contents(&style, vec![Layer::new("default".to_string(), 0)]).unwrap();
+        assert_eq!((contents.edges[0].options.layer, contents.edges[1].options.layer), (1, 2));
+        contents.check(&TessellationCache::new()).unwrap();
+    }
+
+    // What Canvas::set_contents does to the shaders.
+    fn load(shaders : &mut ChartShaders<MockContext>, data : &SpectralSequenceData, style : &SpectralSequenceStyle) {
+        let contents = data.contents(style, shaders.layers().to_vec()).unwrap();
+        contents.check(shaders.tessellation_cache()).unwrap();
+        shaders.set_layers(contents.layers).unwrap();
+        shaders.clear_glyphs();
+        shaders.clear_edges();
+        for node in &contents.nodes {
+            shaders.add_glyph_instance(node.clone()).unwrap();
+        }
+        for edge in &contents.edges {
+            shaders.add_edge(contents.nodes[edge.start].clone(), contents.nodes[edge.end].clone(), &edge.options).unwrap();
+        }
+        shaders.prepare().unwrap();
+    }
+
+    #[test]
+    fn load_twice() {
+        let first = SpectralSequenceData::from_json(r#"{
+            "classes" : [{ "x" : 0, "y" : 0 }, { "x" : 0, "y" : 1 }],
+            "structure_lines" : [{ "source" : 0, "target" : 1, "kind" : "h0" }]
+        }"#).unwrap();
+        let second = SpectralSequenceData::from_json(r#"{
+            "classes" : [{ "x" : 1, "y" : 0 }, { "x" : 0, "y" : 1 }],
+            "differentials" : [{ "source" : 0, "target" : 1, "page" : 2 }]
+        }"#).unwrap();
+        let triangle = GlyphBuilder::from_svg_path("M -4 -2 L 4 -2 L 0 5 Z", 1.0, true).unwrap().build().unwrap();
+        let mut shaders = ChartShaders::new(MockContext::new(), TessellationCache::new()).unwrap();
+        load(&mut shaders, &first, &style(square()));
+        assert_eq!(shaders.glyph_boundaries().len(), 2 * ANGLE_RESOLUTION);
+        load(&mut shaders, &second, &style(triangle.clone()));
+        // The square is gone, the differential is clipped against the triangle as glyph 0.
+        let boundaries = shaders.glyph_boundaries();
+        assert_eq!(boundaries.len(), 2 * ANGLE_RESOLUTION);
+        assert_eq!(&boundaries[.. ANGLE_RESOLUTION], &triangle.boundary()[..]);
+        assert_eq!(&boundaries[ANGLE_RESOLUTION ..], &triangle.star_outline()[..]);
+    }
+}
@@ -0,0 +1,371 @@
+// Parser for the path data mini-language used by the "d" attribute of svg <path> elements.
+// See: https://www.w3.org/TR/SVG/paths.html#PathData
+#[allow(unused_imports)]
+use crate::log;
+
+use lyon::geom::math::{Point, point, Vector, vector, Angle};
+use lyon::geom::{ArcFlags, SvgArc};
+use lyon::path::{Path, PathEvent};
+use lyon::path::builder::{Build, FlatPathBuilder, PathBuilder, SvgBuilder};
+
+
+struct Tokenizer<'a> {
+    source : &'a [u8],
+    position : usize,
+}
+
+impl<'a> Tokenizer<'a> {
+    fn new(source : &'a str) -> Self {
+        Self { source : source.as_bytes(), position : 0 }
+    }
+
+    fn error(&self, message : &str) -> String {
+        format!("Invalid svg path data at position {}: {}", self.position, message)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.source.get(self.position).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            if !c.is_ascii_whitespace() {
+                break;
+            }
+            self.position += 1;
+        }
+    }
+
+    // Whitespace and at most one comma may separate arguments.
+    fn skip_separator(&mut self) {
+        self.skip_whitespace();
+        if self.peek() == Some(b',') {
+            self.position += 1;
+            self.skip_whitespace();
+        }
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.skip_whitespace();
+        self.peek().is_none()
+    }
+
+    // If the next token is a command letter, consume and return it.
+    fn command(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        let c = self.peek()?;
+        if c.is_ascii_alphabetic() && c != b'e' && c != b'E' {
+            self.position += 1;
+            Some(c)
+        } else {
+            None
+        }
+    }
+
+    // Does another argument follow? (Used to handle implicitly repeated commands.)
+    fn has_number(&mut self) -> bool {
+        self.skip_separator();
+        matches!(self.peek(), Some(b'0'..=b'9') | Some(b'-') | Some(b'+') | Some(b'.'))
+    }
+
+    fn number(&mut self) -> Result<f32, String> {
+        self.skip_separator();
+        let start = self.position;
+        if let Some(b'-') | Some(b'+') = self.peek() {
+            self.position += 1;
+        }
+        let mut num_digits = self.digits();
+        if self.peek() == Some(b'.') {
+            self.position += 1;
+            num_digits += self.digits();
+        }
+        if num_digits == 0 {
+            self.position = start;
+            return Err(self.error("expected a number"));
+        }
+        if let Some(b'e') | Some(b'E') = self.peek() {
+            let exponent_start = self.position;
+            self.position += 1;
+            if let Some(b'-') | Some(b'+') = self.peek() {
+                self.position += 1;
+            }
+            if self.digits() == 0 {
+                // Not actually an exponent, leave it for the next token.
+                self.position = exponent_start;
+            }
+        }
+        let text = std::str::from_utf8(&self.source[start..self.position]).unwrap();
+        text.parse().map_err(|_| self.error("expected a number"))
+    }
+
+    fn digits(&mut self) -> usize {
+        let start = self.position;
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.position += 1;
+        }
+        self.position - start
+    }
+
+    // Arc flags are a single character and need not be separated from what follows them.
+    fn flag(&mut self) -> Result<bool, String> {
+        self.skip_separator();
+        let result = match self.peek() {
+            Some(b'0') => false,
+            Some(b'1') => true,
+            _ => return Err(self.error("expected an arc flag (0 or 1)")),
+        };
+        self.position += 1;
+        Ok(result)
+    }
+}
+
+
+pub fn parse_svg_path(d : &str) -> Result<Path, String> {
+    let mut tokens = Tokenizer::new(d);
+    let mut builder = Path::builder().with_svg();
+    let mut command = None;
+    let mut first_command = true;
+    let mut last_cubic_ctrl : Option<Point> = None;
+    let mut last_quadratic_ctrl : Option<Point> = None;
+    while !tokens.at_end() {
+        if let Some(c) = tokens.command() {
+            command = Some(c);
+        } else if !tokens.has_number() {
+            return Err(tokens.error("expected a command letter"));
+        }
+        let c = match command {
+            Some(c) => c,
+            None if first_command => return Err(tokens.error("path data must begin with a moveto command")),
+            None => return Err(tokens.error("expected a command letter")),
+        };
+        if first_command && c != b'M' && c != b'm' {
+            return Err(tokens.error("path data must begin with a moveto command"));
+        }
+        first_command = false;
+        // The builder remembers control points for smooth curves, but it doesn't know about our arcs so we keep track
+        // of them ourselves. Every command other than the curves resets them.
+        let previous_cubic_ctrl = last_cubic_ctrl.take();
+        let previous_quadratic_ctrl = last_quadratic_ctrl.take();
+        match c {
+            b'M' => {
+                builder.move_to(point(tokens.number()?, tokens.number()?));
+                // Subsequent pairs are treated as implicit lineto commands.
+                command = Some(b'L');
+            }
+            b'm' => {
+                builder.relative_move_to(vector(tokens.number()?, tokens.number()?));
+                command = Some(b'l');
+            }
+            b'L' => builder.line_to(point(tokens.number()?, tokens.number()?)),
+            b'l' => builder.relative_line_to(vector(tokens.number()?, tokens.number()?)),
+            b'H' => builder.horizontal_line_to(tokens.number()?),
+            b'h' => builder.relative_horizontal_line_to(tokens.number()?),
+            b'V' => builder.vertical_line_to(tokens.number()?),
+            b'v' => builder.relative_vertical_line_to(tokens.number()?),
+            b'C' | b'c' => {
+                let offset = relative_offset(c, builder.current_position());
+                let ctrl1 = point(tokens.number()?, tokens.number()?) + offset;
+                let ctrl2 = point(tokens.number()?, tokens.number()?) + offset;
+                let to = point(tokens.number()?, tokens.number()?) + offset;
+                builder.cubic_bezier_to(ctrl1, ctrl2, to);
+                last_cubic_ctrl = Some(ctrl2);
+            }
+            b'S' | b's' => {
+                let from = builder.current_position();
+                let offset = relative_offset(c, from);
+                // The first control point is the reflection of the previous one if the previous command was C or S.
+                let ctrl1 = previous_cubic_ctrl.map_or(from, |ctrl| from + (from - ctrl));
+                let ctrl2 = point(tokens.number()?, tokens.number()?) + offset;
+                let to = point(tokens.number()?, tokens.number()?) + offset;
+                builder.cubic_bezier_to(ctrl1, ctrl2, to);
+                last_cubic_ctrl = Some(ctrl2);
+            }
+            b'Q' | b'q' => {
+                let offset = relative_offset(c, builder.current_position());
+                let ctrl = point(tokens.number()?, tokens.number()?) + offset;
+                let to = point(tokens.number()?, tokens.number()?) + offset;
+                builder.quadratic_bezier_to(ctrl, to);
+                last_quadratic_ctrl = Some(ctrl);
+            }
+            b'T' | b't' => {
+                let from = builder.current_position();
+                let offset = relative_offset(c, from);
+                // Likewise reflect the control point of a previous Q or T.
+                let ctrl = previous_quadratic_ctrl.map_or(from, |ctrl| from + (from - ctrl));
+                let to = point(tokens.number()?, tokens.number()?) + offset;
+                builder.quadratic_bezier_to(ctrl, to);
+                last_quadratic_ctrl = Some(ctrl);
+            }
+            b'A' | b'a' => {
+                let radii = vector(tokens.number()?.abs(), tokens.number()?.abs());
+                let x_rotation = Angle::degrees(tokens.number()?);
+                let flags = ArcFlags { large_arc : tokens.flag()?, sweep : tokens.flag()? };
+                let x = tokens.number()?;
+                let y = tokens.number()?;
+                let from = builder.current_position();
+                let to = if c == b'a' { from + vector(x, y) } else { point(x, y) };
+                // A zero radius means that the arc is treated as a straight line.
+                if radii.x == 0.0 || radii.y == 0.0 {
+                    builder.line_to(to);
+                } else if from != to {
+                    // SvgPathBuilder::arc_to inserts a spurious line segment at the start of each arc, so we
+                    // approximate the arc with quadratic curves ourselves.
+                    let arc = SvgArc { from, to, radii, x_rotation, flags }.to_arc();
+                    let mut curves = Vec::new();
+                    arc.for_each_quadratic_bezier(&mut |curve| curves.push(*curve));
+                    // End exactly where the arc was asked to, rather than wherever rounding errors put it.
+                    if let Some(last) = curves.last_mut() {
+                        last.to = to;
+                    }
+                    for curve in curves {
+                        builder.quadratic_bezier_to(curve.ctrl, curve.to);
+                    }
+                }
+            }
+            b'Z' | b'z' => {
+                builder.close();
+                // Closepath takes no arguments, so a following number is an error.
+                command = None;
+            }
+            _ => {
+                return Err(tokens.error(&format!("unknown command '{}'", c as char)));
+            }
+        }
+    }
+    Ok(builder.build())
+}
+
+// Relative commands are lower case.
+fn relative_offset(command : u8, current_position : Point) -> Vector {
+    if command.is_ascii_lowercase() {
+        current_position.to_vector()
+    } else {
+        Vector::zero()
+    }
+}
+
+pub fn parse_svg_path_events(d : &str) -> Result<Vec<PathEvent>, String> {
+    Ok(parse_svg_path(d)?.iter().collect())
+}
+
+/// Write path events as svg path data. Numbers are written with enough precision that parse_svg_path gives back
+/// exactly the same path.
+pub fn write_svg_path<I : IntoIterator<Item = PathEvent>>(path : I) -> String {
+    let mut commands = Vec::new();
+    for event in path {
+        match event {
+            PathEvent::Begin { at } => commands.push(format!("M {} {}", at.x, at.y)),
+            PathEvent::Line { to, .. } => commands.push(format!("L {} {}", to.x, to.y)),
+            PathEvent::Quadratic { ctrl, to, .. } => commands.push(format!("Q {} {} {} {}", ctrl.x, ctrl.y, to.x, to.y)),
+            PathEvent::Cubic { ctrl1, ctrl2, to, .. } => commands.push(format!(
+                "C {} {} {} {} {} {}", ctrl1.x, ctrl1.y, ctrl2.x, ctrl2.y, to.x, to.y
+            )),
+            PathEvent::End { close : true, .. } => commands.push("Z".to_string()),
+            PathEvent::End { close : false, .. } => {}
+        }
+    }
+    commands.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events(d : &str) -> Vec<PathEvent> {
+        parse_svg_path_events(d).unwrap()
+    }
+
+    fn last_curve(d : &str) -> PathEvent {
+        *events(d).iter().rev().find(|event| !matches!(event, PathEvent::End { .. })).unwrap()
+    }
+
+    #[test]
+    fn relative_commands() {
+        assert_eq!(events("m 1 1 l 2 0 h 1 v 2 z"), events("M 1 1 L 3 1 L 4 1 L 4 3 Z"));
+        assert_eq!(events("M 1 1 q 1 1 2 0 c 1 1 2 1 3 0"), events("M 1 1 Q 2 2 3 1 C 4 2 5 2 6 1"));
+    }
+
+    #[test]
+    fn implicit_repeats() {
+        // Pairs after a moveto are linetos, relative if the moveto was.
+        assert_eq!(events("M 0 0 1 1 2 2"), events("M 0 0 L 1 1 L 2 2"));
+        assert_eq!(events("m 1 1 2 2 1 0"), events("M 1 1 L 3 3 L 4 3"));
+        assert_eq!(events("M 0 0 L 1 1 2 0 Q 3 1 4 0 5 -1 6 0"), events("M 0 0 L 1 1 L 2 0 Q 3 1 4 0 Q 5 -1 6 0"));
+    }
+
+    #[test]
+    fn number_syntax() {
+        assert_eq!(events("M.5.5L-1e1,2E-1"), events("M 0.5 0.5 L -10 0.2"));
+    }
+
+    #[test]
+    fn arc_flags() {
+        // Flags need no separator from each other or from the following number.
+        assert_eq!(events("M 0 0 A 5 5 0 1 1 10 0"), events("M0 0A5 5 0 1110 0"));
+        assert_eq!(events("M 0 0 a 5 5 0 0 1 10 0"), events("M 0 0 A 5 5 0 0 1 10 0"));
+        assert!(parse_svg_path("M 0 0 A 5 5 0 2 1 10 0").is_err());
+        // With y pointing down, sweep goes clockwise over the top of the chord.
+        let lowest = |d : &str| events(d).iter().filter_map(|event| match event {
+            PathEvent::Quadratic { ctrl, .. } => Some(ctrl.y),
+            _ => None,
+        }).fold(f32::NAN, f32::min);
+        assert!(lowest("M 0 0 A 5 5 0 0 1 10 0") < -4.0);
+        assert!(lowest("M 0 0 A 5 5 0 0 0 10 0") > -0.1);
+        // A zero radius is a line.
+        assert_eq!(events("M 0 0 A 0 5 0 0 1 10 0"), events("M 0 0 L 10 0"));
+    }
+
+    #[test]
+    fn smooth_curves_reflect_previous_control_point() {
+        match last_curve("M 0 0 Q 5 5 10 0 T 20 0") {
+            PathEvent::Quadratic { ctrl, .. } => assert_eq!(ctrl, point(15.0, -5.0)),
+            event => panic!("unexpected {:?}", event),
+        }
+        match last_curve("M 0 0 C 0 5 10 5 10 0 s 10 -5 10 0") {
+            PathEvent::Cubic { ctrl1, ctrl2, to, .. } => {
+                assert_eq!(ctrl1, point(10.0, -5.0));
+                assert_eq!(ctrl2, point(20.0, -5.0));
+                assert_eq!(to, point(20.0, 0.0));
+            }
+            event => panic!("unexpected {:?}", event),
+        }
+        // T after a C and S after a Q have nothing to reflect.
+        match last_curve("M 0 0 C 0 5 10 5 10 0 T 20 0") {
+            PathEvent::Quadratic { ctrl, .. } => assert_eq!(ctrl, point(10.0, 0.0)),
+            event => panic!("unexpected {:?}", event),
+        }
+    }
+
+    #[test]
+    fn smooth_curves_after_arc() {
+        // The arc is flattened to quadratic curves, which must not count as a previous Q.
+        match last_curve("M 0 0 A 5 5 0 0 1 10 0 T 20 0") {
+            PathEvent::Quadratic { ctrl, .. } => assert_eq!(ctrl, point(10.0, 0.0)),
+            event => panic!("unexpected {:?}", event),
+        }
+        match last_curve("M 0 0 A 5 5 0 0 1 10 0 S 15 5 20 0") {
+            PathEvent::Cubic { ctrl1, .. } => assert_eq!(ctrl1, point(10.0, 0.0)),
+            event => panic!("unexpected {:?}", event),
+        }
+        match last_curve("M 0 0 Q 5 5 10 0 L 12 0 T 20 0") {
+            PathEvent::Quadratic { ctrl, .. } => assert_eq!(ctrl, point(12.0, 0.0)),
+            event => panic!("unexpected {:?}", event),
+        }
+    }
+
+    #[test]
+    fn errors() {
+        assert!(parse_svg_path("L 0 0").is_err());
+        assert!(parse_svg_path("M 0").is_err());
+        assert!(parse_svg_path("M 0 0 Z 1 1").is_err());
+        assert!(parse_svg_path("M 0 0 X 1 1").is_err());
+        assert!(parse_svg_path("").unwrap().iter().next().is_none());
+    }
+
+    #[test]
+    fn write_round_trip() {
+        let d = "M 0.1 0.2 C 1 2 3 4 5 6 Q 7 8 9 10 L 11.5 -12 Z";
+        let written = write_svg_path(events(d));
+        assert_eq!(events(&written), events(d));
+    }
+}
@@ -0,0 +1,151 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use uuid::Uuid;
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use lyon::geom::math::Point;
+use lyon::tessellation::VertexBuffers;
+
+#[allow(unused_imports)]
+use crate::log;
+use crate::error::ChartError;
+use crate::glyph::{Glyph, GlyphUuid, GlyphVertex};
+use crate::arrow::Arrow;
+
+/// Glyphs are tessellated at this size, the shaders scale them down again.
+pub const GLYPH_TESSELLATION_SCALE : f32 = 100.0;
+
+pub struct TessellatedGlyph {
+    pub fill : VertexBuffers<GlyphVertex, u16>,
+    pub stroke : VertexBuffers<GlyphVertex, u16>,
+}
+
+#[derive(Default)]
+struct CacheData {
+    glyphs : BTreeMap<GlyphUuid, Rc<TessellatedGlyph>>,
+    arrows : BTreeMap<Uuid, Rc<VertexBuffers<Point, u16>>>,
+}
+
+/// Tessellated glyphs and arrow tips, keyed by glyph / arrow identity. Clones share the same cache, so canvases
+/// constructed with the same cache only tessellate each glyph and arrow once between them.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Default)]
+pub struct TessellationCache {
+    inner : Rc<RefCell<CacheData>>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl TessellationCache {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Drop all cached tessellations. Canvases keep what they have already uploaded.
+    pub fn clear(&self) {
+        let mut data = self.inner.borrow_mut();
+        data.glyphs.clear();
+        data.arrows.clear();
+    }
+}
+
+impl TessellationCache {
+    pub fn glyph(&self, glyph : &Glyph) -> Result<Rc<TessellatedGlyph>, ChartError> {
+        if let Some(tessellation) = self.inner.borrow().glyphs.get(&glyph.uuid) {
+            return Ok(tessellation.clone());
+        }
+        let mut fill = VertexBuffers::new();
+        glyph.tessellate_fill(&mut fill, GLYPH_TESSELLATION_SCALE)?;
+        let mut stroke = VertexBuffers::new();
+        glyph.tessellate_stroke(&mut stroke, GLYPH_TESSELLATION_SCALE)?;
+        let tessellation = Rc::new(TessellatedGlyph { fill, stroke });
+        self.inner.borrow_mut().glyphs.insert(glyph.uuid, tessellation.clone());
+        Ok(tessellation)
+    }
+
+    pub fn arrow(&self, arrow : &Arrow) -> Result<Rc<VertexBuffers<Point, u16>>, ChartError> {
+        if let Some(tessellation) = self.inner.borrow().arrows.get(&arrow.uuid) {
+            return Ok(tessellation.clone());
+        }
+        let mut buffers = VertexBuffers::new();
+        arrow.tesselate_into_buffers(&mut buffers)?;
+        let tessellation = Rc::new(buffers);
+        self.inner.borrow_mut().arrows.insert(arrow.uuid, tessellation.clone());
+        Ok(tessellation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::glyph::GlyphBuilder;
+
+    fn triangle() -> Glyph {
+        GlyphBuilder::from_svg_path("M 0 0 L 10 0 L 5 10 Z", 1.0, true).unwrap().build().unwrap()
+    }
+
+    #[test]
+    fn clear() {
+        let cache = TessellationCache::new();
+        let (glyph, arrow) = (triangle(), Arrow::test_arrow());
+        let (first_glyph, first_arrow) = (cache.glyph(&glyph).unwrap(), cache.arrow(&arrow).unwrap());
+        assert!(Rc::ptr_eq(&first_glyph, &cache.clone().glyph(&glyph).unwrap()));
+        assert!(Rc::ptr_eq(&first_arrow, &cache.clone().arrow(&arrow).unwrap()));
+        // A copy of the glyph is the same glyph, a new one with the same path isn't.
+        assert!(Rc::ptr_eq(&first_glyph, &cache.glyph(&glyph.clone()).unwrap()));
+        assert!(!Rc::ptr_eq(&first_glyph, &cache.glyph(&triangle()).unwrap()));
+
+        cache.clear();
+        let glyph_again = cache.glyph(&glyph).unwrap();
+        assert!(!Rc::ptr_eq(&first_glyph, &glyph_again));
+        assert_eq!(glyph_again.fill.vertices.len(), first_glyph.fill.vertices.len());
+        assert!(!Rc::ptr_eq(&first_arrow, &cache.arrow(&arrow).unwrap()));
+    }
+
+    #[cfg(feature = "webgl")]
+    #[test]
+    fn shared_between_shaders() {
+        use lyon::geom::math::point;
+        use crate::glyph::GlyphInstance;
+        use crate::vector::Vec4;
+        use crate::shader::{ChartShaders, EdgeOptions};
+        use crate::shader::mock_context::MockContext;
+
+        let cache = TessellationCache::new();
+        let glyph = triangle();
+        let mut options = EdgeOptions::new();
+        options.set_end_tip(Arrow::test_arrow());
+        let black = Vec4::new(0.0, 0.0, 0.0, 1.0);
+        let instance = |x : f32| GlyphInstance::new(glyph.clone(), point(x, 0.0), 1.0, black, black);
+        let add = |shaders : &mut ChartShaders<MockContext>| {
+            shaders.add_glyph_instance(instance(0.0)).unwrap();
+            shaders.add_glyph_instance(instance(1.0)).unwrap();
+            shaders.add_edge(instance(0.0), instance(1.0), &options).unwrap();
+            shaders.prepare().unwrap();
+        };
+
+        let first = MockContext::new();
+        let mut first_shaders = ChartShaders::new(first.clone(), cache.clone()).unwrap();
+        add(&mut first_shaders);
+        let (glyph_tessellation, arrow_tessellation) = (cache.glyph(&glyph).unwrap(), cache.arrow(options.end_tip.as_ref().unwrap()).unwrap());
+        let second = MockContext::new();
+        let mut second_shaders = ChartShaders::new(second.clone(), cache.clone()).unwrap();
+        add(&mut second_shaders);
+        // The second canvas used the tessellations of the first one instead of making its own.
+        assert!(Rc::ptr_eq(&glyph_tessellation, &cache.glyph(&glyph).unwrap()));
+        assert!(Rc::ptr_eq(&arrow_tessellation, &cache.arrow(options.end_tip.as_ref().unwrap()).unwrap()));
+        assert!(first.contents() == second.contents());
+
+        // After clear the next canvas tessellates again, into the same data.
+        cache.clear();
+        let third = MockContext::new();
+        let mut third_shaders = ChartShaders::new(third.clone(), cache.clone()).unwrap();
+        add(&mut third_shaders);
+        assert!(!Rc::ptr_eq(&glyph_tessellation, &cache.glyph(&glyph).unwrap()));
+        assert!(!Rc::ptr_eq(&arrow_tessellation, &cache.arrow(options.end_tip.as_ref().unwrap()).unwrap()));
+        assert!(first.contents() == third.contents());
+    }
+}
@@ -1,14 +1,16 @@
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 
 use lyon::geom::math::{Point, Vector};
 
 use derive_more::{From, Add, Sub, Mul, Div, AddAssign, SubAssign, MulAssign, DivAssign, Sum};
+use serde::{Serialize, Deserialize};
 
 use std::convert::From;
 
 
 
-#[wasm_bindgen(inspectable)]
+#[cfg_attr(feature = "wasm", wasm_bindgen(inspectable))]
 #[derive(Copy, Clone, Debug)]
 #[repr(C)]
 pub struct JsPoint {
@@ -72,9 +74,9 @@ impl From<&JsPoint> for Vector {
     }
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 impl JsPoint {
-    #[wasm_bindgen(constructor)]
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
     pub fn new(x : f32, y : f32) -> Self {
         Self { x, y }
     }
@@ -82,7 +84,7 @@ impl JsPoint {
 
 
 
-#[wasm_bindgen(inspectable)]
+#[cfg_attr(feature = "wasm", wasm_bindgen(inspectable))]
 #[derive(Copy, Clone, Debug, From, Add, Sub, Mul, Div, AddAssign, SubAssign, MulAssign, DivAssign, Sum)]
 #[repr(C)]
 pub struct Vec3 {
@@ -91,9 +93,9 @@ pub struct Vec3 {
     pub z : f32,
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 impl Vec3 {
-    #[wasm_bindgen(constructor)]
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
     pub fn new_js(x : f32, y : f32, z : f32) -> Self {
         Self::new(x, y, z)
     }
@@ -107,8 +109,8 @@ impl Vec3 {
     }
 }
 
-#[wasm_bindgen(inspectable)]
-#[derive(Copy, Clone, Debug, From, Add, Sub, Mul, Div, AddAssign, SubAssign, MulAssign, DivAssign, Sum)]
+#[cfg_attr(feature = "wasm", wasm_bindgen(inspectable))]
+#[derive(Copy, Clone, Debug, PartialEq, From, Add, Sub, Mul, Div, AddAssign, SubAssign, MulAssign, DivAssign, Sum, Serialize, Deserialize)]
 #[repr(C)]
 pub struct Vec4 {
     pub x : f32,
@@ -117,9 +119,9 @@ pub struct Vec4 {
     pub w : f32
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 impl Vec4 {
-    #[wasm_bindgen(constructor)]
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
     pub fn new_js(x : f32, y : f32, z : f32, w : f32) -> Self {
         Self::new(x, y, z, w)
     }
@@ -202,6 +204,10 @@ impl Vec4 {
     pub fn normalize(self) -> Self {
         self * (1.0/self.magnitude())
     }
+
+    pub fn lerp(self, other : Self, t : f32) -> Self {
+        self * (1.0 - t) + other * t
+    }
 }
 
 // We need to pass WebGl a js_sys::Float32Array as input data for various purposes. These are not modified by WebGl.
@@ -210,7 +216,8 @@ impl Vec4 {
 // but the raw pointer api takes a *mut f32. The mutable option seems to make more sense to me. However, we are not planning to ever use the Float32Array view
 // to modify the slice, and we want to allow our functions to take an immutable borrow &[f32].
 // So we use std::mem::transmute!
-pub trait MutPtrF32 {
+#[allow(dead_code)]
+pub(crate) trait MutPtrF32 {
     unsafe fn mut_ptr_f32(&self) -> *mut f32;
 
     fn length(&self) -> usize;
@@ -257,7 +264,8 @@ impl MutPtrF32 for &Vec<Vector> {
     }
 }
 
-pub trait SliceVec2T : MutPtrF32 + IntoIterator {
+#[allow(dead_code)]
+pub(crate) trait SliceVec2T : MutPtrF32 + IntoIterator {
     // type Item;
 
     fn len(&self) -> usize;
@@ -1,11 +1,12 @@
 use web_sys::{HtmlCanvasElement, WebGl2RenderingContext};
-use wasm_bindgen::{JsValue, JsCast};
+use wasm_bindgen::JsCast;
 use std::ops::Deref;
 
 
 #[allow(unused_imports)]
 use crate::log;
 use crate::coordinate_system::BufferDimensions;
+use crate::error::ChartError;
 
 
 
@@ -26,11 +27,14 @@ impl WebGlWrapper {
         Self { inner }
     }
 
-    pub fn canvas(&self) -> Result<HtmlCanvasElement, JsValue> {
-        Ok(self.inner.canvas().unwrap().dyn_into()?)
+    pub fn canvas(&self) -> Result<HtmlCanvasElement, ChartError> {
+        // The context belongs to an OffscreenCanvas if it was created in a worker.
+        self.inner.canvas()
+            .and_then(|canvas| canvas.dyn_into().ok())
+            .ok_or_else(|| ChartError::Js("WebGL context does not belong to a canvas element.".to_string()))
     }
 
-    pub fn dimensions(&self) -> Result<BufferDimensions, JsValue> {
+    pub fn dimensions(&self) -> Result<BufferDimensions, ChartError> {
         let canvas = self.canvas()?;
         let width = canvas.client_width();
         let height = canvas.client_height();
@@ -39,7 +43,8 @@ impl WebGlWrapper {
     }
 
     pub fn pixel_density() -> f64 {
-        web_sys::window().unwrap().device_pixel_ratio()
+        // There is no window in a worker, assume one pixel per css pixel there.
+        web_sys::window().map_or(1.0, |window| window.device_pixel_ratio())
     }
 
     pub fn point_to_pixels(points : f32) -> f32 {